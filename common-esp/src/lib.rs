@@ -2,27 +2,64 @@
 
 extern crate alloc;
 
+mod peers;
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use common_messages::{
+    EspNowChannel, Fragmenter, PeerSeqFilter, Reassembler, Retry, Urgent, is_unknown_variant,
+    send_until_shutdown,
+};
 use defmt::{Format, debug, error, info};
-use embassy_futures::join::join3;
+use embassy_futures::join::join;
+use embassy_futures::select::select;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::{Receiver, Sender};
-use embassy_time::{Duration, Ticker};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer};
 use esp_hal::peripherals::WIFI;
 use esp_radio::esp_now::{
-    BROADCAST_ADDRESS, EspNowManager, EspNowReceiver, EspNowSender, EspNowWifiInterface, PeerInfo,
+    BROADCAST_ADDRESS, EspNowError, EspNowManager, EspNowReceiver, EspNowSender,
+    EspNowWifiInterface, PeerInfo,
 };
 use esp_radio::wifi::WifiMode;
 use wincode::{SchemaReadOwned, SchemaWrite};
 
+pub use common_messages::{Peer, PeerTable};
+pub use peers::PeerPollBackoff;
+
+/// Maximum number of tracked peers
+pub const MAX_PEERS: usize = 8;
+/// Peers not heard from for this long are dropped from the peer table
+pub const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+static PEERS: Mutex<CriticalSectionRawMutex, RefCell<PeerTable<MAX_PEERS>>> =
+    Mutex::new(RefCell::new(PeerTable::new()));
+
+/// Gives access to the recently heard esp-now peers
+pub fn with_peers<R>(f: impl FnOnce(&PeerTable<MAX_PEERS>) -> R) -> R {
+    PEERS.lock(|peers| f(&peers.borrow()))
+}
+
+/// Runs esp-now until `shutdown` is signaled. A message that is being sent is completed
+/// first, so the caller can re-initialize the radio without leaving partial messages behind.
+///
+/// Every outgoing packet is retried as configured by `send_retry`, see [`SEND_RETRY`] and
+/// [`consecutive_send_failures`].
 pub async fn communicate<
     MsgOutgoing: SchemaWrite<Src = MsgOutgoing> + Format,
-    MsgIncoming: SchemaReadOwned<Dst = MsgIncoming> + Format,
+    MsgIncoming: SchemaReadOwned<Dst = MsgIncoming> + Format + Urgent,
     const LEN_OUTGOING: usize,
     const LEN_INCOMING: usize,
 >(
     wifi: WIFI<'_>,
+    channel: EspNowChannel,
+    send_retry: Retry,
     outgoing: Receiver<'_, CriticalSectionRawMutex, MsgOutgoing, LEN_OUTGOING>,
     incoming: Sender<'_, CriticalSectionRawMutex, MsgIncoming, LEN_INCOMING>,
+    shutdown: &Signal<CriticalSectionRawMutex, ()>,
 ) {
     let radio_init = esp_radio::init().expect("Failed to initialize Wi-Fi/BLE controller");
 
@@ -33,46 +70,153 @@ pub async fn communicate<
     wifi_controller.start().unwrap();
 
     let esp_now = interfaces.esp_now;
-    esp_now.set_channel(11).unwrap();
+    esp_now.set_channel(channel.get()).unwrap();
 
-    info!("esp-now version {}", esp_now.version().unwrap());
+    info!(
+        "esp-now version {} on channel {}",
+        esp_now.version().unwrap(),
+        channel.get()
+    );
 
     let (manager, esp_now_sender, esp_now_receiver) = esp_now.split();
 
-    let broadcast_fut = broadcast(esp_now_sender, outgoing);
+    // the failures of a previous run don't count against the re-initialized radio
+    CONSECUTIVE_SEND_FAILURES.store(0, Ordering::Relaxed);
+    let broadcast_fut = broadcast(esp_now_sender, send_retry, outgoing, shutdown);
     let receive_fut = receive(&manager, esp_now_receiver, incoming);
     let fetch_peers_fut = fetch_peers(&manager);
 
-    join3(broadcast_fut, receive_fut, fetch_peers_fut).await;
+    // receiving and peer bookkeeping can be dropped at any await point
+    select(broadcast_fut, join(receive_fut, fetch_peers_fut)).await;
+    info!("esp-now stopped");
+}
+
+/// Send retry policy for [`communicate`], 3 attempts per esp-now packet before the message is
+/// dropped, waiting 2ms before the first retry and doubling that on every further failure
+pub const SEND_RETRY: Retry = Retry::new(3, 2);
+
+/// Number of messages dropped after exhausting all send attempts in a row,
+/// reset by the next successfully sent message
+static CONSECUTIVE_SEND_FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// Returns the number of consecutively dropped outgoing messages, a persistently growing
+/// count indicates a broken link. Starts over from zero whenever [`communicate`] is started.
+pub fn consecutive_send_failures() -> u32 {
+    CONSECUTIVE_SEND_FAILURES.load(Ordering::Relaxed)
+}
+
+/// Sends a single packet, retrying with exponential backoff
+async fn send_with_retry(
+    sender: &mut EspNowSender<'_>,
+    mut retry: Retry,
+    packet: &[u8],
+) -> Result<(), EspNowError> {
+    loop {
+        let err = match sender.send_async(&BROADCAST_ADDRESS, packet).await {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+        match retry.failed() {
+            Some(delay_ms) => {
+                debug!("Send attempt failed, retrying in {}ms: {}", delay_ms, err);
+                Timer::after_millis(delay_ms).await;
+            }
+            None => return Err(err),
+        }
+    }
 }
 
 async fn broadcast<Msg: SchemaWrite<Src = Msg> + Format, const LEN: usize>(
     mut sender: EspNowSender<'_>,
+    send_retry: Retry,
     messages: Receiver<'_, CriticalSectionRawMutex, Msg, LEN>,
+    shutdown: &Signal<CriticalSectionRawMutex, ()>,
 ) {
-    loop {
-        let message = messages.receive().await;
-        let bytes = wincode::serialize(&message).unwrap();
+    let mut fragmenter = Fragmenter::default();
+    let send = async |message: Msg| {
+        let Ok(bytes) = wincode::serialize(&message) else {
+            error!("Unable to serialize {}", message);
+            return;
+        };
+        let Ok(fragments) = fragmenter.fragment(&bytes) else {
+            error!(
+                "Dropping message of {} bytes, too large to fragment",
+                bytes.len()
+            );
+            return;
+        };
 
-        let status = sender.send_async(&BROADCAST_ADDRESS, &bytes).await;
+        let mut status = Ok(());
+        for fragment in fragments {
+            status = send_with_retry(&mut sender, send_retry, &fragment).await;
+            if status.is_err() {
+                break;
+            }
+        }
         match status {
-            Ok(_) => debug!("Sent {}", message),
-            Err(err) => error!("Error while sending: {}", err),
+            Ok(()) => {
+                CONSECUTIVE_SEND_FAILURES.store(0, Ordering::Relaxed);
+                debug!("Sent {}", message);
+            }
+            Err(err) => {
+                CONSECUTIVE_SEND_FAILURES.fetch_add(1, Ordering::Relaxed);
+                error!("Error while sending: {}", err);
+            }
         }
-    }
+    };
+    send_until_shutdown(
+        async || messages.receive().await,
+        send,
+        async || shutdown.wait().await,
+    )
+    .await;
 }
 
-async fn receive<Msg: SchemaReadOwned<Dst = Msg> + Format, const LEN: usize>(
+/// Time after which incompletely received fragmented messages are discarded
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_millis(200);
+/// Silence after which a peer is taken as restarted and its sequence numbers start over,
+/// shorter than a reboot but longer than the gaps between messages of a live peer
+const RESTART_GAP: Duration = Duration::from_millis(100);
+
+async fn receive<Msg: SchemaReadOwned<Dst = Msg> + Format + Urgent, const LEN: usize>(
     manager: &EspNowManager<'_>,
     mut receiver: EspNowReceiver<'_>,
     messages: Sender<'_, CriticalSectionRawMutex, Msg, LEN>,
 ) {
+    let mut reassembler = Reassembler::new(REASSEMBLY_TIMEOUT.as_millis());
+    let mut seq_filter = PeerSeqFilter::<MAX_PEERS>::new(RESTART_GAP.as_millis());
     loop {
         let received = receiver.receive_async().await;
-        let incoming_event = wincode::deserialize(received.data()).unwrap();
-        debug!("Received {:?}", incoming_event);
 
-        messages.send(incoming_event).await;
+        match reassembler.receive(received.data(), Instant::now().as_millis()) {
+            Some((seq, bytes)) => match wincode::deserialize::<Msg>(&bytes) {
+                // urgent messages bypass the filter, a repeated stop must never be dropped
+                Ok(incoming_event)
+                    if seq_filter.accept(
+                        received.info.src_address,
+                        seq,
+                        Instant::now().as_millis(),
+                    ) || incoming_event.is_urgent() =>
+                {
+                    debug!("Received {:?}", incoming_event);
+                    messages.send(incoming_event).await;
+                }
+                Ok(_) => debug!("Dropping duplicate or outdated message {}", seq),
+                Err(err) if is_unknown_variant(&bytes, &err) => {
+                    debug!("Ignoring message of unknown variant from newer firmware");
+                }
+                Err(_) => error!("Unable to deserialize message of {} bytes", bytes.len()),
+            },
+            None => {}
+        }
+
+        PEERS.lock(|peers| {
+            peers.borrow_mut().update(
+                received.info.src_address,
+                received.info.rx_control.rssi,
+                Instant::now().as_millis(),
+            )
+        });
 
         if received.info.dst_address == BROADCAST_ADDRESS
             && !manager.peer_exists(&received.info.src_address)
@@ -92,9 +236,17 @@ async fn receive<Msg: SchemaReadOwned<Dst = Msg> + Format, const LEN: usize>(
 }
 
 async fn fetch_peers(manager: &EspNowManager<'_>) {
-    let mut ticker = Ticker::every(Duration::from_millis(500));
+    let mut backoff = PeerPollBackoff::new();
+    let mut interval = PeerPollBackoff::SEARCHING;
     loop {
-        ticker.next().await;
+        Timer::after(interval).await;
+
+        let peer_count = PEERS.lock(|peers| {
+            let mut peers = peers.borrow_mut();
+            peers.evict(Instant::now().as_millis(), PEER_TIMEOUT.as_millis());
+            peers.iter().count()
+        });
+        interval = backoff.next(peer_count);
 
         if manager.fetch_peer(false).is_err() {
             _ = manager.fetch_peer(true);
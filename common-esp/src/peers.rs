@@ -0,0 +1,40 @@
+use embassy_time::Duration;
+
+/// Interval of the peer bookkeeping, fast while searching for peers and backing off
+/// exponentially once peers are established, to keep management traffic off the radio
+pub struct PeerPollBackoff {
+    interval: Duration,
+    peers: usize,
+}
+
+impl PeerPollBackoff {
+    /// Interval while no peer is known or right after a peer was lost
+    pub const SEARCHING: Duration = Duration::from_millis(100);
+    /// Longest interval with established peers, well below [`crate::PEER_TIMEOUT`]
+    pub const ESTABLISHED: Duration = Duration::from_millis(1600);
+
+    pub const fn new() -> Self {
+        Self {
+            interval: Self::SEARCHING,
+            peers: 0,
+        }
+    }
+
+    /// Returns the delay until the next poll given the current number of peers
+    pub fn next(&mut self, peers: usize) -> Duration {
+        let lost = peers < self.peers;
+        self.interval = if peers == 0 || lost {
+            Self::SEARCHING
+        } else {
+            (self.interval * 2).min(Self::ESTABLISHED)
+        };
+        self.peers = peers;
+        self.interval
+    }
+}
+
+impl Default for PeerPollBackoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
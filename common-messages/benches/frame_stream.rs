@@ -0,0 +1,59 @@
+//! Throughput of `FrameStreamDecoder<DroneResponse>`, run with `cargo bench`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use common_messages::{DroneResponse, Frame, FrameStreamDecoder};
+
+const MEASURE_TIME: Duration = Duration::from_secs(1);
+const FRAMES_PER_STREAM: usize = 64;
+
+/// Encoded stream of `count` log frames with `payload_len` bytes each
+fn log_stream(payload_len: usize, count: usize) -> Vec<u8> {
+    let payload: Box<[u8]> = (0..payload_len).map(|i| (i * 31) as u8).collect();
+    let frame = Frame::encode(&DroneResponse::Log(payload)).unwrap();
+    frame.repeat(count)
+}
+
+/// Feeds `stream` to a decoder in chunks of `chunk_len`, returns the number of decoded frames
+fn decode(stream: &[u8], chunk_len: usize) -> usize {
+    let mut decoder = FrameStreamDecoder::<DroneResponse>::default();
+    let mut decoded = 0;
+    for chunk in stream.chunks(chunk_len) {
+        decoder.receive(|buffer| {
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            chunk.len()
+        });
+        decoded += decoder.by_ref().map(black_box).count();
+    }
+    decoded
+}
+
+fn bench(name: &str, stream: &[u8], chunk_len: usize) {
+    let start = Instant::now();
+    let mut frames = 0;
+    while start.elapsed() < MEASURE_TIME {
+        frames += decode(black_box(stream), chunk_len);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "{name:<32} {:>12.0} frames/s {:>8.1} MB/s",
+        frames as f64 / elapsed,
+        (frames / FRAMES_PER_STREAM * stream.len()) as f64 / elapsed / 1e6,
+    );
+}
+
+fn main() {
+    for payload_len in [8, 64, 200, 900] {
+        let stream = log_stream(payload_len, FRAMES_PER_STREAM);
+        let frame_len = stream.len() / FRAMES_PER_STREAM;
+
+        bench(
+            &format!("log {payload_len}B, whole frames"),
+            &stream,
+            frame_len,
+        );
+        // Worst case: every byte arrives on its own
+        bench(&format!("log {payload_len}B, byte splits"), &stream, 1);
+    }
+}
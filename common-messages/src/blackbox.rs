@@ -0,0 +1,98 @@
+//! In-RAM flight recorder keeping the most recent control loop frames for post-crash analysis
+
+use crate::BlackboxFrame;
+
+/// Fixed capacity ring buffer of [`BlackboxFrame`]s, overwriting the oldest frame when full.
+/// Recording never allocates, so it is safe to use in the control loop.
+pub struct Blackbox<const N: usize> {
+    frames: [BlackboxFrame; N],
+    /// Slot the next frame is written to
+    next: usize,
+    len: usize,
+}
+
+impl<const N: usize> Blackbox<N> {
+    pub const fn new() -> Self {
+        Self {
+            frames: [BlackboxFrame::EMPTY; N],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn record(&mut self, frame: BlackboxFrame) {
+        self.frames[self.next] = frame;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the `index`th recorded frame, oldest first
+    pub fn get(&self, index: usize) -> Option<BlackboxFrame> {
+        if index >= self.len {
+            return None;
+        }
+        let oldest = (self.next + N - self.len) % N;
+        Some(self.frames[(oldest + index) % N])
+    }
+
+    /// Recorded frames, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = BlackboxFrame> + '_ {
+        (0..self.len).filter_map(|i| self.get(i))
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for Blackbox<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn blackbox_wraparound() {
+    use alloc::vec::Vec;
+
+    let frame = |timestamp| BlackboxFrame {
+        timestamp,
+        ..BlackboxFrame::EMPTY
+    };
+    let timestamps =
+        |blackbox: &Blackbox<4>| blackbox.iter().map(|f| f.timestamp).collect::<Vec<_>>();
+
+    let mut blackbox = Blackbox::<4>::new();
+    assert!(blackbox.is_empty());
+    assert_eq!(blackbox.get(0), None);
+
+    for t in 1..=3 {
+        blackbox.record(frame(t));
+    }
+    assert_eq!(blackbox.len(), 3);
+    assert_eq!(timestamps(&blackbox), [1, 2, 3]);
+
+    // full, the oldest frames are overwritten and the dump stays oldest first
+    for t in 4..=10 {
+        blackbox.record(frame(t));
+    }
+    assert_eq!(blackbox.len(), 4);
+    assert_eq!(timestamps(&blackbox), [7, 8, 9, 10]);
+    assert_eq!(blackbox.get(0), Some(frame(7)));
+    assert_eq!(blackbox.get(3), Some(frame(10)));
+    assert_eq!(blackbox.get(4), None);
+
+    blackbox.clear();
+    assert!(blackbox.is_empty());
+    blackbox.record(frame(11));
+    assert_eq!(timestamps(&blackbox), [11]);
+}
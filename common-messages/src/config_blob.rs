@@ -0,0 +1,123 @@
+use crate::ControlConfig;
+
+/// Size of a stored config blob
+pub const CONFIG_BLOB_LEN: usize = 128;
+/// Layout of the stored [`ControlConfig`]. Bump it with every change to the config fields, so
+/// a config stored by older firmware is discarded instead of misread.
+pub const CONFIG_VERSION: u16 = 1;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"QCFG");
+const HEADER_LEN: usize = 8;
+const CRC_LEN: usize = 4;
+/// Largest payload that fits a blob
+const MAX_PAYLOAD_LEN: usize = CONFIG_BLOB_LEN - HEADER_LEN - CRC_LEN;
+
+/// Serializes a config for flash.
+///
+/// Blob layout: magic (u32), [`CONFIG_VERSION`] (u16), payload length (u16), wincode payload,
+/// crc32 of the payload (u32), padded with 0xff
+pub fn encode_config(config: &ControlConfig) -> [u8; CONFIG_BLOB_LEN] {
+    let payload = wincode::serialize(config).expect("config serialization");
+    assert!(
+        payload.len() <= MAX_PAYLOAD_LEN,
+        "config of {} bytes exceeds the blob",
+        payload.len()
+    );
+    let crc_offset = HEADER_LEN + payload.len();
+
+    let mut blob = [0xff; CONFIG_BLOB_LEN];
+    blob[..4].copy_from_slice(&MAGIC.to_le_bytes());
+    blob[4..6].copy_from_slice(&CONFIG_VERSION.to_le_bytes());
+    blob[6..HEADER_LEN].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    blob[HEADER_LEN..crc_offset].copy_from_slice(&payload);
+    blob[crc_offset..crc_offset + CRC_LEN].copy_from_slice(&crc32(&payload).to_le_bytes());
+    blob
+}
+
+/// Returns `None` for an empty or corrupt blob, or one of another [`CONFIG_VERSION`]
+pub fn decode_config(blob: &[u8; CONFIG_BLOB_LEN]) -> Option<ControlConfig> {
+    let (header, rest) = blob.split_first_chunk::<HEADER_LEN>()?;
+    let [m0, m1, m2, m3, v0, v1, l0, l1] = *header;
+    if u32::from_le_bytes([m0, m1, m2, m3]) != MAGIC
+        || u16::from_le_bytes([v0, v1]) != CONFIG_VERSION
+    {
+        return None;
+    }
+
+    let len = u16::from_le_bytes([l0, l1]) as usize;
+    let payload = rest.get(..len)?;
+    let crc = rest.get(len..len + CRC_LEN)?;
+    if crc32(payload).to_le_bytes() != crc {
+        return None;
+    }
+
+    wincode::deserialize(payload).ok()
+}
+
+/// CRC-32 (IEEE)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[test]
+fn config_blob_roundtrip() {
+    use crate::MotorOutput;
+
+    let config = ControlConfig {
+        kp: [25.0, 25.0, 10.0],
+        ki: [0.0, 0.0, 0.5],
+        kd: [10.0, 10.0, 0.0],
+        alpha: 0.95,
+        accel_lowpass: 0.1,
+        idle_thrust: 70.0,
+        thrust_expo: 0.3,
+        arm_timeout_ms: 500,
+        settle_ms: 2000,
+        max_tilt_deg: 60.0,
+        control_rate_hz: 1600,
+        throttle_deadband: 30.0,
+        motor_outputs: [(1, false), (2, true), (3, false), (0, true)].map(
+            |(mixer_index, reversed)| MotorOutput {
+                mixer_index,
+                reversed,
+            },
+        ),
+    };
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    // room left for new fields
+    assert!(wincode::serialize(&config).unwrap().len() <= MAX_PAYLOAD_LEN);
+
+    let blob = encode_config(&config);
+    assert_eq!(decode_config(&blob), Some(config));
+
+    // erased flash
+    assert_eq!(decode_config(&[0xff; CONFIG_BLOB_LEN]), None);
+
+    // any flipped payload bit fails the checksum
+    for bit in 0..8 {
+        let mut corrupt = blob;
+        corrupt[HEADER_LEN + 5] ^= 1 << bit;
+        assert_eq!(decode_config(&corrupt), None);
+    }
+
+    // stored by firmware with another config layout
+    let mut outdated = blob;
+    outdated[4..6].copy_from_slice(&(CONFIG_VERSION - 1).to_le_bytes());
+    assert_eq!(decode_config(&outdated), None);
+
+    // length past the end of the blob
+    let mut truncated = blob;
+    truncated[6..HEADER_LEN].copy_from_slice(&(CONFIG_BLOB_LEN as u16).to_le_bytes());
+    assert_eq!(decode_config(&truncated), None);
+}
@@ -0,0 +1,88 @@
+use defmt::Format;
+use wincode::{SchemaRead, SchemaWrite};
+
+use crate::STICK_MAX_ANGLE;
+
+/// How the attitude targets of [`crate::RemoteRequest::SetTarget`] and
+/// [`crate::RemoteRequest::Sticks`] are interpreted by the controller
+#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Eq, Clone, Copy, Default)]
+pub enum ControlMode {
+    /// Roll and pitch targets are angles in degrees, the drone levels itself with centered
+    /// sticks. Yaw is a rotation rate in degrees per second.
+    #[default]
+    Angle,
+    /// All targets are rotation rates in degrees per second, centered sticks hold the current
+    /// attitude (acro). Flips are allowed, so the tilt cutoff of
+    /// [`crate::ControlConfig::max_tilt_deg`] doesn't apply.
+    ///
+    /// The PID gains act on rate instead of angle errors here, which are an order of magnitude
+    /// larger. Gains tuned for angle mode saturate the mixer, rate mode needs its own tune.
+    Rate,
+}
+
+impl ControlMode {
+    /// Rotation rate in degrees per second at full stick deflection in [`ControlMode::Rate`]
+    pub const STICK_MAX_RATE: f32 = 200.0;
+
+    /// Scales a target of [`crate::sticks_to_setpoint`] from stick angles to this mode
+    pub fn stick_target(self, target: [f32; 3]) -> [f32; 3] {
+        match self {
+            ControlMode::Angle => target,
+            ControlMode::Rate => target.map(|angle| angle / STICK_MAX_ANGLE * Self::STICK_MAX_RATE),
+        }
+    }
+
+    /// Roll, pitch and yaw errors fed into the PIDs, from the `target`, the estimated
+    /// `orientation` in degrees and the gyro `rates` in degrees per second
+    pub fn attitude_error(
+        self,
+        target: [f32; 3],
+        orientation: [f32; 3],
+        rates: [f32; 3],
+    ) -> [f32; 3] {
+        match self {
+            ControlMode::Angle => [
+                target[0] - orientation[0],
+                target[1] - orientation[1],
+                target[2] - rates[2],
+            ],
+            ControlMode::Rate => [0, 1, 2].map(|i| target[i] - rates[i]),
+        }
+    }
+}
+
+#[test]
+fn attitude_error_by_mode() {
+    use crate::sticks_to_setpoint;
+
+    // half roll stick, full pitch stick back, no yaw, while banked and rotating
+    let (sticks, _) = sticks_to_setpoint(0.5, -1.0, 0.0, 0.0);
+    let orientation = [10.0, 5.0, 90.0];
+    let rates = [40.0, -20.0, 15.0];
+
+    let angle = ControlMode::Angle;
+    assert_eq!(angle.stick_target(sticks), [15.0, -30.0, 0.0]);
+    assert_eq!(
+        angle.attitude_error(angle.stick_target(sticks), orientation, rates),
+        [5.0, -35.0, -15.0]
+    );
+
+    let rate = ControlMode::Rate;
+    assert_eq!(rate.stick_target(sticks), [100.0, -200.0, 0.0]);
+    assert_eq!(
+        rate.attitude_error(rate.stick_target(sticks), orientation, rates),
+        [60.0, -180.0, -15.0]
+    );
+
+    // centered sticks level the drone in angle mode but only stop the rotation in rate mode
+    let (centered, _) = sticks_to_setpoint(0.0, 0.0, 0.0, 0.0);
+    assert_eq!(
+        angle.attitude_error(centered, orientation, rates),
+        [-10.0, -5.0, -15.0]
+    );
+    assert_eq!(
+        rate.attitude_error(rate.stick_target(centered), orientation, rates),
+        [-40.0, 20.0, -15.0]
+    );
+    assert_eq!(ControlMode::default(), ControlMode::Angle);
+}
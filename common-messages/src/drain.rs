@@ -0,0 +1,93 @@
+use alloc::vec::Vec;
+
+use crate::RemoteRequest;
+
+/// Collapses a queue of pending requests so a burst is handled at once.
+///
+/// Of the continuous requests ([`RemoteRequest::SetThrust`], [`RemoteRequest::SetTarget`] and
+/// [`RemoteRequest::Sticks`]) only the latest of each kind is kept, at the position it was
+/// received. All other requests are discrete and kept once each, in order. They act as
+/// barriers, only runs of continuous requests between them are collapsed, so a discrete request
+/// like [`RemoteRequest::SetArm`] still sees the state sent before it.
+pub fn drain_latest(requests: impl IntoIterator<Item = RemoteRequest>) -> Vec<RemoteRequest> {
+    let mut drained: Vec<RemoteRequest> = Vec::new();
+    // start of the run of continuous requests since the last discrete one
+    let mut run_start = 0;
+    for req in requests {
+        if matches!(
+            req,
+            RemoteRequest::SetThrust(_)
+                | RemoteRequest::SetTarget(_)
+                | RemoteRequest::Sticks { .. }
+        ) {
+            let kind = core::mem::discriminant(&req);
+            let run = &drained[run_start..];
+            if let Some(earlier) = run
+                .iter()
+                .position(|earlier| core::mem::discriminant(earlier) == kind)
+            {
+                drained.remove(run_start + earlier);
+            }
+        } else {
+            run_start = drained.len() + 1;
+        }
+        drained.push(req);
+    }
+    drained
+}
+
+#[test]
+fn drain_keeps_latest_continuous_requests() {
+    use alloc::vec;
+
+    let queue = vec![
+        RemoteRequest::SetThrust(100.0),
+        RemoteRequest::SetTarget([1.0, 0.0, 0.0]),
+        RemoteRequest::SetThrust(200.0),
+        RemoteRequest::SetTarget([2.0, 0.0, 0.0]),
+        RemoteRequest::ArmConfirm,
+        RemoteRequest::SetThrust(50.0),
+        RemoteRequest::SetThrust(0.0),
+        RemoteRequest::SetArm(true),
+        RemoteRequest::SetTarget([3.0, 0.0, 0.0]),
+        RemoteRequest::SetTarget([4.0, 0.0, 0.0]),
+        RemoteRequest::SetThrust(300.0),
+    ];
+    assert_eq!(
+        drain_latest(queue),
+        [
+            RemoteRequest::SetThrust(200.0),
+            RemoteRequest::SetTarget([2.0, 0.0, 0.0]),
+            RemoteRequest::ArmConfirm,
+            RemoteRequest::SetThrust(0.0),
+            RemoteRequest::SetArm(true),
+            RemoteRequest::SetTarget([4.0, 0.0, 0.0]),
+            RemoteRequest::SetThrust(300.0),
+        ]
+    );
+
+    // arming is checked against the thrust sent right before it
+    let arm_at_idle = [
+        RemoteRequest::SetThrust(500.0),
+        RemoteRequest::SetThrust(0.0),
+        RemoteRequest::SetArm(true),
+    ];
+    assert_eq!(
+        drain_latest(arm_at_idle),
+        [RemoteRequest::SetThrust(0.0), RemoteRequest::SetArm(true)]
+    );
+    let arm_then_climb = || {
+        [
+            RemoteRequest::SetThrust(0.0),
+            RemoteRequest::SetArm(true),
+            RemoteRequest::SetThrust(800.0),
+        ]
+    };
+    assert_eq!(drain_latest(arm_then_climb()), arm_then_climb());
+
+    assert!(drain_latest([]).is_empty());
+    assert_eq!(
+        drain_latest([RemoteRequest::Reset, RemoteRequest::Reset]),
+        [RemoteRequest::Reset, RemoteRequest::Reset]
+    );
+}
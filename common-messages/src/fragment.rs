@@ -0,0 +1,201 @@
+use alloc::{boxed::Box, vec::Vec};
+
+use defmt::Format;
+
+use crate::ESP_NOW_MTU;
+
+/// Fragment header: seq (u16), frag_idx (u8), frag_count (u8)
+pub const FRAGMENT_HEADER_LEN: usize = 4;
+
+/// Largest payload carried by a single fragment
+pub const MAX_FRAGMENT_PAYLOAD: usize = ESP_NOW_MTU - FRAGMENT_HEADER_LEN;
+
+#[derive(Debug, Format, PartialEq, Eq)]
+pub enum FragmentError {
+    /// Message needs more than `u8::MAX` fragments
+    TooLarge,
+}
+
+/// Splits serialized messages into packets of at most [`ESP_NOW_MTU`] bytes
+#[derive(Default)]
+pub struct Fragmenter {
+    next_seq: u16,
+}
+
+impl Fragmenter {
+    pub fn fragment<'a>(
+        &mut self,
+        data: &'a [u8],
+    ) -> Result<impl Iterator<Item = Box<[u8]>> + 'a, FragmentError> {
+        let frag_count = data.len().div_ceil(MAX_FRAGMENT_PAYLOAD).max(1);
+        let frag_count = u8::try_from(frag_count).map_err(|_| FragmentError::TooLarge)?;
+
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        let [seq0, seq1] = seq.to_le_bytes();
+        let chunks = data.chunks(MAX_FRAGMENT_PAYLOAD);
+        // Empty messages still need a single (empty) fragment
+        let empty = data.is_empty().then_some(&[][..]);
+
+        Ok(chunks
+            .chain(empty)
+            .enumerate()
+            .map(move |(frag_idx, chunk)| {
+                let mut packet = Vec::with_capacity(FRAGMENT_HEADER_LEN + chunk.len());
+                packet.extend_from_slice(&[seq0, seq1, frag_idx as u8, frag_count]);
+                packet.extend_from_slice(chunk);
+                packet.into_boxed_slice()
+            }))
+    }
+}
+
+struct PendingMessage {
+    seq: u16,
+    started_ms: u64,
+    fragments: Vec<Option<Box<[u8]>>>,
+    received: usize,
+}
+
+/// Reassembles packets produced by a [`Fragmenter`] into the serialized message.
+///
+/// Incomplete messages are discarded after a timeout.
+pub struct Reassembler {
+    timeout_ms: u64,
+    pending: Vec<PendingMessage>,
+}
+
+impl Reassembler {
+    /// Maximum number of messages reassembled concurrently
+    const MAX_PENDING: usize = 4;
+
+    pub fn new(timeout_ms: u64) -> Self {
+        Self {
+            timeout_ms,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Receives a single packet, returns the sequence number and full message once all its
+    /// fragments arrived
+    pub fn receive(&mut self, packet: &[u8], now_ms: u64) -> Option<(u16, Vec<u8>)> {
+        self.pending
+            .retain(|msg| now_ms.saturating_sub(msg.started_ms) <= self.timeout_ms);
+
+        let (&[seq0, seq1, frag_idx, frag_count], payload) =
+            packet.split_first_chunk::<FRAGMENT_HEADER_LEN>()?;
+        if frag_idx >= frag_count {
+            return None;
+        }
+        let seq = u16::from_le_bytes([seq0, seq1]);
+        if frag_count == 1 {
+            return Some((seq, payload.to_vec()));
+        }
+
+        let pos = match self.pending.iter().position(|msg| msg.seq == seq) {
+            Some(pos) if self.pending[pos].fragments.len() == frag_count as usize => pos,
+            existing => {
+                // New message, or a stale one with a reused sequence number
+                if let Some(pos) = existing {
+                    self.pending.remove(pos);
+                }
+                if self.pending.len() >= Self::MAX_PENDING {
+                    self.pending.remove(0);
+                }
+                self.pending.push(PendingMessage {
+                    seq,
+                    started_ms: now_ms,
+                    fragments: (0..frag_count).map(|_| None).collect(),
+                    received: 0,
+                });
+                self.pending.len() - 1
+            }
+        };
+
+        let msg = &mut self.pending[pos];
+        let fragment = &mut msg.fragments[frag_idx as usize];
+        if fragment.is_none() {
+            *fragment = Some(Box::from(payload));
+            msg.received += 1;
+        }
+
+        if msg.received < msg.fragments.len() {
+            return None;
+        }
+
+        let msg = self.pending.remove(pos);
+        Some((seq, msg.fragments.into_iter().flatten().flatten().collect()))
+    }
+}
+
+#[cfg(test)]
+fn fragment_test_data() -> (Vec<u8>, Vec<Box<[u8]>>) {
+    let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+    let packets: Vec<_> = Fragmenter::default().fragment(&data).unwrap().collect();
+    (data, packets)
+}
+
+#[test]
+fn fragment_in_order() {
+    let (data, packets) = fragment_test_data();
+    assert_eq!(packets.len(), 5);
+    assert!(packets.iter().all(|p| p.len() <= ESP_NOW_MTU));
+
+    let mut reassembler = Reassembler::new(100);
+    let (last, rest) = packets.split_last().unwrap();
+    for packet in rest {
+        assert_eq!(reassembler.receive(packet, 0), None);
+    }
+    assert_eq!(reassembler.receive(last, 0), Some((0, data)));
+}
+
+#[test]
+fn fragment_out_of_order() {
+    let (data, packets) = fragment_test_data();
+
+    let mut reassembler = Reassembler::new(100);
+    for i in [3, 0, 4, 0, 1, 3] {
+        assert_eq!(reassembler.receive(&packets[i], 0), None);
+    }
+    assert_eq!(reassembler.receive(&packets[2], 0), Some((0, data)));
+}
+
+#[test]
+fn fragment_missing() {
+    let (data, packets) = fragment_test_data();
+
+    let mut reassembler = Reassembler::new(100);
+    for packet in &packets[1..] {
+        assert_eq!(reassembler.receive(packet, 0), None);
+    }
+
+    // Incomplete message is discarded after the timeout
+    assert_eq!(reassembler.receive(&packets[0], 101), None);
+
+    // ...and so is the one started by that packet
+    for packet in &packets[1..] {
+        assert_eq!(reassembler.receive(packet, 202), None);
+    }
+    assert_eq!(reassembler.receive(&packets[0], 250), Some((0, data)));
+
+    // Single fragment and empty messages pass straight through
+    let mut fragmenter = Fragmenter::default();
+    let small: Vec<_> = fragmenter.fragment(&[1, 2, 3]).unwrap().collect();
+    assert_eq!(small.len(), 1);
+    assert_eq!(
+        reassembler.receive(&small[0], 300),
+        Some((0, alloc::vec![1, 2, 3]))
+    );
+    let empty: Vec<_> = fragmenter.fragment(&[]).unwrap().collect();
+    assert_eq!(empty.len(), 1);
+    assert_eq!(reassembler.receive(&empty[0], 300), Some((1, Vec::new())));
+
+    // Truncated packets are ignored
+    assert_eq!(reassembler.receive(&[0, 0, 0], 300), None);
+    assert_eq!(
+        fragmenter
+            .fragment(&alloc::vec![0; 256 * MAX_FRAGMENT_PAYLOAD])
+            .err(),
+        Some(FragmentError::TooLarge)
+    );
+}
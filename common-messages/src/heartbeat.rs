@@ -0,0 +1,48 @@
+use crate::DroneResponse;
+
+/// Rate limits [`DroneResponse::Heartbeat`] to one per [`HeartbeatTimer::INTERVAL_MS`].
+///
+/// Beats missed while the caller was stalled are skipped instead of sent in a burst.
+#[derive(Default)]
+pub struct HeartbeatTimer {
+    next_ms: Option<u64>,
+}
+
+impl HeartbeatTimer {
+    pub const INTERVAL_MS: u64 = 500;
+
+    /// Returns the heartbeat to send at `uptime_ms`, if one is due
+    pub fn poll(&mut self, uptime_ms: u64) -> Option<DroneResponse> {
+        match self.next_ms {
+            Some(next) if uptime_ms < next => return None,
+            Some(next) if uptime_ms < next + Self::INTERVAL_MS => {
+                self.next_ms = Some(next + Self::INTERVAL_MS)
+            }
+            _ => self.next_ms = Some(uptime_ms + Self::INTERVAL_MS),
+        }
+        Some(DroneResponse::Heartbeat {
+            uptime_ms: uptime_ms as u32,
+        })
+    }
+}
+
+#[test]
+fn heartbeat_rate_limit() {
+    let mut timer = HeartbeatTimer::default();
+    let beats = |timer: &mut HeartbeatTimer, times: core::ops::Range<u64>| {
+        times
+            .filter_map(|t| timer.poll(t))
+            .map(|beat| match beat {
+                DroneResponse::Heartbeat { uptime_ms } => uptime_ms,
+                _ => unreachable!(),
+            })
+            .collect::<alloc::vec::Vec<_>>()
+    };
+
+    // First poll beats immediately, then at a fixed rate keeping the phase
+    assert_eq!(beats(&mut timer, 3..1600), [3, 503, 1003, 1503]);
+
+    // After a stall only a single beat is sent and the phase restarts
+    assert_eq!(beats(&mut timer, 5000..5001), [5000]);
+    assert_eq!(beats(&mut timer, 5001..5600), [5500]);
+}
@@ -0,0 +1,163 @@
+pub trait ImuSample {
+    fn gyro(&self) -> [f32; 3];
+    fn accel(&self) -> [f32; 3];
+    fn dt(&self) -> f32;
+    /// Time the sample was taken in microseconds, monotonic across counter wraps
+    fn timestamp(&self) -> u64;
+    /// Magnetic field, if the sensor has a magnetometer
+    fn mag(&self) -> Option<[f32; 3]> {
+        None
+    }
+    /// Barometric pressure in Pa, if a barometer is read along with the IMU
+    fn pressure(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Consecutive samples combined into one, so a control loop running slower than the IMU
+/// still integrates every gyro reading.
+///
+/// Gyro rates are averaged weighted by `dt`, which integrates to the same angle as the single
+/// samples. Accel, magnetometer, pressure and timestamp are those of the newest sample.
+#[derive(Debug, Clone, Copy)]
+pub struct MergedSample {
+    gyro: [f32; 3],
+    accel: [f32; 3],
+    mag: Option<[f32; 3]>,
+    pressure: Option<f32>,
+    dt: f32,
+    timestamp: u64,
+    count: u32,
+}
+
+impl MergedSample {
+    pub fn new(sample: impl ImuSample) -> Self {
+        Self {
+            gyro: sample.gyro(),
+            accel: sample.accel(),
+            mag: sample.mag(),
+            pressure: sample.pressure(),
+            dt: sample.dt(),
+            timestamp: sample.timestamp(),
+            count: 1,
+        }
+    }
+
+    /// Adds the next newer sample
+    pub fn merge(&mut self, sample: impl ImuSample) {
+        let dt = self.dt + sample.dt();
+        let gyro = sample.gyro();
+        if dt > 0.0 {
+            self.gyro = [0, 1, 2].map(|i| (self.gyro[i] * self.dt + gyro[i] * sample.dt()) / dt);
+        } else {
+            self.gyro = gyro;
+        }
+        self.accel = sample.accel();
+        self.mag = sample.mag();
+        self.pressure = sample.pressure();
+        self.dt = dt;
+        self.timestamp = sample.timestamp();
+        self.count += 1;
+    }
+
+    /// Number of merged samples
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+impl ImuSample for MergedSample {
+    fn gyro(&self) -> [f32; 3] {
+        self.gyro
+    }
+
+    fn accel(&self) -> [f32; 3] {
+        self.accel
+    }
+
+    fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn mag(&self) -> Option<[f32; 3]> {
+        self.mag
+    }
+
+    fn pressure(&self) -> Option<f32> {
+        self.pressure
+    }
+}
+
+#[test]
+fn merge_queued_samples() {
+    #[derive(Clone, Copy)]
+    struct Sample {
+        gyro: [f32; 3],
+        accel: [f32; 3],
+        dt: f32,
+        timestamp: u64,
+    }
+
+    impl ImuSample for Sample {
+        fn gyro(&self) -> [f32; 3] {
+            self.gyro
+        }
+
+        fn accel(&self) -> [f32; 3] {
+            self.accel
+        }
+
+        fn dt(&self) -> f32 {
+            self.dt
+        }
+
+        fn timestamp(&self) -> u64 {
+            self.timestamp
+        }
+
+        fn pressure(&self) -> Option<f32> {
+            Some(self.timestamp as f32)
+        }
+    }
+
+    let queued = [
+        (10.0, 0.001, 1_000),
+        (40.0, 0.002, 3_000),
+        (-20.0, 0.001, 4_000),
+    ]
+    .map(|(rate, dt, timestamp)| Sample {
+        gyro: [rate, 0.0, -rate],
+        accel: [0.0, 0.0, timestamp as f32],
+        dt,
+        timestamp,
+    });
+
+    // drained the way the control loop does
+    let mut queue = queued.into_iter();
+    let mut merged = MergedSample::new(queue.next().unwrap());
+    for sample in queue {
+        merged.merge(sample);
+    }
+
+    // integrates to the same angle as the single samples
+    let angle: f32 = queued.iter().map(|s| s.gyro[0] * s.dt).sum();
+    assert!((merged.gyro()[0] * merged.dt() - angle).abs() < 1e-6);
+    assert!((merged.gyro()[2] * merged.dt() + angle).abs() < 1e-6);
+    assert!((merged.dt() - 0.004).abs() < 1e-6);
+    assert_eq!(merged.count(), 3);
+
+    // everything else is the newest sample
+    assert_eq!(merged.accel(), [0.0, 0.0, 4_000.0]);
+    assert_eq!(merged.timestamp(), 4_000);
+    assert_eq!(merged.pressure(), Some(4_000.0));
+    assert_eq!(merged.mag(), None);
+
+    // a single sample passes unchanged
+    let single = MergedSample::new(queued[1]);
+    assert_eq!(single.gyro(), queued[1].gyro);
+    assert_eq!(single.count(), 1);
+}
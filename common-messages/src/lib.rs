@@ -8,7 +8,59 @@ use alloc::{boxed::Box, vec::Vec};
 use defmt::Format;
 use wincode::{SchemaRead, SchemaReadOwned, SchemaWrite};
 
-#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq)]
+mod blackbox;
+mod config_blob;
+mod control_mode;
+mod crc;
+mod drain;
+mod fragment;
+mod heartbeat;
+mod imu;
+mod motor_test;
+mod peers;
+mod queue;
+mod rate;
+mod relay;
+mod retry;
+mod self_test;
+mod sensor_fusion;
+mod sequence;
+mod shutdown;
+mod sticks;
+mod throttle;
+mod version;
+pub use blackbox::Blackbox;
+pub use config_blob::{CONFIG_BLOB_LEN, CONFIG_VERSION, decode_config, encode_config};
+pub use control_mode::ControlMode;
+pub use crc::crc16;
+pub use drain::drain_latest;
+pub use fragment::{
+    FRAGMENT_HEADER_LEN, FragmentError, Fragmenter, MAX_FRAGMENT_PAYLOAD, Reassembler,
+};
+pub use heartbeat::HeartbeatTimer;
+pub use imu::{ImuSample, MergedSample};
+pub use motor_test::{MOTOR_TEST_MAX_THROTTLE, MotorTestRejected, motor_test};
+pub use peers::{Peer, PeerTable};
+pub use queue::send_drop_oldest;
+pub use rate::{RateLimiter, RateMonitor};
+pub use relay::relay_step;
+pub use retry::Retry;
+pub use self_test::SelfTestChecks;
+pub use sensor_fusion::{
+    AltitudeEstimator, ComplementaryFilterFusion, ComplementaryFilterFusionBuilder, GyroBias, Pid,
+};
+pub use sequence::{PeerSeqFilter, SeqFilter};
+pub use shutdown::send_until_shutdown;
+pub use sticks::{STICK_MAX_ANGLE, STICK_MAX_THRUST, sticks_to_setpoint};
+pub use throttle::{
+    PULSE_MAX, PULSE_MIN, RampLimiter, THROTTLE_MAX, THROTTLE_STOP, map_motor_throttles,
+    normalized_throttle, throttle_deadband, throttle_to_pulse, thrust_expo, thrust_to_throttle,
+};
+pub use version::{GitHash, parse_git_hash};
+
+/// Encoded by variant position, so new variants must only ever be appended, see
+/// [`RemoteRequest::wire_tag`]
+#[derive(Debug, SchemaWrite, SchemaRead, PartialEq)]
 #[non_exhaustive]
 pub enum RemoteRequest {
     Ping(PingTarget, PingId),
@@ -22,6 +74,232 @@ pub enum RemoteRequest {
         kd: [f32; 3],
     },
     Reset,
+    /// Interval between telemetry responses in milliseconds
+    SetTelemetryInterval(u32),
+    /// Briefly spins a single motor (mixer order) with throttle 0..=1000, only when disarmed
+    MotorTest {
+        index: u8,
+        throttle: u16,
+    },
+    /// Gyro weight of the complementary filter in 0.0..=1.0
+    SetAlpha(f32),
+    /// Replaces the complete control configuration, answered with [`DroneResponse::Config`]
+    SetConfig(ControlConfig),
+    /// Requests the active [`DroneResponse::Config`]
+    GetConfig,
+    /// Stores the active config in flash, only when disarmed.
+    /// Answered with [`DroneResponse::ConfigSaved`]
+    SaveConfig,
+    /// Re-estimates the gyro bias, only when disarmed. The drone has to be kept still.
+    CalibrateGyro,
+    /// Re-estimates gyro bias and accelerometer offset, only when disarmed. The drone has to
+    /// be kept still and level. Answered with [`DroneResponse::CalibrationResult`]
+    Calibrate,
+    /// Streams the recorded control loop frames as [`DroneResponse::BlackboxFrame`]s, only
+    /// when disarmed
+    DumpBlackbox,
+    /// Cuts the motors unconditionally, even while arming, and ignores all further requests
+    /// until [`RemoteRequest::Reset`]
+    EmergencyStop,
+    /// Requests applied in order within a single control loop iteration, so related changes
+    /// never take effect one by one. See [`RemoteRequest::is_valid_batch`]
+    Batch(Vec<RemoteRequest>),
+    /// Runs the control loop as usual but keeps the motors idle, the intended throttles are
+    /// still reported. Can only be left while disarmed.
+    SetDryRun(bool),
+    /// Normalized stick positions in -1.0..=1.0, replacing a [`RemoteRequest::SetTarget`]
+    /// and [`RemoteRequest::SetThrust`] pair. See [`sticks_to_setpoint`]
+    Sticks {
+        roll: f32,
+        pitch: f32,
+        yaw: f32,
+        thrust: f32,
+    },
+    /// Logs the IMU configuration and status registers over defmt, only when disarmed
+    DumpImu,
+    /// Requests the [`DroneResponse::Version`] of the drone firmware
+    GetVersion,
+    /// Checks IMU, motors and link before a flight, only when disarmed. Spins each motor
+    /// briefly. Answered with [`DroneResponse::SelfTestResult`]
+    SelfTest {
+        /// Latest drone ping round trip measured by the remote, `None` without an answered ping
+        link_ms: Option<u16>,
+    },
+    /// Switches between angle and rate targets, see [`ControlMode`]. Resets the target.
+    SetControlMode(ControlMode),
+}
+
+impl RemoteRequest {
+    /// Tag of the variant on the wire. Pinned by a test, so inserting or reordering variants
+    /// fails instead of silently remapping messages between firmware builds.
+    pub const fn wire_tag(&self) -> u32 {
+        match self {
+            RemoteRequest::Ping(..) => 0,
+            RemoteRequest::SetArm(_) => 1,
+            RemoteRequest::ArmConfirm => 2,
+            RemoteRequest::SetThrust(_) => 3,
+            RemoteRequest::SetTarget(_) => 4,
+            RemoteRequest::SetTune { .. } => 5,
+            RemoteRequest::Reset => 6,
+            RemoteRequest::SetTelemetryInterval(_) => 7,
+            RemoteRequest::MotorTest { .. } => 8,
+            RemoteRequest::SetAlpha(_) => 9,
+            RemoteRequest::SetConfig(_) => 10,
+            RemoteRequest::GetConfig => 11,
+            RemoteRequest::SaveConfig => 12,
+            RemoteRequest::CalibrateGyro => 13,
+            RemoteRequest::Calibrate => 14,
+            RemoteRequest::DumpBlackbox => 15,
+            RemoteRequest::EmergencyStop => 16,
+            RemoteRequest::Batch(_) => 17,
+            RemoteRequest::SetDryRun(_) => 18,
+            RemoteRequest::Sticks { .. } => 19,
+            RemoteRequest::DumpImu => 20,
+            RemoteRequest::GetVersion => 21,
+            RemoteRequest::SelfTest { .. } => 22,
+            RemoteRequest::SetControlMode(_) => 23,
+        }
+    }
+
+    /// Maximum number of requests in a [`RemoteRequest::Batch`]
+    pub const MAX_BATCH_LEN: usize = 8;
+
+    /// Whether `requests` may be sent as a [`RemoteRequest::Batch`]. Batches can't be nested
+    /// and an emergency stop must be sent on its own, so it is never held back.
+    pub fn is_valid_batch(requests: &[RemoteRequest]) -> bool {
+        requests.len() <= Self::MAX_BATCH_LEN
+            && !requests
+                .iter()
+                .any(|req| matches!(req, RemoteRequest::Batch(_) | RemoteRequest::EmergencyStop))
+    }
+}
+
+/// Written by hand as the derive can't handle the recursion of [`RemoteRequest::Batch`],
+/// batches are logged by their length
+impl Format for RemoteRequest {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            RemoteRequest::Ping(target, id) => defmt::write!(f, "Ping({}, {})", target, id),
+            RemoteRequest::SetArm(arm) => defmt::write!(f, "SetArm({})", arm),
+            RemoteRequest::ArmConfirm => defmt::write!(f, "ArmConfirm"),
+            RemoteRequest::SetThrust(thrust) => defmt::write!(f, "SetThrust({})", thrust),
+            RemoteRequest::SetTarget(target) => defmt::write!(f, "SetTarget({})", target),
+            RemoteRequest::SetTune { kp, ki, kd } => {
+                defmt::write!(f, "SetTune {{ kp: {}, ki: {}, kd: {} }}", kp, ki, kd)
+            }
+            RemoteRequest::Reset => defmt::write!(f, "Reset"),
+            RemoteRequest::SetTelemetryInterval(ms) => {
+                defmt::write!(f, "SetTelemetryInterval({})", ms)
+            }
+            RemoteRequest::MotorTest { index, throttle } => defmt::write!(
+                f,
+                "MotorTest {{ index: {}, throttle: {} }}",
+                index,
+                throttle
+            ),
+            RemoteRequest::SetAlpha(alpha) => defmt::write!(f, "SetAlpha({})", alpha),
+            RemoteRequest::SetConfig(config) => defmt::write!(f, "SetConfig({})", config),
+            RemoteRequest::GetConfig => defmt::write!(f, "GetConfig"),
+            RemoteRequest::SaveConfig => defmt::write!(f, "SaveConfig"),
+            RemoteRequest::CalibrateGyro => defmt::write!(f, "CalibrateGyro"),
+            RemoteRequest::Calibrate => defmt::write!(f, "Calibrate"),
+            RemoteRequest::DumpBlackbox => defmt::write!(f, "DumpBlackbox"),
+            RemoteRequest::EmergencyStop => defmt::write!(f, "EmergencyStop"),
+            RemoteRequest::Batch(requests) => {
+                defmt::write!(f, "Batch(len: {})", requests.len())
+            }
+            RemoteRequest::SetDryRun(dry_run) => defmt::write!(f, "SetDryRun({})", dry_run),
+            RemoteRequest::Sticks {
+                roll,
+                pitch,
+                yaw,
+                thrust,
+            } => defmt::write!(
+                f,
+                "Sticks {{ roll: {}, pitch: {}, yaw: {}, thrust: {} }}",
+                roll,
+                pitch,
+                yaw,
+                thrust
+            ),
+            RemoteRequest::DumpImu => defmt::write!(f, "DumpImu"),
+            RemoteRequest::GetVersion => defmt::write!(f, "GetVersion"),
+            RemoteRequest::SelfTest { link_ms } => {
+                defmt::write!(f, "SelfTest {{ link_ms: {} }}", link_ms)
+            }
+            RemoteRequest::SetControlMode(mode) => defmt::write!(f, "SetControlMode({})", mode),
+        }
+    }
+}
+
+/// Messages acted on even when they look like duplicates, see [`SeqFilter`]
+pub trait Urgent {
+    fn is_urgent(&self) -> bool;
+}
+
+impl Urgent for RemoteRequest {
+    fn is_urgent(&self) -> bool {
+        matches!(self, RemoteRequest::EmergencyStop)
+    }
+}
+
+/// Sensor offsets subtracted from the raw IMU readings
+#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Clone, Copy)]
+pub struct ImuOffsets {
+    /// Gyro bias in deg/s
+    pub gyro: [f32; 3],
+    /// Accelerometer offset from a level reading in mg
+    pub accel: [f32; 3],
+}
+
+/// Complete set of runtime tunable control parameters
+#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Clone, Copy)]
+pub struct ControlConfig {
+    pub kp: [f32; 3],
+    pub ki: [f32; 3],
+    pub kd: [f32; 3],
+    /// Gyro weight of the complementary filter in 0.0..=1.0
+    pub alpha: f32,
+    /// Accelerometer low-pass smoothing factor, 1.0 disables filtering
+    pub accel_lowpass: f32,
+    /// Minimum motor throttle while armed
+    pub idle_thrust: f32,
+    /// Thrust curve around mid throttle, 0.0 is linear and 1.0 fully cubic
+    pub thrust_expo: f32,
+    /// Time without arm confirmation after which the drone disarms
+    pub arm_timeout_ms: u32,
+    /// Time after boot in which the motors stay off while the filter settles
+    pub settle_ms: u32,
+    /// Roll or pitch in degrees beyond which the drone disarms instead of fighting a flip, only
+    /// in [`ControlMode::Angle`]
+    pub max_tilt_deg: f32,
+    /// Frequency of the control update, independent of the IMU data rate
+    pub control_rate_hz: u32,
+    /// Motor throttles closer to zero than this snap to either off or this minimum spin
+    pub throttle_deadband: f32,
+    /// Mixer motor driven by each ESC output, see [`ControlConfig::motor_outputs_valid`]
+    pub motor_outputs: [MotorOutput; 4],
+}
+
+impl ControlConfig {
+    /// Whether every mixer motor is driven by exactly one ESC output
+    pub fn motor_outputs_valid(&self) -> bool {
+        let mut driven = [false; 4];
+        self.motor_outputs.iter().all(|output| {
+            driven
+                .get_mut(output.mixer_index as usize)
+                .is_some_and(|driven| !core::mem::replace(driven, true))
+        })
+    }
+}
+
+/// Assignment of a mixer motor to an ESC output
+#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Eq, Clone, Copy)]
+pub struct MotorOutput {
+    /// Mixer motor in 0..4 whose throttle is sent on this output
+    pub mixer_index: u8,
+    /// Inverts the throttle around stop, for motors wired to spin the wrong way
+    pub reversed: bool,
 }
 
 #[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Eq, Clone, Copy)]
@@ -31,6 +309,8 @@ pub enum PingTarget {
 }
 pub type PingId = u32;
 
+/// Encoded by variant position, so new variants must only ever be appended, see
+/// [`DroneResponse::wire_tag`]
 #[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq)]
 #[non_exhaustive]
 pub enum DroneResponse {
@@ -38,6 +318,200 @@ pub enum DroneResponse {
     ArmState(bool),
     Telemetry(Telemetry),
     Log(Box<[u8]>),
+    /// Lightweight numeric log event, decodable without the defmt table
+    Event {
+        level: LogLevel,
+        code: u16,
+        args: [f32; 2],
+    },
+    /// Active control configuration
+    Config(ControlConfig),
+    /// Whether [`RemoteRequest::SaveConfig`] succeeded
+    ConfigSaved(bool),
+    /// Fatal error, sent right before the drone resets
+    Error {
+        code: u16,
+        /// Zero padded utf-8 description, see [`DroneResponse::error_detail`]
+        detail: [u8; 16],
+    },
+    /// Offsets measured by [`RemoteRequest::Calibrate`], `None` if the calibration was refused
+    CalibrationResult(Option<ImuOffsets>),
+    /// Unsolicited liveness signal, sent every [`HeartbeatTimer::INTERVAL_MS`]
+    Heartbeat {
+        uptime_ms: u32,
+    },
+    /// Recorded control loop frame in response to [`RemoteRequest::DumpBlackbox`], oldest first
+    BlackboxFrame {
+        /// Frames still to follow, 0 for the last frame of a dump
+        remaining: u16,
+        frame: BlackboxFrame,
+    },
+    /// Motor throttles in ESC output order, see [`ControlConfig::motor_outputs`], as their
+    /// distance from stop normalized to 0.0..=1.0. Reversed motors are positive as well.
+    MotorsState([f32; 4]),
+    /// Build identity of the drone firmware, all zeros if built outside of git, and its
+    /// [`SCHEMA_VERSION`]
+    Version {
+        git_hash: GitHash,
+        schema: u8,
+    },
+    /// Outcome of [`RemoteRequest::SelfTest`], see [`SelfTestChecks`]
+    SelfTestResult {
+        imu_ok: bool,
+        motors_ok: bool,
+        /// Round trip the test was requested with
+        link_ms: Option<u16>,
+    },
+}
+
+/// Snapshot of a single control loop iteration
+#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Clone, Copy)]
+pub struct BlackboxFrame {
+    /// Time since boot in microseconds
+    pub timestamp: u64,
+    pub orientation: [f32; 3],
+    pub target: [f32; 3],
+    pub throttles: [u16; 4],
+    pub armed: bool,
+}
+
+impl BlackboxFrame {
+    pub const EMPTY: Self = Self {
+        timestamp: 0,
+        orientation: [0.0; 3],
+        target: [0.0; 3],
+        throttles: [0; 4],
+        armed: false,
+    };
+}
+
+/// Maximum payload of a single esp-now packet
+pub const ESP_NOW_MTU: usize = 250;
+
+/// Wi-Fi channel used for esp-now.
+///
+/// Relay and drone only hear each other on the same channel, so both should use
+/// [`EspNowChannel::DEFAULT`] unless changed together.
+#[derive(Debug, Format, PartialEq, Eq, Clone, Copy)]
+pub struct EspNowChannel(u8);
+
+impl EspNowChannel {
+    pub const DEFAULT: Self = Self(11);
+
+    /// Returns `None` outside of the legal 2.4GHz channels 1..=13
+    pub const fn new(channel: u8) -> Option<Self> {
+        match channel {
+            1..=13 => Some(Self(channel)),
+            _ => None,
+        }
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for EspNowChannel {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Urgent for DroneResponse {
+    fn is_urgent(&self) -> bool {
+        false
+    }
+}
+
+impl DroneResponse {
+    /// Tag of the variant on the wire, see [`RemoteRequest::wire_tag`]
+    pub const fn wire_tag(&self) -> u32 {
+        match self {
+            DroneResponse::Pong(..) => 0,
+            DroneResponse::ArmState(_) => 1,
+            DroneResponse::Telemetry(_) => 2,
+            DroneResponse::Log(_) => 3,
+            DroneResponse::Event { .. } => 4,
+            DroneResponse::Config(_) => 5,
+            DroneResponse::ConfigSaved(_) => 6,
+            DroneResponse::Error { .. } => 7,
+            DroneResponse::CalibrationResult(_) => 8,
+            DroneResponse::Heartbeat { .. } => 9,
+            DroneResponse::BlackboxFrame { .. } => 10,
+            DroneResponse::MotorsState(_) => 11,
+            DroneResponse::Version { .. } => 12,
+            DroneResponse::SelfTestResult { .. } => 13,
+        }
+    }
+
+    /// Slots of the outgoing response queue only taken by responses that are never dropped
+    pub const PRIORITY_RESERVE: usize = 16;
+
+    /// Whether the response may be dropped when the link can't keep up. Raw defmt data
+    /// can't be classified without the elf, it's treated as debug output.
+    pub fn is_droppable(&self) -> bool {
+        match self {
+            DroneResponse::Log(_) => true,
+            DroneResponse::Event { level, .. } => *level <= LogLevel::Debug,
+            _ => false,
+        }
+    }
+
+    /// Whether the response is queued given the free slots of the outgoing queue. Droppable
+    /// responses leave [`Self::PRIORITY_RESERVE`] slots for errors and warnings.
+    pub fn admit(&self, free_slots: usize) -> bool {
+        free_slots > 0 && (!self.is_droppable() || free_slots > Self::PRIORITY_RESERVE)
+    }
+
+    /// Wire overhead of a `Log` response: variant tag + slice length
+    const LOG_OVERHEAD: usize = size_of::<u32>() + size_of::<u64>();
+
+    /// Largest defmt payload that still fits a `Log` response into a single esp-now packet
+    pub const MAX_LOG_CHUNK: usize = MAX_FRAGMENT_PAYLOAD - Self::LOG_OVERHEAD;
+
+    /// Builds an `Error` response, truncating `detail` to fit
+    pub fn error(code: u16, detail: &str) -> Self {
+        let mut len = detail.len().min(16);
+        while !detail.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; 16];
+        bytes[..len].copy_from_slice(&detail.as_bytes()[..len]);
+        DroneResponse::Error {
+            code,
+            detail: bytes,
+        }
+    }
+
+    /// Builds a `MotorsState` response from ESC throttles, see [`map_motor_throttles`]
+    pub fn motors_state(throttles: [u16; 4]) -> Self {
+        DroneResponse::MotorsState(throttles.map(normalized_throttle))
+    }
+
+    /// Returns the description of an `Error` response
+    pub fn error_detail(detail: &[u8; 16]) -> &str {
+        let len = detail.iter().position(|&b| b == 0).unwrap_or(detail.len());
+        match core::str::from_utf8(&detail[..len]) {
+            Ok(detail) => detail,
+            Err(err) => core::str::from_utf8(&detail[..err.valid_up_to()]).unwrap(),
+        }
+    }
+
+    /// Splits defmt data into `Log` responses which each fit into a single esp-now packet.
+    /// As defmt data is a stream, the receiver reassembles it by decoding the chunks in order.
+    pub fn log_chunks(data: &[u8]) -> impl Iterator<Item = DroneResponse> + '_ {
+        data.chunks(Self::MAX_LOG_CHUNK)
+            .map(|chunk| DroneResponse::Log(Box::from(chunk)))
+    }
+}
+
+#[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
 }
 
 #[derive(Debug, Format, SchemaWrite, SchemaRead, PartialEq, Clone, Copy)]
@@ -48,13 +522,19 @@ pub struct Telemetry {
     pub armed: bool,
     pub output: [f32; 3],
     pub throttles: [u16; 4],
+    /// Signal strength of the last packet received from the relay in dBm
+    pub rssi: Option<i8>,
+    /// Whether the settle time after boot has passed and the controller drives the motors
+    pub live: bool,
+    /// Achieved control loop rate, see [`RateMonitor`]
+    pub loop_rate_hz: f32,
 }
 
 impl Display for Telemetry {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         writeln!(
             f,
-            "{{time: {}, ori: [x={:.3},y={:.3},z={:.3}], thrust: {}, armed: {}, out: {:?}, throttles: {:?}}}",
+            "{{time: {}, ori: [x={:.3},y={:.3},z={:.3}], thrust: {}, armed: {}, out: {:?}, throttles: {:?}, rssi: {:?}, live: {}, loop: {:.0}Hz}}",
             self.timestamp,
             self.orientation[0],
             self.orientation[1],
@@ -62,17 +542,43 @@ impl Display for Telemetry {
             self.thrust,
             self.armed,
             self.output,
-            self.throttles
+            self.throttles,
+            self.rssi,
+            self.live,
+            self.loop_rate_hz
         )
     }
 }
 
+/// Version of the message schema, prepended to every [`Frame`].
+///
+/// Bump whenever [`RemoteRequest`], [`DroneResponse`] or the framing change incompatibly.
+pub const SCHEMA_VERSION: u8 = 5;
+
 #[derive(Debug, Format, PartialEq, Eq)]
 pub enum FrameDecodeError {
+    /// Malformed escaping, failed [`crc16`] check or undecodable payload
     Corrupted,
     Incomplete,
+    /// Frame was encoded with a different [`SCHEMA_VERSION`]
+    VersionMismatch,
+    /// Message variant unknown to this firmware, e.g. sent by a newer peer
+    UnknownVariant,
 }
 
+/// Whether `payload` failed to deserialize with `err` only because its top level variant tag
+/// is unknown. Such messages come from newer firmware and should be ignored, not reported.
+pub fn is_unknown_variant(payload: &[u8], err: &wincode::ReadError) -> bool {
+    let Some(&tag) = payload.first_chunk::<4>() else {
+        return false;
+    };
+    matches!(err, wincode::ReadError::InvalidTagEncoding(invalid) if *invalid == u32::from_le_bytes(tag) as usize)
+}
+
+const FRAME_CRC_LEN: usize = size_of::<u16>();
+
+/// A message on the RTT link: [`SCHEMA_VERSION`], payload and a little endian [`crc16`] of
+/// both, escaped between a start and an end byte
 pub struct Frame<T: SchemaWrite<Src = T> + SchemaReadOwned<Dst = T>>(core::marker::PhantomData<T>);
 
 impl<T: SchemaWrite<Src = T> + SchemaReadOwned<Dst = T>> Frame<T> {
@@ -81,16 +587,42 @@ impl<T: SchemaWrite<Src = T> + SchemaReadOwned<Dst = T>> Frame<T> {
 
     pub fn encode(value: &T) -> wincode::WriteResult<Box<[u8]>> {
         let base_size = wincode::serialized_size(value)? as usize;
-        let mut encoded = Box::new_uninit_slice(base_size);
-        wincode::serialize_into(&mut &mut *encoded, &value)?;
-        let encoded = unsafe { encoded.assume_init() };
+        let mut encoded = Vec::with_capacity(1 + base_size + FRAME_CRC_LEN);
+        encoded.push(SCHEMA_VERSION);
+        wincode::serialize_into(&mut encoded, &value)?;
+        Self::append_crc(&mut encoded);
 
         Ok(Self::escaped(&encoded))
     }
 
     pub fn decode(data: &[u8]) -> Result<T, FrameDecodeError> {
         let mut unescaped = Self::unescaped(data)?;
-        Ok(wincode::deserialize_mut(&mut unescaped).map_err(|_| FrameDecodeError::Corrupted)?)
+        // checked first, other versions may be framed differently
+        if unescaped
+            .first()
+            .is_some_and(|&version| version != SCHEMA_VERSION)
+        {
+            return Err(FrameDecodeError::VersionMismatch);
+        }
+        let Some((checked, crc)) = unescaped.split_last_chunk_mut::<FRAME_CRC_LEN>() else {
+            return Err(FrameDecodeError::Corrupted);
+        };
+        if crc16(checked) != u16::from_le_bytes(*crc) {
+            return Err(FrameDecodeError::Corrupted);
+        }
+        let Some((_version, payload)) = checked.split_first_mut() else {
+            return Err(FrameDecodeError::Corrupted);
+        };
+        let tag = payload.first_chunk::<4>().copied();
+        wincode::deserialize_mut(payload).map_err(|err| match tag {
+            Some(tag) if is_unknown_variant(&tag, &err) => FrameDecodeError::UnknownVariant,
+            _ => FrameDecodeError::Corrupted,
+        })
+    }
+
+    fn append_crc(data: &mut Vec<u8>) {
+        let crc = crc16(data);
+        data.extend_from_slice(&crc.to_le_bytes());
     }
 
     fn escaped(data: &[u8]) -> Box<[u8]> {
@@ -146,9 +678,28 @@ impl<T: SchemaWrite<Src = T> + SchemaReadOwned<Dst = T>> Frame<T> {
     }
 }
 
+/// Alias of [`FrameDecodeError`] for use with [`decode`]
+pub type DecodeError = FrameDecodeError;
+
+/// Encodes a single message as a [`Frame`]
+pub fn encode<T: SchemaWrite<Src = T> + SchemaReadOwned<Dst = T>>(
+    value: &T,
+) -> wincode::WriteResult<Box<[u8]>> {
+    Frame::encode(value)
+}
+
+/// Decodes a single, complete [`Frame`]
+pub fn decode<T: SchemaWrite<Src = T> + SchemaReadOwned<Dst = T>>(
+    data: &[u8],
+) -> Result<T, DecodeError> {
+    Frame::decode(data)
+}
+
 pub struct FrameStreamDecoder<Msg> {
     buffer: [u8; 1024],
     len: usize,
+    version_mismatch: bool,
+    unknown_variant: bool,
     _msg: core::marker::PhantomData<Msg>,
 }
 
@@ -157,6 +708,8 @@ impl<Msg> Default for FrameStreamDecoder<Msg> {
         Self {
             buffer: [0; 1024],
             len: 0,
+            version_mismatch: false,
+            unknown_variant: false,
             _msg: core::marker::PhantomData,
         }
     }
@@ -169,6 +722,16 @@ impl<Msg> FrameStreamDecoder<Msg> {
         let read_len = f(&mut self.buffer[self.len..]);
         self.len += read_len;
     }
+
+    /// Whether frames with a different [`SCHEMA_VERSION`] were dropped since the last call
+    pub fn take_version_mismatch(&mut self) -> bool {
+        core::mem::take(&mut self.version_mismatch)
+    }
+
+    /// Whether frames with an unknown message variant were skipped since the last call
+    pub fn take_unknown_variant(&mut self) -> bool {
+        core::mem::take(&mut self.unknown_variant)
+    }
 }
 
 impl<Msg: SchemaWrite<Src = Msg> + SchemaReadOwned<Dst = Msg>> Iterator
@@ -198,19 +761,43 @@ impl<Msg: SchemaWrite<Src = Msg> + SchemaReadOwned<Dst = Msg>> Iterator
             };
             let frame_start = processed_up_to + start;
 
-            let Some(end) = self.buffer[frame_start..self.len]
-                .iter()
-                .position(|&b| b == 0xff)
-            else {
+            // An escaped end byte in the payload or crc looks like a frame end too,
+            // try each one until a frame decodes
+            let mut search_from = frame_start + 1;
+            let decoded = loop {
+                let Some(end) = self.buffer[search_from..self.len]
+                    .iter()
+                    .position(|&b| b == 0xff)
+                else {
+                    break None;
+                };
+                let frame_end = search_from + end;
+                let frame = &self.buffer[frame_start..=frame_end];
+
+                let result = Frame::<Msg>::decode(frame);
+                if matches!(result, Err(FrameDecodeError::Corrupted))
+                    && self.buffer[..self.len].get(frame_end + 1) == Some(&0xff)
+                    && resync_point(&self.buffer, frame_start, frame_end) > frame_end
+                {
+                    // Skip the escaped pair, unless an unescaped start shows the frame is cut off
+                    search_from = frame_end + 2;
+                    continue;
+                }
+                break Some((frame_end, result));
+            };
+
+            let Some((frame_end, result)) = decoded else {
+                if frame_start == 0 && self.len == self.buffer.len() {
+                    // Frame can't fit the buffer, drop its start to make room
+                    processed_up_to = 1;
+                    continue;
+                }
                 // Incomplete frame, wait for more data
                 processed_up_to = frame_start;
                 break None;
             };
 
-            let frame_end = frame_start + end;
-            let frame = &self.buffer[frame_start..=frame_end];
-
-            match Frame::<Msg>::decode(frame) {
+            match result {
                 Ok(msg) => {
                     // Move past current frame, stop decoding
                     processed_up_to = frame_end + 1;
@@ -218,10 +805,28 @@ impl<Msg: SchemaWrite<Src = Msg> + SchemaReadOwned<Dst = Msg>> Iterator
                 }
                 Err(FrameDecodeError::Incomplete) => {
                     // Incomplete frame, wait for more data
+                    processed_up_to = frame_start;
                     break None;
                 }
                 Err(FrameDecodeError::Corrupted) => {
-                    // Move past current frame, continue decoding
+                    let resync = resync_point(&self.buffer, frame_start, frame_end);
+                    if resync > frame_end
+                        && frame_end + 1 == self.len
+                        && self.len < self.buffer.len()
+                    {
+                        // The end may be the first half of an escaped pair, wait for the next byte
+                        processed_up_to = frame_start;
+                        break None;
+                    }
+                    // A truncated frame runs into the next one, continue at the next start
+                    processed_up_to = resync;
+                }
+                Err(FrameDecodeError::VersionMismatch) => {
+                    self.version_mismatch = true;
+                    processed_up_to = resync_point(&self.buffer, frame_start, frame_end);
+                }
+                Err(FrameDecodeError::UnknownVariant) => {
+                    self.unknown_variant = true;
                     processed_up_to = frame_end + 1;
                 }
             };
@@ -237,6 +842,20 @@ impl<Msg: SchemaWrite<Src = Msg> + SchemaReadOwned<Dst = Msg>> Iterator
     }
 }
 
+/// Position of the first frame start after `frame_start` that isn't half of an escaped pair,
+/// or the position after `frame_end` if there is none
+fn resync_point(buffer: &[u8], frame_start: usize, frame_end: usize) -> usize {
+    let mut i = frame_start + 1;
+    while i <= frame_end {
+        match buffer[i] {
+            byte @ (0x00 | 0xff) if i < frame_end && buffer[i + 1] == byte => i += 2,
+            0x00 => return i,
+            _ => i += 1,
+        }
+    }
+    frame_end + 1
+}
+
 #[test]
 fn encode_decode_roundtrip() {
     fn roundtrip<
@@ -256,11 +875,403 @@ fn encode_decode_roundtrip() {
         ki: [1.0, 2.0, 100e8],
         kd: [80.0, 0.5, -398.3],
     });
+    roundtrip(RemoteRequest::SetTelemetryInterval(100));
+    roundtrip(RemoteRequest::MotorTest {
+        index: 3,
+        throttle: 150,
+    });
+    roundtrip(RemoteRequest::SetAlpha(0.98));
+    let config = ControlConfig {
+        kp: [25.0, 25.0, 10.0],
+        ki: [0.0, 0.0, 0.5],
+        kd: [10.0, 10.0, 0.0],
+        alpha: 0.95,
+        accel_lowpass: 0.1,
+        idle_thrust: 70.0,
+        thrust_expo: 0.3,
+        arm_timeout_ms: 500,
+        settle_ms: 2000,
+        max_tilt_deg: 60.0,
+        control_rate_hz: 1600,
+        throttle_deadband: 30.0,
+        motor_outputs: [(1, false), (2, true), (3, false), (0, true)].map(
+            |(mixer_index, reversed)| MotorOutput {
+                mixer_index,
+                reversed,
+            },
+        ),
+    };
+    roundtrip(RemoteRequest::SetConfig(config));
+    roundtrip(RemoteRequest::GetConfig);
+    roundtrip(DroneResponse::Config(config));
+    roundtrip(RemoteRequest::SaveConfig);
+    roundtrip(RemoteRequest::CalibrateGyro);
+    roundtrip(RemoteRequest::Calibrate);
+    roundtrip(DroneResponse::CalibrationResult(Some(ImuOffsets {
+        gyro: [0.25, -1.5, 0.0],
+        accel: [12.0, -3.5, 20.0],
+    })));
+    roundtrip(DroneResponse::CalibrationResult(None));
+    roundtrip(DroneResponse::Heartbeat { uptime_ms: 123_456 });
+    roundtrip(RemoteRequest::DumpBlackbox);
+    roundtrip(RemoteRequest::EmergencyStop);
+    roundtrip(RemoteRequest::Batch(alloc::vec![
+        RemoteRequest::SetTune {
+            kp: [1.0; 3],
+            ki: [0.0; 3],
+            kd: [0.5; 3],
+        },
+        RemoteRequest::SetTarget([0.0, 5.0, 0.0]),
+        RemoteRequest::SetAlpha(0.97),
+    ]));
+    roundtrip(RemoteRequest::Batch(Vec::new()));
+    roundtrip(RemoteRequest::SetDryRun(true));
+    roundtrip(RemoteRequest::Sticks {
+        roll: -0.25,
+        pitch: 1.0,
+        yaw: 0.0,
+        thrust: -1.0,
+    });
+    roundtrip(RemoteRequest::DumpImu);
+    roundtrip(RemoteRequest::GetVersion);
+    roundtrip(RemoteRequest::SelfTest { link_ms: Some(12) });
+    roundtrip(RemoteRequest::SelfTest { link_ms: None });
+    roundtrip(RemoteRequest::SetControlMode(ControlMode::Rate));
+    roundtrip(DroneResponse::SelfTestResult {
+        imu_ok: true,
+        motors_ok: false,
+        link_ms: Some(250),
+    });
+    roundtrip(DroneResponse::Version {
+        git_hash: parse_git_hash("d4e84da0123456789abcdef0123456789abcdef0"),
+        schema: SCHEMA_VERSION,
+    });
+    roundtrip(DroneResponse::MotorsState([0.0, 0.25, 0.5, 1.0]));
+    roundtrip(DroneResponse::BlackboxFrame {
+        remaining: 3,
+        frame: BlackboxFrame {
+            timestamp: 1_234_567,
+            orientation: [1.0, -2.0, 90.0],
+            target: [0.0, 5.0, -10.0],
+            throttles: [1100, 1200, 1300, 1400],
+            armed: true,
+        },
+    });
+    roundtrip(DroneResponse::ConfigSaved(true));
 
     roundtrip(DroneResponse::Pong(PingTarget::Relay, 0xab));
     roundtrip(DroneResponse::ArmState(true));
     roundtrip(DroneResponse::ArmState(false));
     roundtrip(DroneResponse::Log(Box::from([0, 1, 2, 3])));
+    roundtrip(DroneResponse::Telemetry(Telemetry {
+        timestamp: 123_456,
+        orientation: [1.5, -2.0, 180.0],
+        thrust: 400.0,
+        armed: true,
+        output: [0.1, 0.2, -0.3],
+        throttles: [1000, 1250, 1500, 2000],
+        rssi: Some(-67),
+        live: true,
+        loop_rate_hz: 1598.5,
+    }));
+    roundtrip(DroneResponse::Event {
+        level: LogLevel::Warn,
+        code: 0xff00,
+        args: [-1.5, f32::INFINITY],
+    });
+    roundtrip(DroneResponse::Event {
+        level: LogLevel::Trace,
+        code: 0,
+        args: [0.0; 2],
+    });
+    roundtrip(DroneResponse::error(3, "Unable to verify chip"));
+}
+
+#[test]
+fn free_function_roundtrip() {
+    let req = RemoteRequest::SetThrust(420.0);
+    let encoded = encode(&req).unwrap();
+    assert_eq!(encoded, Frame::encode(&req).unwrap());
+    assert_eq!(decode(&encoded), Ok(req));
+
+    let res = DroneResponse::Log(Box::from([0x00, 0xff, 0x00]));
+    assert_eq!(decode(&encode(&res).unwrap()), Ok(res));
+
+    assert_eq!(
+        decode::<RemoteRequest>(&encoded[..encoded.len() - 1]),
+        Err(DecodeError::Incomplete)
+    );
+    assert_eq!(
+        decode::<RemoteRequest>(&encoded[1..]),
+        Err(DecodeError::Corrupted)
+    );
+}
+
+#[test]
+fn schema_version() {
+    let msg = RemoteRequest::SetArm(true);
+    let mut encoded = Frame::encode(&msg).unwrap();
+    assert_eq!(encoded[1], SCHEMA_VERSION);
+    assert_eq!(Frame::decode(&encoded), Ok(msg));
+
+    encoded[1] = SCHEMA_VERSION + 1;
+    assert_eq!(
+        Frame::<RemoteRequest>::decode(&encoded),
+        Err(FrameDecodeError::VersionMismatch)
+    );
+
+    let mut decoder = FrameStreamDecoder::<RemoteRequest>::default();
+    decoder.receive(|buffer| {
+        buffer[..encoded.len()].copy_from_slice(&encoded);
+        encoded.len()
+    });
+    assert_eq!(decoder.next(), None);
+    assert!(decoder.take_version_mismatch());
+    assert!(!decoder.take_version_mismatch());
+}
+
+#[test]
+fn motors_state_of_mapped_throttles() {
+    // front left, front right, back right, back left with outputs 1 and 3 reversed
+    let outputs =
+        [(1, false), (2, true), (3, false), (0, true)].map(|(mixer_index, reversed)| MotorOutput {
+            mixer_index,
+            reversed,
+        });
+    let throttles = map_motor_throttles([100.0, 200.0, 300.0, 400.0], &outputs);
+    assert_eq!(throttles, [1200, 700, 1400, 900]);
+    assert_eq!(
+        DroneResponse::motors_state(throttles),
+        DroneResponse::MotorsState(throttles.map(normalized_throttle))
+    );
+    assert_eq!(
+        DroneResponse::motors_state(throttles),
+        DroneResponse::MotorsState([0.2, 0.3, 0.4, 0.1])
+    );
+}
+
+#[test]
+fn motors_state_normalizes_throttles() {
+    assert_eq!(
+        DroneResponse::motors_state([1000, 1250, 1500, 2000]),
+        DroneResponse::MotorsState([0.0, 0.25, 0.5, 1.0])
+    );
+    // reversed motors by their distance from stop, out of range throttles are clamped
+    assert_eq!(
+        DroneResponse::motors_state([0, 999, 2001, u16::MAX]),
+        DroneResponse::MotorsState([1.0, 0.001, 1.0, 1.0])
+    );
+}
+
+#[test]
+fn wire_tags_are_pinned() {
+    fn encoded_tag<T: SchemaWrite<Src = T>>(msg: &T) -> u32 {
+        let bytes = wincode::serialize(msg).unwrap();
+        u32::from_le_bytes(*bytes.first_chunk::<4>().unwrap())
+    }
+
+    let config = ControlConfig {
+        kp: [0.0; 3],
+        ki: [0.0; 3],
+        kd: [0.0; 3],
+        alpha: 0.0,
+        accel_lowpass: 0.0,
+        idle_thrust: 0.0,
+        thrust_expo: 0.0,
+        arm_timeout_ms: 0,
+        settle_ms: 0,
+        max_tilt_deg: 0.0,
+        control_rate_hz: 0,
+        throttle_deadband: 0.0,
+        motor_outputs: [MotorOutput {
+            mixer_index: 0,
+            reversed: false,
+        }; 4],
+    };
+    let requests = [
+        RemoteRequest::Ping(PingTarget::Drone, 1),
+        RemoteRequest::SetArm(true),
+        RemoteRequest::ArmConfirm,
+        RemoteRequest::SetThrust(0.0),
+        RemoteRequest::SetTarget([0.0; 3]),
+        RemoteRequest::SetTune {
+            kp: [0.0; 3],
+            ki: [0.0; 3],
+            kd: [0.0; 3],
+        },
+        RemoteRequest::Reset,
+        RemoteRequest::SetTelemetryInterval(0),
+        RemoteRequest::MotorTest {
+            index: 0,
+            throttle: 0,
+        },
+        RemoteRequest::SetAlpha(0.0),
+        RemoteRequest::SetConfig(config),
+        RemoteRequest::GetConfig,
+        RemoteRequest::SaveConfig,
+        RemoteRequest::CalibrateGyro,
+        RemoteRequest::Calibrate,
+        RemoteRequest::DumpBlackbox,
+        RemoteRequest::EmergencyStop,
+        RemoteRequest::Batch(Vec::new()),
+        RemoteRequest::SetDryRun(false),
+        RemoteRequest::Sticks {
+            roll: 0.0,
+            pitch: 0.0,
+            yaw: 0.0,
+            thrust: 0.0,
+        },
+        RemoteRequest::DumpImu,
+        RemoteRequest::GetVersion,
+        RemoteRequest::SelfTest { link_ms: None },
+        RemoteRequest::SetControlMode(ControlMode::Angle),
+    ];
+    for (tag, request) in requests.iter().enumerate() {
+        assert_eq!(request.wire_tag(), tag as u32, "{request:?}");
+        assert_eq!(encoded_tag(request), tag as u32, "{request:?}");
+    }
+
+    let responses = [
+        DroneResponse::Pong(PingTarget::Drone, 1),
+        DroneResponse::ArmState(true),
+        DroneResponse::Telemetry(Telemetry {
+            timestamp: 0,
+            orientation: [0.0; 3],
+            thrust: 0.0,
+            armed: false,
+            output: [0.0; 3],
+            throttles: [0; 4],
+            rssi: None,
+            live: false,
+            loop_rate_hz: 0.0,
+        }),
+        DroneResponse::Log(Box::from([])),
+        DroneResponse::Event {
+            level: LogLevel::Info,
+            code: 0,
+            args: [0.0; 2],
+        },
+        DroneResponse::Config(config),
+        DroneResponse::ConfigSaved(true),
+        DroneResponse::error(0, ""),
+        DroneResponse::CalibrationResult(None),
+        DroneResponse::Heartbeat { uptime_ms: 0 },
+        DroneResponse::BlackboxFrame {
+            remaining: 0,
+            frame: BlackboxFrame::EMPTY,
+        },
+        DroneResponse::MotorsState([0.0; 4]),
+        DroneResponse::Version {
+            git_hash: [0; 20],
+            schema: 0,
+        },
+        DroneResponse::SelfTestResult {
+            imu_ok: false,
+            motors_ok: false,
+            link_ms: None,
+        },
+    ];
+    for (tag, response) in responses.iter().enumerate() {
+        assert_eq!(response.wire_tag(), tag as u32, "{response:?}");
+        assert_eq!(encoded_tag(response), tag as u32, "{response:?}");
+    }
+}
+
+#[test]
+fn motor_outputs_validation() {
+    let outputs = |indices: [u8; 4]| ControlConfig {
+        kp: [0.0; 3],
+        ki: [0.0; 3],
+        kd: [0.0; 3],
+        alpha: 0.0,
+        accel_lowpass: 0.0,
+        idle_thrust: 0.0,
+        thrust_expo: 0.0,
+        arm_timeout_ms: 0,
+        settle_ms: 0,
+        max_tilt_deg: 0.0,
+        control_rate_hz: 0,
+        throttle_deadband: 0.0,
+        motor_outputs: indices.map(|mixer_index| MotorOutput {
+            mixer_index,
+            reversed: mixer_index % 2 == 0,
+        }),
+    };
+    assert!(outputs([0, 1, 2, 3]).motor_outputs_valid());
+    assert!(outputs([1, 2, 3, 0]).motor_outputs_valid());
+    // a motor driven twice leaves another one undriven
+    assert!(!outputs([1, 2, 1, 0]).motor_outputs_valid());
+    assert!(!outputs([0, 1, 2, 4]).motor_outputs_valid());
+}
+
+#[test]
+fn only_emergency_stop_is_urgent() {
+    assert!(RemoteRequest::EmergencyStop.is_urgent());
+    assert!(!RemoteRequest::SetArm(false).is_urgent());
+    assert!(!RemoteRequest::Reset.is_urgent());
+    assert!(!DroneResponse::ArmState(false).is_urgent());
+}
+
+#[test]
+fn batch_validation() {
+    let tune = || RemoteRequest::SetAlpha(0.9);
+    assert!(RemoteRequest::is_valid_batch(&[]));
+    assert!(RemoteRequest::is_valid_batch(&[
+        tune(),
+        RemoteRequest::SetThrust(0.0)
+    ]));
+    assert!(RemoteRequest::is_valid_batch(
+        &[(); RemoteRequest::MAX_BATCH_LEN].map(|()| tune())
+    ));
+    assert!(!RemoteRequest::is_valid_batch(
+        &[(); RemoteRequest::MAX_BATCH_LEN + 1].map(|()| tune())
+    ));
+    assert!(!RemoteRequest::is_valid_batch(&[
+        tune(),
+        RemoteRequest::Batch(alloc::vec![tune()])
+    ]));
+    assert!(!RemoteRequest::is_valid_batch(&[
+        RemoteRequest::EmergencyStop
+    ]));
+}
+
+#[test]
+fn unknown_variant_ignored() {
+    // A variant appended by newer firmware
+    let mut future = alloc::vec![SCHEMA_VERSION];
+    future.extend_from_slice(&1000u32.to_le_bytes());
+    future.extend_from_slice(&[1, 2, 3]);
+    Frame::<DroneResponse>::append_crc(&mut future);
+    let future = Frame::<DroneResponse>::escaped(&future);
+    assert_eq!(
+        Frame::<DroneResponse>::decode(&future),
+        Err(FrameDecodeError::UnknownVariant)
+    );
+
+    // An invalid tag of a nested enum is still corruption
+    let mut nested = alloc::vec![SCHEMA_VERSION];
+    nested.extend_from_slice(&0u32.to_le_bytes()); // Pong
+    nested.extend_from_slice(&7u32.to_le_bytes()); // PingTarget
+    nested.extend_from_slice(&1u32.to_le_bytes());
+    Frame::<DroneResponse>::append_crc(&mut nested);
+    let nested = Frame::<DroneResponse>::escaped(&nested);
+    assert_eq!(
+        Frame::<DroneResponse>::decode(&nested),
+        Err(FrameDecodeError::Corrupted)
+    );
+
+    let mut decoder = FrameStreamDecoder::<DroneResponse>::default();
+    let mut data = Vec::new();
+    data.extend_from_slice(&future);
+    data.extend_from_slice(&Frame::encode(&DroneResponse::ArmState(true)).unwrap());
+    decoder.receive(|buffer| {
+        buffer[..data.len()].copy_from_slice(&data);
+        data.len()
+    });
+    assert_eq!(decoder.next(), Some(DroneResponse::ArmState(true)));
+    assert_eq!(decoder.next(), None);
+    assert!(decoder.take_unknown_variant());
+    assert!(!decoder.take_version_mismatch());
 }
 
 #[test]
@@ -305,3 +1316,255 @@ fn stream_decode() {
         ]
     );
 }
+
+#[test]
+fn stream_drops_corrupted_frames() {
+    let good = Frame::encode(&RemoteRequest::SetThrust(420.0)).unwrap();
+    let mut bad = Frame::encode(&RemoteRequest::SetThrust(421.0)).unwrap();
+    // Flip a bit of the thrust's exponent, the payload still deserializes to 105.25
+    let pos = bad.iter().position(|&b| b == 0x43).unwrap();
+    bad[pos] ^= 0x01;
+    assert_eq!(
+        Frame::<RemoteRequest>::decode(&bad),
+        Err(FrameDecodeError::Corrupted)
+    );
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&good);
+    data.extend_from_slice(&bad);
+    data.extend_from_slice(&good);
+    let mut decoder = FrameStreamDecoder::<RemoteRequest>::default();
+    decoder.receive(|buffer| {
+        buffer[..data.len()].copy_from_slice(&data);
+        data.len()
+    });
+    assert_eq!(
+        decoder.collect::<Vec<_>>(),
+        [
+            RemoteRequest::SetThrust(420.0),
+            RemoteRequest::SetThrust(420.0)
+        ]
+    );
+}
+
+#[test]
+fn stream_keeps_frame_after_corrupted_frame() {
+    let good = Frame::encode(&RemoteRequest::SetThrust(420.0)).unwrap();
+    let mut flipped = Frame::encode(&RemoteRequest::SetThrust(421.0))
+        .unwrap()
+        .into_vec();
+    let pos = flipped.iter().position(|&b| b == 0x43).unwrap();
+    flipped[pos] ^= 0x01;
+    let mut truncated = Frame::encode(&RemoteRequest::SetThrust(421.0))
+        .unwrap()
+        .into_vec();
+    truncated.truncate(truncated.len() - 3);
+
+    for bad in [flipped, truncated] {
+        // The valid frame is split across two receives at every position
+        for split in 1..good.len() {
+            let mut first = bad.clone();
+            first.extend_from_slice(&good[..split]);
+            let second = &good[split..];
+
+            let mut decoder = FrameStreamDecoder::<RemoteRequest>::default();
+            let mut decoded = Vec::new();
+            for data in [&first[..], second] {
+                decoder.receive(|buffer| {
+                    buffer[..data.len()].copy_from_slice(data);
+                    data.len()
+                });
+                decoded.extend(decoder.by_ref());
+            }
+            assert_eq!(
+                decoded,
+                [RemoteRequest::SetThrust(420.0)],
+                "split at {split}"
+            );
+        }
+    }
+}
+
+#[test]
+fn stream_decodes_escaped_end_bytes() {
+    let msg = || DroneResponse::Log(Box::from([0xff, 1, 0xff, 0xff, 0x00]));
+    let mut data = Frame::encode(&msg()).unwrap().into_vec();
+    data.extend_from_slice(&Frame::encode(&DroneResponse::ArmState(true)).unwrap());
+
+    for split in 0..data.len() {
+        let mut decoder = FrameStreamDecoder::<DroneResponse>::default();
+        let mut decoded = Vec::new();
+        for chunk in [&data[..split], &data[split..]] {
+            decoder.receive(|buffer| {
+                buffer[..chunk.len()].copy_from_slice(chunk);
+                chunk.len()
+            });
+            decoded.extend(decoder.by_ref());
+        }
+        assert_eq!(
+            decoded,
+            [msg(), DroneResponse::ArmState(true)],
+            "split at {split}"
+        );
+    }
+}
+
+#[test]
+fn error_detail() {
+    let DroneResponse::Error { code, detail } = DroneResponse::error(7, "Self-Test failed") else {
+        panic!("expected error");
+    };
+    assert_eq!(code, 7);
+    assert_eq!(DroneResponse::error_detail(&detail), "Self-Test failed");
+
+    // truncated on a char boundary
+    let DroneResponse::Error { detail, .. } = DroneResponse::error(1, "spi error: äöüßäöü")
+    else {
+        panic!("expected error");
+    };
+    assert_eq!(DroneResponse::error_detail(&detail), "spi error: äö");
+
+    let DroneResponse::Error { detail, .. } = DroneResponse::error(1, "") else {
+        panic!("expected error");
+    };
+    assert_eq!(DroneResponse::error_detail(&detail), "");
+}
+
+#[test]
+fn esp_now_channel_validation() {
+    assert_eq!(EspNowChannel::new(0), None);
+    assert_eq!(EspNowChannel::new(1).map(EspNowChannel::get), Some(1));
+    assert_eq!(EspNowChannel::new(13).map(EspNowChannel::get), Some(13));
+    assert_eq!(EspNowChannel::new(14), None);
+    assert_eq!(EspNowChannel::default().get(), 11);
+}
+
+#[test]
+fn log_chunks_fit_mtu() {
+    let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    assert!(
+        wincode::serialized_size(&DroneResponse::Log(data.clone().into())).unwrap() as usize
+            > ESP_NOW_MTU
+    );
+
+    let mut reassembled = Vec::new();
+    for res in DroneResponse::log_chunks(&data) {
+        let bytes = wincode::serialize(&res).unwrap();
+        assert!(bytes.len() <= MAX_FRAGMENT_PAYLOAD);
+
+        let Ok(DroneResponse::Log(chunk)) = wincode::deserialize(&bytes) else {
+            panic!("expected log chunk");
+        };
+        reassembled.extend_from_slice(&chunk);
+    }
+    assert_eq!(reassembled, data);
+
+    let full_chunk = DroneResponse::log_chunks(&data).next().unwrap();
+    assert_eq!(
+        wincode::serialized_size(&full_chunk).unwrap() as usize,
+        MAX_FRAGMENT_PAYLOAD
+    );
+}
+
+#[test]
+fn stream_decode_random() {
+    fn is_subsequence(needle: &[u8], haystack: &[u8]) -> bool {
+        let mut haystack = haystack.iter();
+        needle.iter().all(|b| haystack.any(|h| h == b))
+    }
+
+    fn check(stream: &[u8], chunk_len: usize) -> usize {
+        let mut decoder = FrameStreamDecoder::<DroneResponse>::default();
+        let mut decoded = 0;
+        for chunk in stream.chunks(chunk_len) {
+            decoder.receive(|buffer| {
+                let len = chunk.len().min(buffer.len());
+                buffer[..len].copy_from_slice(&chunk[..len]);
+                len
+            });
+            for msg in decoder.by_ref() {
+                let encoded = Frame::encode(&msg).unwrap();
+                assert!(is_subsequence(&encoded, stream), "{msg:?}");
+                decoded += 1;
+            }
+        }
+        decoded
+    }
+
+    // xorshift, deterministic so failures are reproducible
+    let mut state = 0x2545_f491_u32;
+    let mut random = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    let valid = Frame::encode(&DroneResponse::ArmState(true)).unwrap();
+    for _ in 0..1000 {
+        let mut stream = Vec::new();
+        for _ in 0..random() % 64 {
+            match random() % 4 {
+                // Bias towards frame delimiters to hit the interesting cases
+                0 => stream.push([0x00, 0xff][random() as usize % 2]),
+                1 => stream.extend_from_slice(&valid),
+                _ => stream.push(random() as u8),
+            }
+        }
+        check(&stream, 1 + random() as usize % 16);
+    }
+
+    // Regression: stream cut between the two bytes of an escaped 0x00,
+    // the second half is taken as a frame start
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&Frame::encode(&DroneResponse::Log(Box::from([1, 0, 2, 3]))).unwrap());
+    stream.extend_from_slice(&valid);
+    let split = stream.windows(2).position(|w| w == [0x00, 0x00]).unwrap() + 1;
+    assert_eq!(
+        check(&stream[split..], 1),
+        check(&stream[split..], stream.len())
+    );
+    assert!(check(&stream[split..], 3) >= 1);
+}
+
+#[test]
+fn backpressure_keeps_errors_and_warnings() {
+    const CAPACITY: usize = DroneResponse::PRIORITY_RESERVE + 8;
+    let event = |level| DroneResponse::Event {
+        level,
+        code: 0,
+        args: [0.0; 2],
+    };
+    let levels = [
+        LogLevel::Trace,
+        LogLevel::Debug,
+        LogLevel::Info,
+        LogLevel::Warn,
+        LogLevel::Error,
+    ];
+
+    // Nothing is drained, so the mixed stream exceeds the queue capacity
+    let mut queue = Vec::new();
+    for i in 0..100 {
+        let res = match i % 6 {
+            5 => DroneResponse::Log(Box::from([0; 4])),
+            n => event(levels[n]),
+        };
+        if res.admit(CAPACITY - queue.len()) {
+            queue.push(res);
+        }
+    }
+
+    assert_eq!(queue.len(), CAPACITY);
+    let count = |level| queue.iter().filter(|res| **res == event(level)).count();
+    // Once the reserve is reached only Info, Warn and Error get in
+    assert!(count(LogLevel::Error) > count(LogLevel::Debug));
+    assert!(count(LogLevel::Warn) > count(LogLevel::Trace));
+    assert!(
+        queue[CAPACITY - DroneResponse::PRIORITY_RESERVE..]
+            .iter()
+            .all(|res| !res.is_droppable())
+    );
+    assert!(!DroneResponse::error(1, "x").is_droppable());
+    assert!(!event(LogLevel::Error).admit(0));
+}
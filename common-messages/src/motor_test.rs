@@ -0,0 +1,46 @@
+use defmt::Format;
+
+/// Highest throttle a [`crate::RemoteRequest::MotorTest`] spins a motor with
+pub const MOTOR_TEST_MAX_THROTTLE: u16 = 1000;
+
+/// Why a [`crate::RemoteRequest::MotorTest`] was rejected
+#[derive(Debug, Format, PartialEq, Eq)]
+pub enum MotorTestRejected {
+    /// Only a disarmed drone spins single motors
+    Armed,
+    /// Index past the four motors
+    NoMotor(u8),
+}
+
+/// Checks a [`crate::RemoteRequest::MotorTest`], returns the motor index in mixer order and the
+/// thrust in 0.0..=1000.0 to spin it with
+pub fn motor_test(
+    index: u8,
+    throttle: u16,
+    armed: bool,
+) -> Result<(usize, f32), MotorTestRejected> {
+    if armed {
+        Err(MotorTestRejected::Armed)
+    } else if index >= 4 {
+        Err(MotorTestRejected::NoMotor(index))
+    } else {
+        Ok((index as usize, throttle.min(MOTOR_TEST_MAX_THROTTLE) as f32))
+    }
+}
+
+#[test]
+fn motor_test_checks() {
+    assert_eq!(motor_test(0, 150, false), Ok((0, 150.0)));
+    assert_eq!(motor_test(3, 150, false), Ok((3, 150.0)));
+    assert_eq!(
+        motor_test(4, 150, false),
+        Err(MotorTestRejected::NoMotor(4))
+    );
+    assert_eq!(
+        motor_test(u8::MAX, 150, false),
+        Err(MotorTestRejected::NoMotor(u8::MAX))
+    );
+    assert_eq!(motor_test(0, 150, true), Err(MotorTestRejected::Armed));
+    assert_eq!(motor_test(7, 150, true), Err(MotorTestRejected::Armed));
+    assert_eq!(motor_test(1, u16::MAX, false), Ok((1, 1000.0)));
+}
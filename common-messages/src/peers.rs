@@ -0,0 +1,144 @@
+use defmt::Format;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub struct Peer {
+    pub address: [u8; 6],
+    /// Time of the last received packet in milliseconds
+    pub last_seen_ms: u64,
+    /// Signal strength of the last received packet in dBm
+    pub rssi: i32,
+}
+
+impl Peer {
+    /// Signal strength for [`crate::Telemetry::rssi`], saturated to the range radios report
+    pub fn telemetry_rssi(&self) -> i8 {
+        self.rssi.clamp(i8::MIN.into(), 0) as i8
+    }
+}
+
+/// Fixed capacity table of recently heard esp-now peers
+pub struct PeerTable<const N: usize> {
+    peers: [Option<Peer>; N],
+}
+
+impl<const N: usize> PeerTable<N> {
+    pub const fn new() -> Self {
+        Self { peers: [None; N] }
+    }
+
+    /// Records a packet from `address`, replacing the least recently seen peer when full
+    pub fn update(&mut self, address: [u8; 6], rssi: i32, now_ms: u64) {
+        let peer = Peer {
+            address,
+            last_seen_ms: now_ms,
+            rssi,
+        };
+
+        let slot = self
+            .peers
+            .iter()
+            .position(|p| p.is_some_and(|p| p.address == address))
+            .or_else(|| self.peers.iter().position(Option::is_none))
+            .or_else(|| (0..N).min_by_key(|&i| self.peers[i].map(|p| p.last_seen_ms)));
+        if let Some(slot) = slot {
+            self.peers[slot] = Some(peer);
+        }
+    }
+
+    /// Removes peers not heard from within `timeout_ms`
+    pub fn evict(&mut self, now_ms: u64, timeout_ms: u64) {
+        for slot in &mut self.peers {
+            if slot.is_some_and(|p| now_ms.saturating_sub(p.last_seen_ms) > timeout_ms) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Returns the peer heard from most recently
+    pub fn latest(&self) -> Option<&Peer> {
+        self.iter().max_by_key(|p| p.last_seen_ms)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Peer> {
+        self.peers.iter().flatten()
+    }
+}
+
+impl<const N: usize> Default for PeerTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn peer_table_bookkeeping() {
+    use alloc::vec::Vec;
+
+    let addresses = |table: &PeerTable<2>| table.iter().map(|p| p.address[0]).collect::<Vec<_>>();
+    let mut table = PeerTable::<2>::new();
+    assert!(table.latest().is_none());
+
+    // insertion and update in place
+    table.update([1; 6], -40, 0);
+    table.update([2; 6], -60, 10);
+    table.update([1; 6], -45, 20);
+    assert_eq!(addresses(&table), [1, 2]);
+    assert_eq!(
+        table.latest(),
+        Some(&Peer {
+            address: [1; 6],
+            last_seen_ms: 20,
+            rssi: -45,
+        })
+    );
+
+    // a full table replaces the least recently seen peer
+    table.update([3; 6], -70, 30);
+    assert_eq!(addresses(&table), [1, 3]);
+
+    // eviction after the timeout
+    table.evict(120, 100);
+    assert_eq!(addresses(&table), [1, 3]);
+    table.evict(121, 100);
+    assert_eq!(addresses(&table), [3]);
+    table.evict(1_000, 100);
+    assert!(table.latest().is_none());
+}
+
+#[test]
+fn rssi_plumbing() {
+    /// Metadata of a received esp-now packet
+    struct RxInfo {
+        src_address: [u8; 6],
+        rssi: i32,
+    }
+    let received = [
+        RxInfo {
+            src_address: [1; 6],
+            rssi: -80,
+        },
+        RxInfo {
+            src_address: [2; 6],
+            rssi: -300,
+        },
+        RxInfo {
+            src_address: [1; 6],
+            rssi: -52,
+        },
+    ];
+
+    let mut table = PeerTable::<4>::new();
+    let telemetry_rssi = |table: &PeerTable<4>| table.latest().map(Peer::telemetry_rssi);
+    assert_eq!(telemetry_rssi(&table), None);
+
+    let mut rssi = alloc::vec::Vec::new();
+    for (now_ms, info) in received.iter().enumerate() {
+        table.update(info.src_address, info.rssi, now_ms as u64);
+        rssi.push(telemetry_rssi(&table));
+    }
+    assert_eq!(rssi, [Some(-80), Some(i8::MIN), Some(-52)]);
+
+    // positive readings are bogus
+    table.update([3; 6], 12, 10);
+    assert_eq!(telemetry_rssi(&table), Some(0));
+}
@@ -0,0 +1,55 @@
+/// Queues `msg` without waiting, evicting the oldest queued messages while the queue is full.
+///
+/// For latency sensitive messages on a bounded queue, a stale message is worth less than a
+/// stalled producer. Returns the last message dropped to make room, or `msg` itself if the
+/// queue has no capacity at all.
+pub fn send_drop_oldest<T>(
+    mut msg: T,
+    mut try_send: impl FnMut(T) -> Result<(), T>,
+    mut pop_oldest: impl FnMut() -> Option<T>,
+) -> Option<T> {
+    let mut dropped = None;
+    loop {
+        match try_send(msg) {
+            Ok(()) => return dropped,
+            Err(full) => msg = full,
+        }
+        match pop_oldest() {
+            Some(oldest) => dropped = Some(oldest),
+            None => return Some(msg),
+        }
+    }
+}
+
+#[test]
+fn drop_oldest_when_full() {
+    use alloc::collections::VecDeque;
+    use core::cell::RefCell;
+
+    // shared like a channel, sending and receiving both work through `&`
+    const CAPACITY: usize = 4;
+    let queue = RefCell::new(VecDeque::new());
+    let send = |msg| {
+        send_drop_oldest(
+            msg,
+            |msg| {
+                let mut queue = queue.borrow_mut();
+                if queue.len() < CAPACITY {
+                    queue.push_back(msg);
+                    Ok(())
+                } else {
+                    Err(msg)
+                }
+            },
+            || queue.borrow_mut().pop_front(),
+        )
+    };
+
+    assert_eq!((1..=4).map(send).collect::<alloc::vec::Vec<_>>(), [None; 4]);
+    assert_eq!(send(5), Some(1));
+    assert_eq!(send(6), Some(2));
+    assert_eq!(*queue.borrow(), [3, 4, 5, 6]);
+
+    // nothing to evict
+    assert_eq!(send_drop_oldest(7, Err, || None), Some(7));
+}
@@ -0,0 +1,127 @@
+/// Measures the achieved rate of a loop, averaged over windows of a fixed duration
+pub struct RateMonitor {
+    window_us: u64,
+    start_us: Option<u64>,
+    iterations: u32,
+    rate_hz: f32,
+}
+
+impl RateMonitor {
+    pub const fn new(window_us: u64) -> Self {
+        Self {
+            window_us,
+            start_us: None,
+            iterations: 0,
+            rate_hz: 0.0,
+        }
+    }
+
+    /// Records an iteration at `timestamp_us`, returns the new rate once a window is complete
+    pub fn record(&mut self, timestamp_us: u64) -> Option<f32> {
+        let Some(start_us) = self.start_us else {
+            self.start_us = Some(timestamp_us);
+            return None;
+        };
+
+        self.iterations += 1;
+        let elapsed_us = timestamp_us.saturating_sub(start_us);
+        if elapsed_us < self.window_us.max(1) {
+            return None;
+        }
+
+        self.rate_hz = self.iterations as f32 * 1_000_000.0 / elapsed_us as f32;
+        self.start_us = Some(timestamp_us);
+        self.iterations = 0;
+        Some(self.rate_hz)
+    }
+
+    /// Rate of the last complete window, zero before the first one
+    pub fn rate_hz(&self) -> f32 {
+        self.rate_hz
+    }
+}
+
+/// Lets an event through at most once per interval, e.g. telemetry out of the control loop
+pub struct RateLimiter {
+    interval_us: u64,
+    next_us: u64,
+}
+
+impl RateLimiter {
+    pub const fn new(interval_us: u64) -> Self {
+        Self {
+            interval_us,
+            next_us: 0,
+        }
+    }
+
+    /// Changes the interval, the next event is let through right away
+    pub fn set_interval(&mut self, interval_us: u64) {
+        self.interval_us = interval_us;
+        self.next_us = 0;
+    }
+
+    /// Whether an event at `timestamp_us` is let through. The interval counts from the last
+    /// event let through, so a slow caller delays the following events instead of bursting.
+    pub fn ready(&mut self, timestamp_us: u64) -> bool {
+        if timestamp_us < self.next_us {
+            return false;
+        }
+        self.next_us = timestamp_us.saturating_add(self.interval_us);
+        true
+    }
+}
+
+#[test]
+fn loop_rate() {
+    let mut monitor = RateMonitor::new(100_000);
+    let rates = |monitor: &mut RateMonitor, timestamps: &mut dyn Iterator<Item = u64>| {
+        timestamps
+            .filter_map(|t| monitor.record(t))
+            .collect::<alloc::vec::Vec<_>>()
+    };
+
+    // 1kHz for 0.25s, the partial window isn't reported
+    assert_eq!(
+        rates(&mut monitor, &mut (0..250).map(|i| i * 1_000)),
+        [1000.0, 1000.0]
+    );
+    assert_eq!(monitor.rate_hz(), 1000.0);
+
+    // The window started at 200ms holds 50 iterations when the loop slows down to 400Hz
+    assert_eq!(
+        rates(&mut monitor, &mut (0..=100).map(|i| 250_000 + i * 2_500)),
+        [700.0, 400.0, 400.0]
+    );
+
+    // A stalled loop reports its single iteration
+    assert_eq!(monitor.record(10_000_000), Some(1e6 / 9_500_000.0));
+}
+
+#[test]
+fn rate_limited_events() {
+    let passed = |limiter: &mut RateLimiter, timestamps: &mut dyn Iterator<Item = u64>| {
+        timestamps
+            .filter(|&t| limiter.ready(t))
+            .collect::<alloc::vec::Vec<_>>()
+    };
+
+    // 250ms interval out of a 1kHz loop
+    let mut limiter = RateLimiter::new(250_000);
+    assert_eq!(
+        passed(&mut limiter, &mut (0..1_000).map(|i| i * 1_000)),
+        [0, 250_000, 500_000, 750_000]
+    );
+
+    // a new interval applies right away
+    limiter.set_interval(100_000);
+    assert_eq!(
+        passed(&mut limiter, &mut (1_000..1_250).map(|i| i * 1_000)),
+        [1_000_000, 1_100_000, 1_200_000]
+    );
+
+    // a stalled caller doesn't get a burst of events afterwards
+    assert!(limiter.ready(5_000_000));
+    assert!(!limiter.ready(5_001_000));
+    assert!(limiter.ready(5_100_000));
+}
@@ -0,0 +1,85 @@
+use alloc::vec::Vec;
+
+use crate::{DroneResponse, Frame, FrameStreamDecoder, PingTarget, RemoteRequest};
+
+/// One pass of the relay's framing path, free of any io so it can be tested on the host.
+///
+/// Decodes the remote requests `read` into `decoder`, answers pings addressed to the relay
+/// itself and encodes `responses` from the drone, passing each frame for the remote to
+/// `write`. Returns the requests to forward to the drone, in order.
+pub fn relay_step(
+    decoder: &mut FrameStreamDecoder<RemoteRequest>,
+    read: impl FnMut(&mut [u8]) -> usize,
+    responses: impl IntoIterator<Item = DroneResponse>,
+    mut write: impl FnMut(&[u8]),
+) -> Vec<RemoteRequest> {
+    decoder.receive(read);
+
+    let mut to_drone = Vec::new();
+    for req in &mut *decoder {
+        match req {
+            RemoteRequest::Ping(target @ PingTarget::Relay, ping_id) => {
+                write(&Frame::encode(&DroneResponse::Pong(target, ping_id)).unwrap());
+            }
+            req => to_drone.push(req),
+        }
+    }
+
+    for res in responses {
+        write(&Frame::encode(&res).unwrap());
+    }
+
+    to_drone
+}
+
+#[test]
+fn relay_step_forwards_in_order() {
+    use alloc::vec;
+
+    let frame = |req: &RemoteRequest| Frame::encode(req).unwrap().into_vec();
+    let mut stream = Vec::new();
+    stream.extend(frame(&RemoteRequest::SetArm(true)));
+    stream.extend(frame(&RemoteRequest::Ping(PingTarget::Relay, 7)));
+    // corrupted frame: invalid escape sequence
+    stream.extend([0x00, 0x01, 0x00, 0x02, 0xff]);
+    stream.extend(frame(&RemoteRequest::SetThrust(250.0)));
+    stream.extend(frame(&RemoteRequest::Ping(PingTarget::Drone, 8)));
+    let split = stream.len();
+    stream.extend(frame(&RemoteRequest::SetTarget([1.0, -2.0, 0.5])));
+
+    // The last frame is cut in half between two reads
+    let cut = split + 3;
+    let mut chunks = [&stream[..cut], &stream[cut..]].into_iter();
+
+    let mut decoder = FrameStreamDecoder::default();
+    let mut written = Vec::new();
+    let mut step = |responses: Vec<DroneResponse>| {
+        let chunk = chunks.next().unwrap();
+        relay_step(
+            &mut decoder,
+            |buffer| {
+                buffer[..chunk.len()].copy_from_slice(chunk);
+                chunk.len()
+            },
+            responses,
+            |frame| written.push(Frame::<DroneResponse>::decode(frame).unwrap()),
+        )
+    };
+
+    assert_eq!(
+        step(vec![DroneResponse::ArmState(true)]),
+        [
+            RemoteRequest::SetArm(true),
+            RemoteRequest::SetThrust(250.0),
+            RemoteRequest::Ping(PingTarget::Drone, 8),
+        ]
+    );
+    assert_eq!(step(vec![]), [RemoteRequest::SetTarget([1.0, -2.0, 0.5])]);
+    assert_eq!(
+        written,
+        [
+            DroneResponse::Pong(PingTarget::Relay, 7),
+            DroneResponse::ArmState(true),
+        ]
+    );
+}
@@ -0,0 +1,62 @@
+/// Bounded retries with exponential backoff, e.g. for sending a packet on a busy radio.
+///
+/// Copied to start over with the same policy, e.g. for every packet.
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    attempts_left: u32,
+    backoff_ms: u64,
+}
+
+impl Retry {
+    /// Allows `attempts` tries in total, the first retry waits `backoff_ms`
+    pub const fn new(attempts: u32, backoff_ms: u64) -> Self {
+        Self {
+            attempts_left: attempts,
+            backoff_ms,
+        }
+    }
+
+    /// Records a failed attempt, returns the delay in milliseconds before the next one or `None`
+    /// once all attempts are used up. The delay doubles on every further failure.
+    pub fn failed(&mut self) -> Option<u64> {
+        self.attempts_left = self.attempts_left.saturating_sub(1);
+        if self.attempts_left == 0 {
+            return None;
+        }
+        let delay_ms = self.backoff_ms;
+        self.backoff_ms = self.backoff_ms.saturating_mul(2);
+        Some(delay_ms)
+    }
+}
+
+#[test]
+fn retry_until_sent() {
+    use alloc::vec::Vec;
+
+    // sender failing `failures` times before it succeeds, returns the outcome, the number of
+    // sends and the delays waited in between
+    let send = |failures: u32| {
+        let mut retry = Retry::new(3, 2);
+        let mut sends = 0;
+        let mut delays = Vec::new();
+        loop {
+            sends += 1;
+            if sends > failures {
+                return (Ok(()), sends, delays);
+            }
+            match retry.failed() {
+                Some(delay_ms) => delays.push(delay_ms),
+                None => return (Err(()), sends, delays),
+            }
+        }
+    };
+
+    assert_eq!(send(0), (Ok(()), 1, Vec::new()));
+    assert_eq!(send(2), (Ok(()), 3, alloc::vec![2, 4]));
+    assert_eq!(send(3), (Err(()), 3, alloc::vec![2, 4]));
+    assert_eq!(send(u32::MAX), (Err(()), 3, alloc::vec![2, 4]));
+
+    // a single attempt never retries
+    assert_eq!(Retry::new(1, 2).failed(), None);
+    assert_eq!(Retry::new(0, 2).failed(), None);
+}
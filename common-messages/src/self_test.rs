@@ -0,0 +1,123 @@
+use crate::DroneResponse;
+
+/// Sub-checks of a [`crate::RemoteRequest::SelfTest`], fed by the control loop while it spins
+/// the motors one after another, aggregated into a [`DroneResponse::SelfTestResult`]
+pub struct SelfTestChecks {
+    link_ms: Option<u16>,
+    start_us: u64,
+    /// Timestamps of the first and the newest IMU sample during the test
+    imu_span: Option<(u64, u64)>,
+    imu_finite: bool,
+    /// Motors whose wiggle ran to the end, in mixer order
+    motors: [bool; 4],
+}
+
+impl SelfTestChecks {
+    /// Time each motor is spun for in microseconds
+    pub const WIGGLE_US: u64 = 400_000;
+    /// Throttle in 0.0..=1000.0 the motors are spun with, just above idle
+    pub const WIGGLE_THROTTLE: f32 = 80.0;
+    /// Share of the test duration the IMU sample timestamps have to cover, less means samples
+    /// stalled or got lost. Leaves room for the drift of the sensor clock.
+    pub const MIN_IMU_COVERAGE: f32 = 0.8;
+    /// Longest link round trip that passes
+    pub const MAX_LINK_MS: u16 = 100;
+
+    pub fn new(link_ms: Option<u16>, now_us: u64) -> Self {
+        Self {
+            link_ms,
+            start_us: now_us,
+            imu_span: None,
+            imu_finite: true,
+            motors: [false; 4],
+        }
+    }
+
+    /// Records an IMU sample taken during the test
+    pub fn record_sample(&mut self, timestamp_us: u64, finite: bool) {
+        self.imu_finite &= finite;
+        self.imu_span = Some(match self.imu_span {
+            Some((first, _)) => (first, timestamp_us),
+            None => (timestamp_us, timestamp_us),
+        });
+    }
+
+    /// Motor to spin at `now_us` in mixer order, `None` once all motors were spun. Motors
+    /// whose wiggle ran out by then pass.
+    pub fn wiggle(&mut self, now_us: u64) -> Option<usize> {
+        let index = (now_us.saturating_sub(self.start_us) / Self::WIGGLE_US) as usize;
+        for passed in self.motors.iter_mut().take(index) {
+            *passed = true;
+        }
+        (index < self.motors.len()).then_some(index)
+    }
+
+    /// Aggregated result at `now_us`. Ending the test early, e.g. because the drone was armed,
+    /// fails the motors that weren't spun to the end.
+    pub fn result(&self, now_us: u64) -> DroneResponse {
+        let elapsed_us = now_us.saturating_sub(self.start_us);
+        let imu_fresh = self.imu_span.is_some_and(|(first, newest)| {
+            elapsed_us > 0
+                && newest.saturating_sub(first) as f32 >= elapsed_us as f32 * Self::MIN_IMU_COVERAGE
+        });
+        DroneResponse::SelfTestResult {
+            imu_ok: imu_fresh && self.imu_finite,
+            motors_ok: self.motors.iter().all(|&passed| passed),
+            link_ms: self.link_ms,
+        }
+    }
+
+    /// Whether the link round trip of a [`DroneResponse::SelfTestResult`] passes
+    pub fn link_ok(link_ms: Option<u16>) -> bool {
+        link_ms.is_some_and(|ms| ms <= Self::MAX_LINK_MS)
+    }
+}
+
+#[test]
+fn self_test_aggregation() {
+    const END_US: u64 = 1_000 + 4 * SelfTestChecks::WIGGLE_US;
+
+    // runs the test from 1ms until `end_us`, with an IMU sample every millisecond
+    let run = |end_us: u64, sample: &dyn Fn(u64) -> Option<bool>| {
+        let mut checks = SelfTestChecks::new(Some(20), 1_000);
+        let mut wiggled = alloc::vec::Vec::new();
+        for now_us in (1_000..=end_us).step_by(1_000) {
+            if let Some(finite) = sample(now_us) {
+                // sensor clock with an offset to the system clock
+                checks.record_sample(now_us + 7_777, finite);
+            }
+            match checks.wiggle(now_us) {
+                Some(index) if wiggled.last() != Some(&index) => wiggled.push(index),
+                Some(_) => {}
+                None => break,
+            }
+        }
+        (checks.result(end_us), wiggled)
+    };
+    let result = |imu_ok, motors_ok| DroneResponse::SelfTestResult {
+        imu_ok,
+        motors_ok,
+        link_ms: Some(20),
+    };
+
+    let (passed, wiggled) = run(END_US, &|_| Some(true));
+    assert_eq!(passed, result(true, true));
+    assert_eq!(wiggled, [0, 1, 2, 3]);
+
+    // ended while the third motor was spinning
+    let (aborted, wiggled) = run(1_000 + 5 * SelfTestChecks::WIGGLE_US / 2, &|_| Some(true));
+    assert_eq!(aborted, result(true, false));
+    assert_eq!(wiggled, [0, 1, 2]);
+
+    // samples stop halfway through
+    let stalled = run(END_US, &|t| (t < END_US / 2).then_some(true)).0;
+    assert_eq!(stalled, result(false, true));
+    assert_eq!(run(END_US, &|_| None).0, result(false, true));
+    let non_finite = run(END_US, &|t| Some(t != 100_000)).0;
+    assert_eq!(non_finite, result(false, true));
+
+    assert!(SelfTestChecks::link_ok(Some(20)));
+    assert!(SelfTestChecks::link_ok(Some(SelfTestChecks::MAX_LINK_MS)));
+    assert!(!SelfTestChecks::link_ok(Some(500)));
+    assert!(!SelfTestChecks::link_ok(None));
+}
@@ -0,0 +1,832 @@
+#[cfg_attr(test, allow(unused_imports))]
+use m::Float;
+
+use crate::{ControlMode, ImuSample};
+
+type F = f32;
+
+const IMU_AXIS_MAP: [usize; 3] = [0, 1, 2];
+const IMU_AXIS_SCALE: [F; 3] = [-1.0, 1.0, 1.0];
+
+pub struct Pid {
+    // tune
+    pub k_p: F,
+    pub k_i: F,
+    pub k_d: F,
+
+    // state
+    pub last_input: F,
+    pub sum: F,
+}
+
+impl Pid {
+    fn advance(&mut self, error: F, saturated: bool) -> F {
+        if !saturated || (self.sum + error).abs() < self.sum {
+            self.sum += error;
+        }
+        let control = self.k_p * error + self.k_i * self.sum + self.k_d * (self.last_input - error);
+        self.last_input = error;
+
+        control
+    }
+
+    /// Changes the gains without a jump in the control output for the latest error.
+    ///
+    /// The integral sum is rescaled so proportional and integral term keep their combined
+    /// value, later updates follow the new gains. Without an integral gain the output can't be
+    /// kept continuous and the sum is cleared.
+    pub fn set_gains_bumpless(&mut self, k_p: F, k_i: F, k_d: F) {
+        let error = self.last_input;
+        let output = self.k_p * error + self.k_i * self.sum;
+        self.sum = if k_i != 0.0 {
+            (output - k_p * error) / k_i
+        } else {
+            0.0
+        };
+        (self.k_p, self.k_i, self.k_d) = (k_p, k_i, k_d);
+    }
+}
+
+/// Estimates the residual gyro bias by averaging over a window in which the drone is stationary.
+/// A level calibration additionally estimates the accelerometer offset.
+pub struct GyroBias {
+    bias: [F; 3],
+    accel_offset: [F; 3],
+    calibrating: bool,
+    level: bool,
+    samples: u32,
+    gyro_sum: [F; 3],
+    gyro_sum_sq: [F; 3],
+    accel_sum: [F; 3],
+    accel_sum_sq: [F; 3],
+}
+
+impl Default for GyroBias {
+    fn default() -> Self {
+        Self {
+            bias: [0.0; 3],
+            accel_offset: [0.0; 3],
+            calibrating: true,
+            level: false,
+            samples: 0,
+            gyro_sum: [0.0; 3],
+            gyro_sum_sq: [0.0; 3],
+            accel_sum: [0.0; 3],
+            accel_sum_sq: [0.0; 3],
+        }
+    }
+}
+
+impl GyroBias {
+    /// Number of samples averaged per calibration window
+    pub const WINDOW: u32 = 512;
+    /// Maximum gyro variance per axis in (deg/s)² to consider the drone stationary
+    const MAX_GYRO_VARIANCE: F = 0.5;
+    /// Maximum accel variance per axis in mg² to consider the drone stationary
+    const MAX_ACCEL_VARIANCE: F = 400.0;
+    /// Accelerometer reading in mg when level and stationary
+    const LEVEL_ACCEL: [F; 3] = [0.0, 0.0, 1000.0];
+
+    pub fn bias(&self) -> [F; 3] {
+        self.bias
+    }
+
+    pub fn accel_offset(&self) -> [F; 3] {
+        self.accel_offset
+    }
+
+    pub fn is_calibrating(&self) -> bool {
+        self.calibrating
+    }
+
+    /// Starts a new calibration, the current bias is kept until it completes
+    pub fn recalibrate(&mut self) {
+        self.level = false;
+        self.restart();
+    }
+
+    /// Starts a new calibration which also estimates the accelerometer offset, the drone has
+    /// to be level
+    pub fn recalibrate_level(&mut self) {
+        self.level = true;
+        self.restart();
+    }
+
+    fn restart(&mut self) {
+        *self = Self {
+            bias: self.bias,
+            accel_offset: self.accel_offset,
+            level: self.level,
+            ..Self::default()
+        };
+    }
+
+    /// Feeds a raw sample into a running calibration and returns the corrected gyro and accel
+    pub fn correct(&mut self, gyro: [F; 3], accel: [F; 3]) -> ([F; 3], [F; 3]) {
+        if self.calibrating {
+            self.accumulate(gyro, accel);
+        }
+        (
+            [0, 1, 2].map(|i| gyro[i] - self.bias[i]),
+            [0, 1, 2].map(|i| accel[i] - self.accel_offset[i]),
+        )
+    }
+
+    fn accumulate(&mut self, gyro: [F; 3], accel: [F; 3]) {
+        for i in 0..3 {
+            self.gyro_sum[i] += gyro[i];
+            self.gyro_sum_sq[i] += gyro[i] * gyro[i];
+            self.accel_sum[i] += accel[i];
+            self.accel_sum_sq[i] += accel[i] * accel[i];
+        }
+        self.samples += 1;
+        if self.samples < Self::WINDOW {
+            return;
+        }
+
+        let n = self.samples as F;
+        let variance = |sum: F, sum_sq: F| sum_sq / n - (sum / n) * (sum / n);
+        let stationary = (0..3).all(|i| {
+            variance(self.gyro_sum[i], self.gyro_sum_sq[i]) <= Self::MAX_GYRO_VARIANCE
+                && variance(self.accel_sum[i], self.accel_sum_sq[i]) <= Self::MAX_ACCEL_VARIANCE
+        });
+
+        if stationary {
+            self.bias = self.gyro_sum.map(|sum| sum / n);
+            if self.level {
+                self.accel_offset = [0, 1, 2].map(|i| self.accel_sum[i] / n - Self::LEVEL_ACCEL[i]);
+            }
+            self.calibrating = false;
+        } else {
+            // Moved during the window, try again with the next one
+            self.restart();
+        }
+    }
+}
+
+pub struct ComplementaryFilterFusion {
+    /// filter tune
+    /// alpha * gyro + (1-alpha) * accel
+    alpha: F,
+
+    /// accelerometer low-pass smoothing factor, 1.0 disables filtering
+    /// filtered = accel_lpf * accel + (1-accel_lpf) * filtered
+    accel_lpf: F,
+    accel_filtered: Option<[F; 3]>,
+
+    /// current roll, pitch and yaw estimates
+    orientation: [F; 3],
+
+    /// roll, pitch and yaw targets
+    target: [F; 3],
+    /// whether the roll and pitch targets are angles or rates
+    mode: ControlMode,
+
+    /// roll, pitch and yaw PID contorller
+    pub pid: [Pid; 3],
+
+    gyro_bias: GyroBias,
+
+    /// set when a non-finite value was rejected, see [`Self::take_non_finite`]
+    non_finite: bool,
+}
+
+/// Named alternative to the positional [`ComplementaryFilterFusion::new`].
+///
+/// Defaults to a level, untargeted filter with alpha 0.95, all PID gains zero and the
+/// accelerometer low-pass disabled.
+#[derive(Debug, Clone, Copy)]
+pub struct ComplementaryFilterFusionBuilder {
+    alpha: F,
+    orientation: [F; 3],
+    target: [F; 3],
+    k_p: [F; 3],
+    k_i: [F; 3],
+    k_d: [F; 3],
+    accel_lowpass: F,
+}
+
+impl Default for ComplementaryFilterFusionBuilder {
+    fn default() -> Self {
+        Self {
+            alpha: 0.95,
+            orientation: [0.0; 3],
+            target: [0.0; 3],
+            k_p: [0.0; 3],
+            k_i: [0.0; 3],
+            k_d: [0.0; 3],
+            accel_lowpass: 1.0,
+        }
+    }
+}
+
+impl ComplementaryFilterFusionBuilder {
+    /// Gyro weight in 0.0..=1.0
+    pub fn alpha(mut self, alpha: F) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Roll, pitch and yaw to start from
+    pub fn initial_orientation(mut self, orientation: [F; 3]) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// Roll, pitch and yaw rate targets
+    pub fn target(mut self, target: [F; 3]) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn kp(mut self, k_p: [F; 3]) -> Self {
+        self.k_p = k_p;
+        self
+    }
+
+    pub fn ki(mut self, k_i: [F; 3]) -> Self {
+        self.k_i = k_i;
+        self
+    }
+
+    pub fn kd(mut self, k_d: [F; 3]) -> Self {
+        self.k_d = k_d;
+        self
+    }
+
+    /// See [`ComplementaryFilterFusion::with_accel_lowpass`]
+    pub fn accel_lowpass(mut self, factor: F) -> Self {
+        self.accel_lowpass = factor;
+        self
+    }
+
+    pub fn build(self) -> ComplementaryFilterFusion {
+        ComplementaryFilterFusion::new(
+            self.alpha,
+            self.orientation,
+            self.target,
+            self.k_p,
+            self.k_i,
+            self.k_d,
+        )
+        .with_accel_lowpass(self.accel_lowpass)
+    }
+}
+
+impl ComplementaryFilterFusion {
+    pub fn builder() -> ComplementaryFilterFusionBuilder {
+        ComplementaryFilterFusionBuilder::default()
+    }
+
+    pub fn new(
+        alpha: F,
+        orientation: [F; 3],
+        target: [F; 3],
+        k_p: [F; 3],
+        k_i: [F; 3],
+        k_d: [F; 3],
+    ) -> Self {
+        Self {
+            alpha,
+            accel_lpf: 1.0,
+            accel_filtered: None,
+            orientation,
+            target,
+            mode: ControlMode::Angle,
+            pid: [
+                Pid {
+                    k_p: k_p[0],
+                    k_i: k_i[0],
+                    k_d: k_d[0],
+                    last_input: 0.0,
+                    sum: 0.0,
+                },
+                Pid {
+                    k_p: k_p[1],
+                    k_i: k_i[1],
+                    k_d: k_d[1],
+                    last_input: 0.0,
+                    sum: 0.0,
+                },
+                Pid {
+                    k_p: k_p[2],
+                    k_i: k_i[2],
+                    k_d: k_d[2],
+                    last_input: 0.0,
+                    sum: 0.0,
+                },
+            ],
+            gyro_bias: GyroBias::default(),
+            non_finite: false,
+        }
+    }
+}
+
+impl ComplementaryFilterFusion {
+    /// Enables a first order low-pass on the accelerometer input, `factor` in 0.0..=1.0
+    /// where smaller values filter more strongly
+    pub fn with_accel_lowpass(mut self, factor: F) -> Self {
+        self.accel_lpf = factor.clamp(0.0, 1.0);
+        self
+    }
+
+    /// See [`Self::with_accel_lowpass`]
+    pub fn set_accel_lowpass(&mut self, factor: F) {
+        self.accel_lpf = factor.clamp(0.0, 1.0);
+    }
+
+    fn filter_accel(&mut self, accel: [F; 3]) -> [F; 3] {
+        let filtered = match self.accel_filtered {
+            Some(prev) => {
+                [0, 1, 2].map(|i| self.accel_lpf * accel[i] + (1.0 - self.accel_lpf) * prev[i])
+            }
+            None => accel,
+        };
+        self.accel_filtered = Some(filtered);
+        filtered
+    }
+
+    /// Whether roll or pitch exceed `max_tilt` degrees in either direction
+    pub fn tilt_exceeds(&self, max_tilt: F) -> bool {
+        self.orientation[0].abs() > max_tilt || self.orientation[1].abs() > max_tilt
+    }
+
+    /// Whether the drone tilted beyond `max_tilt` degrees and should be disarmed. Never in
+    /// [`ControlMode::Rate`], where flips are intended.
+    pub fn tilt_cutoff(&self, max_tilt: F) -> bool {
+        self.mode == ControlMode::Angle && self.tilt_exceeds(max_tilt)
+    }
+
+    pub fn target(&self) -> [F; 3] {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: [F; 3]) {
+        self.target = target;
+    }
+
+    pub fn mode(&self) -> ControlMode {
+        self.mode
+    }
+
+    /// Switches how the targets are interpreted. The target is reset, as an angle it would
+    /// otherwise become a rate or vice versa, and so are the PID integrators.
+    pub fn set_mode(&mut self, mode: ControlMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.target = [0.0; 3];
+            self.pid.iter_mut().for_each(|pid| pid.sum = 0.0);
+        }
+    }
+
+    pub fn alpha(&self) -> F {
+        self.alpha
+    }
+
+    /// Sets the gyro weight of the filter, returns false and keeps the current alpha
+    /// if `alpha` is outside of `0.0..=1.0`
+    pub fn set_alpha(&mut self, alpha: F) -> bool {
+        if !(0.0..=1.0).contains(&alpha) {
+            return false;
+        }
+        self.alpha = alpha;
+        true
+    }
+
+    pub fn gyro_bias(&self) -> &GyroBias {
+        &self.gyro_bias
+    }
+
+    /// Re-estimates the gyro bias, the drone has to be kept still until it completes
+    pub fn calibrate_gyro(&mut self) {
+        self.gyro_bias.recalibrate();
+    }
+
+    /// Re-estimates gyro bias and accelerometer offset, the drone has to be still and level
+    pub fn calibrate(&mut self) {
+        self.gyro_bias.recalibrate_level();
+    }
+
+    /// Whether a non-finite input or state was rejected since the last call
+    pub fn take_non_finite(&mut self) -> bool {
+        core::mem::take(&mut self.non_finite)
+    }
+
+    /// Drops all accumulated state after it turned non-finite
+    fn reset_state(&mut self) {
+        self.orientation = [0.0; 3];
+        self.accel_filtered = None;
+        for pid in &mut self.pid {
+            pid.sum = 0.0;
+            pid.last_input = 0.0;
+        }
+        self.non_finite = true;
+    }
+
+    pub fn orientation(&mut self) -> [F; 3] {
+        self.orientation
+    }
+
+    /// Heading in radians from a magnetometer reading, tilt compensated with the
+    /// normalized gravity vector (pointing up)
+    fn tilt_compensated_heading(mag: [F; 3], up: [F; 3]) -> F {
+        fn cross(a: [F; 3], b: [F; 3]) -> [F; 3] {
+            [
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+            ]
+        }
+
+        let mag = [0, 1, 2].map(|i| IMU_AXIS_SCALE[i] * mag[IMU_AXIS_MAP[i]]);
+        let east = cross(mag, up);
+        let north = cross(up, east);
+        F::atan2(east[0], north[0])
+    }
+
+    /// Fuses a sample and returns the roll, pitch and yaw control outputs.
+    ///
+    /// Non-finite samples are skipped and non-finite results reset the filter and PID state,
+    /// both return a zero output.
+    pub fn advance(&mut self, sample: impl ImuSample, saturated: bool) -> [F; 3] {
+        let finite = |v: [F; 3]| v.iter().all(|x| x.is_finite());
+        if !finite(sample.gyro()) || !finite(sample.accel()) || !sample.dt().is_finite() {
+            self.non_finite = true;
+            return [0.0; 3];
+        }
+
+        let output = self.advance_finite(sample, saturated);
+        let pid_state = self.pid.each_ref().map(|pid| pid.sum + pid.last_input);
+        if !finite(output) || !finite(self.orientation) || !finite(pid_state) {
+            self.reset_state();
+            return [0.0; 3];
+        }
+        output
+    }
+
+    fn advance_finite(&mut self, sample: impl ImuSample, saturated: bool) -> [F; 3] {
+        let (gyro, accel) = self.gyro_bias.correct(sample.gyro(), sample.accel());
+        let rates = [0, 1, 2].map(|i| IMU_AXIS_SCALE[i] * gyro[IMU_AXIS_MAP[i]]);
+        let gyro_orientation = [0, 1, 2].map(|i| self.orientation[i] + rates[i] * sample.dt());
+
+        let accel = self.filter_accel(accel);
+        let gravity = [
+            IMU_AXIS_SCALE[0] * accel[IMU_AXIS_MAP[0]] * sample.dt(),
+            IMU_AXIS_SCALE[1] * accel[IMU_AXIS_MAP[1]] * sample.dt(),
+            IMU_AXIS_SCALE[2] * accel[IMU_AXIS_MAP[2]] * sample.dt(),
+        ];
+        let gravity_norm = gravity
+            .iter()
+            .map(|g| g * g)
+            .reduce(|a, b| a + b)
+            .unwrap()
+            .sqrt();
+        let ngravity = [
+            gravity[0] / gravity_norm,
+            gravity[1] / gravity_norm,
+            gravity[2] / gravity_norm,
+        ];
+
+        const RAD2DEG: F = 180.0 / core::f32::consts::PI;
+        let accel_orientation = [
+            -F::atan2(ngravity[1], ngravity[2]) * RAD2DEG,
+            -F::atan2(
+                -gravity[0],
+                (ngravity[1] * ngravity[1] + ngravity[2] * ngravity[2]).sqrt(),
+            ) * RAD2DEG,
+            0.0,
+        ];
+
+        self.orientation[0] =
+            self.alpha * gyro_orientation[0] + (1.0 - self.alpha) * accel_orientation[0];
+        self.orientation[1] =
+            self.alpha * gyro_orientation[1] + (1.0 - self.alpha) * accel_orientation[1];
+        // self.orientation[2] =
+        //     self.alpha * gyro_orientation[2] + (1.0 - self.alpha) * accel_orientation[2];
+        self.orientation[2] = match sample.mag() {
+            Some(mag) => {
+                let heading = Self::tilt_compensated_heading(mag, ngravity) * RAD2DEG;
+                // blend along the shortest way around, yaw itself stays unwrapped
+                let mut error = heading - gyro_orientation[2];
+                while error > 180.0 {
+                    error -= 360.0;
+                }
+                while error < -180.0 {
+                    error += 360.0;
+                }
+                gyro_orientation[2] + (1.0 - self.alpha) * error
+            }
+            None => gyro_orientation[2],
+        };
+
+        // yaw always follows a target rotation speed, not a fixed setpoint
+        let error = self
+            .mode
+            .attitude_error(self.target, self.orientation, rates);
+        [0, 1, 2].map(|i| self.pid[i].advance(error[i], saturated))
+    }
+}
+
+/// Estimates the altitude above the first barometer reading by fusing the barometric
+/// altitude with the vertical acceleration, and holds a target altitude with a thrust
+/// correction.
+///
+/// Stays inert without barometer data: the estimate isn't updated and the correction is zero.
+pub struct AltitudeEstimator {
+    /// Time constant in seconds in which the barometer corrects the integrated acceleration
+    time_constant: F,
+    /// Pressure the altitude is measured from, taken from the first reading
+    reference: Option<F>,
+    altitude: F,
+    velocity: F,
+    /// Altitude held by the thrust correction, if any
+    target: Option<F>,
+    pub pid: Pid,
+}
+
+impl AltitudeEstimator {
+    /// Altitude change per pressure change near sea level
+    const METERS_PER_PASCAL: F = 0.083;
+    /// Acceleration of one mg in m/s²
+    const MPS2_PER_MG: F = 9.81e-3;
+    /// Largest thrust correction, so a bad estimate can't take over the throttle
+    pub const MAX_CORRECTION: F = 150.0;
+
+    pub fn new(time_constant: F, k_p: F, k_i: F, k_d: F) -> Self {
+        Self {
+            time_constant,
+            reference: None,
+            altitude: 0.0,
+            velocity: 0.0,
+            target: None,
+            pid: Pid {
+                k_p,
+                k_i,
+                k_d,
+                last_input: 0.0,
+                sum: 0.0,
+            },
+        }
+    }
+
+    /// Altitude in m above the first barometer reading
+    pub fn altitude(&self) -> F {
+        self.altitude
+    }
+
+    /// Holds the current altitude, or releases the hold
+    pub fn hold(&mut self, enabled: bool) {
+        self.target = (enabled && self.reference.is_some()).then_some(self.altitude);
+        self.pid.sum = 0.0;
+        self.pid.last_input = 0.0;
+    }
+
+    /// Fuses a sample and returns the thrust correction towards the held altitude.
+    ///
+    /// The vertical acceleration is taken from the body z axis, which is close enough while
+    /// hovering near level.
+    pub fn advance(&mut self, sample: impl ImuSample) -> F {
+        let Some(pressure) = sample.pressure().filter(|p| p.is_finite()) else {
+            return 0.0;
+        };
+        let accel = sample.accel()[IMU_AXIS_MAP[2]] * IMU_AXIS_SCALE[2];
+        let dt = sample.dt();
+        if !accel.is_finite() || !dt.is_finite() {
+            return 0.0;
+        }
+
+        let reference = *self.reference.get_or_insert(pressure);
+        let baro_altitude = (reference - pressure) * Self::METERS_PER_PASCAL;
+        let vertical_accel = (accel - 1000.0) * Self::MPS2_PER_MG;
+
+        // second order complementary filter, critically damped
+        let error = baro_altitude - self.altitude;
+        let k1 = 2.0 / self.time_constant;
+        let k2 = 1.0 / (self.time_constant * self.time_constant);
+        self.velocity += (vertical_accel + k2 * error) * dt;
+        self.altitude += (self.velocity + k1 * error) * dt;
+
+        match self.target {
+            Some(target) => self
+                .pid
+                .advance(target - self.altitude, false)
+                .clamp(-Self::MAX_CORRECTION, Self::MAX_CORRECTION),
+            None => 0.0,
+        }
+    }
+}
+
+#[test]
+fn pid_bumpless_gain_switch() {
+    let mut pid = Pid {
+        k_p: 25.0,
+        k_i: 0.5,
+        k_d: 10.0,
+        last_input: 0.0,
+        sum: 0.0,
+    };
+    for error in [4.0, 3.0, 2.5] {
+        pid.advance(error, false);
+    }
+    // proportional and integral part for the latest error, the derivative part only reacts to
+    // changes of the error
+    let output = |pid: &Pid| pid.k_p * pid.last_input + pid.k_i * pid.sum;
+    let before = output(&pid);
+
+    pid.set_gains_bumpless(40.0, 2.0, 5.0);
+    assert!((output(&pid) - before).abs() < 1e-4);
+    // the next update only adds the new integral step
+    assert!((pid.advance(2.5, false) - (before + 2.0 * 2.5)).abs() < 1e-4);
+
+    // without an integral gain the sum can't absorb the change
+    pid.set_gains_bumpless(40.0, 0.0, 5.0);
+    assert_eq!(pid.sum, 0.0);
+    assert_eq!((pid.k_p, pid.k_i, pid.k_d), (40.0, 0.0, 5.0));
+}
+
+#[cfg(test)]
+#[derive(Clone, Copy)]
+struct TestSample {
+    gyro: [F; 3],
+    accel: [F; 3],
+    dt: F,
+}
+
+#[cfg(test)]
+impl ImuSample for TestSample {
+    fn gyro(&self) -> [F; 3] {
+        self.gyro
+    }
+
+    fn accel(&self) -> [F; 3] {
+        self.accel
+    }
+
+    fn dt(&self) -> F {
+        self.dt
+    }
+
+    fn timestamp(&self) -> u64 {
+        0
+    }
+}
+
+#[test]
+fn filter_alpha() {
+    // at rest, tilted by 30° in roll
+    let tilted = TestSample {
+        gyro: [0.0; 3],
+        accel: [0.0, 500.0, 866.0],
+        dt: 0.01,
+    };
+    let roll = |alpha: F| {
+        let mut fusion = ComplementaryFilterFusion::builder().alpha(alpha).build();
+        fusion.advance(tilted, false);
+        fusion.orientation()[0]
+    };
+
+    // all gyro keeps the initial orientation, all accel jumps to the measured tilt
+    assert_eq!(roll(1.0), 0.0);
+    assert!((roll(0.0) + 30.0).abs() < 0.1);
+    assert!((roll(0.5) + 15.0).abs() < 0.1);
+
+    let mut fusion = ComplementaryFilterFusion::builder().alpha(0.9).build();
+    assert!(!fusion.set_alpha(1.1));
+    assert!(!fusion.set_alpha(-0.1));
+    assert!(!fusion.set_alpha(F::NAN));
+    assert_eq!(fusion.alpha(), 0.9);
+    assert!(fusion.set_alpha(0.0));
+    assert!(fusion.set_alpha(1.0));
+    assert_eq!(fusion.alpha(), 1.0);
+}
+
+#[test]
+fn accel_lowpass() {
+    let step = |factor: F| {
+        let mut fusion = ComplementaryFilterFusion::builder()
+            .accel_lowpass(factor)
+            .build();
+        fusion.filter_accel([0.0, 0.0, 1000.0]);
+        [0; 3].map(|_| fusion.filter_accel([100.0, 0.0, 1000.0])[0])
+    };
+
+    // the first sample is taken as is, later ones are blended in
+    assert_eq!(step(0.1), [10.0, 19.0, 27.1]);
+    assert_eq!(step(1.0), [100.0; 3]);
+    // factors are clamped, zero holds the first sample
+    assert_eq!(step(2.0), [100.0; 3]);
+    assert_eq!(step(-1.0), [0.0; 3]);
+
+    let mut fusion = ComplementaryFilterFusion::builder().build();
+    fusion.set_accel_lowpass(0.5);
+    fusion.filter_accel([0.0; 3]);
+    assert_eq!(
+        fusion.filter_accel([100.0, -100.0, 1000.0]),
+        [50.0, -50.0, 500.0]
+    );
+}
+
+#[test]
+fn gyro_bias_calibration() {
+    const BIAS: [F; 3] = [1.0, -2.0, 0.5];
+    const LEVEL: [F; 3] = [10.0, -20.0, 1010.0];
+    let mut bias = GyroBias::default();
+    assert!(bias.is_calibrating());
+
+    // moved during the first window
+    for i in 0..GyroBias::WINDOW {
+        let wobble = if i % 2 == 0 { 10.0 } else { -10.0 };
+        bias.correct(BIAS.map(|b| b + wobble), LEVEL);
+    }
+    assert!(bias.is_calibrating());
+    assert_eq!(bias.bias(), [0.0; 3]);
+
+    // still during the next one, the raw readings pass until it completes
+    for _ in 0..GyroBias::WINDOW - 1 {
+        assert_eq!(bias.correct(BIAS, LEVEL), (BIAS, LEVEL));
+    }
+    bias.correct(BIAS, LEVEL);
+    assert!(!bias.is_calibrating());
+    assert_eq!(bias.bias(), BIAS);
+    assert_eq!(bias.correct(BIAS, LEVEL), ([0.0; 3], LEVEL));
+
+    // a level calibration also takes the accelerometer offset
+    bias.recalibrate_level();
+    assert_eq!(bias.bias(), BIAS);
+    for _ in 0..GyroBias::WINDOW {
+        bias.correct(BIAS, LEVEL);
+    }
+    assert_eq!(bias.accel_offset(), [10.0, -20.0, 10.0]);
+    assert_eq!(bias.correct(BIAS, LEVEL), ([0.0; 3], [0.0, 0.0, 1000.0]));
+
+    // which a plain gyro calibration keeps
+    bias.recalibrate();
+    for _ in 0..GyroBias::WINDOW {
+        bias.correct([0.0; 3], [0.0; 3]);
+    }
+    assert_eq!(bias.bias(), [0.0; 3]);
+    assert_eq!(bias.accel_offset(), [10.0, -20.0, 10.0]);
+}
+
+#[test]
+fn builder_matches_new() {
+    let mut built = ComplementaryFilterFusion::builder()
+        .alpha(0.9)
+        .initial_orientation([1.0, -2.0, 3.0])
+        .target([5.0, 0.0, -10.0])
+        .kp([25.0, 20.0, 15.0])
+        .ki([0.1, 0.2, 0.3])
+        .kd([10.0, 8.0, 6.0])
+        .accel_lowpass(0.2)
+        .build();
+    let mut positional = ComplementaryFilterFusion::new(
+        0.9,
+        [1.0, -2.0, 3.0],
+        [5.0, 0.0, -10.0],
+        [25.0, 20.0, 15.0],
+        [0.1, 0.2, 0.3],
+        [10.0, 8.0, 6.0],
+    )
+    .with_accel_lowpass(0.2);
+
+    for i in 0..20 {
+        let sample = TestSample {
+            gyro: [i as F, -2.0, 0.5],
+            accel: [50.0, 100.0 - i as F, 990.0],
+            dt: 0.001,
+        };
+        assert_eq!(
+            built.advance(sample, false),
+            positional.advance(sample, false)
+        );
+        assert_eq!(built.orientation(), positional.orientation());
+    }
+    assert_eq!(built.target(), positional.target());
+
+    // defaults
+    let built = ComplementaryFilterFusion::builder().build();
+    assert_eq!(built.alpha(), 0.95);
+    assert_eq!(built.accel_lpf, 1.0);
+    assert_eq!(built.target(), [0.0; 3]);
+    assert!(
+        built
+            .pid
+            .iter()
+            .all(|pid| (pid.k_p, pid.k_i, pid.k_d) == (0.0, 0.0, 0.0))
+    );
+}
+
+#[test]
+fn tilt_cutoff_by_mode() {
+    let mut fusion = ComplementaryFilterFusion::builder()
+        .initial_orientation([-70.0, 10.0, 0.0])
+        .build();
+    assert!(fusion.tilt_exceeds(60.0));
+    assert!(fusion.tilt_cutoff(60.0));
+    assert!(!fusion.tilt_cutoff(80.0));
+
+    // upside down is fine in acro
+    fusion.set_mode(ControlMode::Rate);
+    assert!(fusion.tilt_exceeds(60.0));
+    assert!(!fusion.tilt_cutoff(60.0));
+}
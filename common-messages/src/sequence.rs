@@ -0,0 +1,160 @@
+/// Sliding window filter dropping duplicated and outdated sequence numbers.
+///
+/// Sequence numbers wrap around at `u16::MAX`. A sequence number far behind the newest one
+/// is taken as a restarted sender and resynchronizes the window.
+#[derive(Default)]
+pub struct SeqFilter {
+    newest: Option<u16>,
+    /// Bit `n` is set if `newest - n` was already received
+    seen: u64,
+}
+
+impl SeqFilter {
+    /// Number of sequence numbers behind the newest one still accepted out of order
+    pub const WINDOW: u16 = u64::BITS as u16;
+    /// Distance behind the newest sequence number from which on the sender is assumed restarted
+    const RESYNC: u16 = 1024;
+
+    /// Returns whether a message with sequence number `seq` should be processed
+    pub fn accept(&mut self, seq: u16) -> bool {
+        let Some(newest) = self.newest else {
+            self.resync(seq);
+            return true;
+        };
+
+        let ahead = seq.wrapping_sub(newest);
+        let behind = newest.wrapping_sub(seq);
+        if ahead != 0 && ahead < u16::MAX / 2 {
+            self.seen = self.seen.checked_shl(ahead as u32).unwrap_or(0) | 1;
+            self.newest = Some(seq);
+            true
+        } else if behind < Self::WINDOW {
+            let bit = 1 << behind;
+            let fresh = self.seen & bit == 0;
+            self.seen |= bit;
+            fresh
+        } else if behind >= Self::RESYNC {
+            self.resync(seq);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn resync(&mut self, seq: u16) {
+        self.newest = Some(seq);
+        self.seen = 1;
+    }
+}
+
+/// One [`SeqFilter`] for each of up to `N` senders, as every sender numbers its messages on its
+/// own.
+///
+/// A sender that restarts after a few messages lands within [`SeqFilter`]'s outdated range and
+/// would be dropped, so a sender silent for longer than `restart_gap_ms` is taken as restarted and
+/// resynchronizes. The least recently heard sender makes room for new ones.
+pub struct PeerSeqFilter<const N: usize> {
+    restart_gap_ms: u64,
+    /// Sender address, last time heard from in milliseconds and its filter
+    peers: [Option<([u8; 6], u64, SeqFilter)>; N],
+}
+
+impl<const N: usize> PeerSeqFilter<N> {
+    pub fn new(restart_gap_ms: u64) -> Self {
+        Self {
+            restart_gap_ms,
+            peers: core::array::from_fn(|_| None),
+        }
+    }
+
+    /// Returns whether a message from `address` with sequence number `seq` should be processed
+    pub fn accept(&mut self, address: [u8; 6], seq: u16, now_ms: u64) -> bool {
+        let known = self
+            .peers
+            .iter()
+            .position(|p| p.as_ref().is_some_and(|(a, _, _)| *a == address));
+        let Some(slot) = known
+            .or_else(|| self.peers.iter().position(Option::is_none))
+            .or_else(|| (0..N).min_by_key(|&i| self.peers[i].as_ref().map(|(_, t, _)| *t)))
+        else {
+            return true;
+        };
+
+        match &mut self.peers[slot] {
+            Some((a, last_ms, filter))
+                if *a == address && now_ms.saturating_sub(*last_ms) <= self.restart_gap_ms =>
+            {
+                *last_ms = now_ms;
+                filter.accept(seq)
+            }
+            entry => {
+                let mut filter = SeqFilter::default();
+                filter.accept(seq);
+                *entry = Some((address, now_ms, filter));
+                true
+            }
+        }
+    }
+}
+
+#[test]
+fn seq_filter_drops_duplicates() {
+    let mut filter = SeqFilter::default();
+    assert!(filter.accept(5));
+    assert!(!filter.accept(5));
+
+    // Reordered messages within the window are accepted once
+    assert!(filter.accept(8));
+    assert!(filter.accept(6));
+    assert!(!filter.accept(6));
+    assert!(filter.accept(7));
+    assert!(!filter.accept(8));
+
+    // Outdated messages are dropped
+    assert!(filter.accept(8 + SeqFilter::WINDOW));
+    assert!(!filter.accept(8));
+    assert!(filter.accept(9));
+
+    // Restarted sender
+    let restarted = (8 + SeqFilter::WINDOW).wrapping_sub(SeqFilter::RESYNC);
+    assert!(filter.accept(restarted));
+    assert!(filter.accept(restarted + 1));
+}
+
+#[test]
+fn seq_filter_wraparound() {
+    let mut filter = SeqFilter::default();
+    assert!(filter.accept(u16::MAX - 1));
+    assert!(filter.accept(1));
+    assert!(filter.accept(u16::MAX));
+    assert!(filter.accept(0));
+    assert!(!filter.accept(u16::MAX));
+    assert!(!filter.accept(u16::MAX - 1));
+    assert!(!filter.accept(1));
+    assert!(filter.accept(2));
+}
+
+#[test]
+fn peer_seq_filter() {
+    const RELAY: [u8; 6] = [1; 6];
+    const OTHER: [u8; 6] = [2; 6];
+    let mut filter = PeerSeqFilter::<2>::new(100);
+
+    // senders count independently
+    for seq in 0..200 {
+        assert!(filter.accept(RELAY, seq, seq as u64));
+    }
+    assert!(filter.accept(OTHER, 0, 200));
+    assert!(!filter.accept(RELAY, 199, 200));
+    assert!(!filter.accept(OTHER, 0, 200));
+
+    // restarted after 200 messages, within the outdated range of the old numbers
+    assert!(filter.accept(RELAY, 0, 500));
+    assert!(filter.accept(RELAY, 1, 501));
+    assert!(!filter.accept(RELAY, 0, 502));
+
+    // a third sender replaces the least recently heard one, which starts over
+    assert!(filter.accept([3; 6], 7, 600));
+    assert!(!filter.accept(RELAY, 1, 601));
+    assert!(filter.accept(OTHER, 0, 602));
+}
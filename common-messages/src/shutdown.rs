@@ -0,0 +1,97 @@
+use embassy_futures::select::{Either, select};
+
+/// Passes every message from `receive` to `send` until `shutdown` completes.
+///
+/// Shutdown is checked before waiting for the next message and takes precedence over queued
+/// ones. A message that is being sent is always completed first, so no partial messages are
+/// left behind.
+pub async fn send_until_shutdown<Msg>(
+    mut receive: impl AsyncFnMut() -> Msg,
+    mut send: impl AsyncFnMut(Msg),
+    mut shutdown: impl AsyncFnMut(),
+) {
+    loop {
+        match select(shutdown(), receive()).await {
+            Either::First(()) => return,
+            Either::Second(message) => send(message).await,
+        }
+    }
+}
+
+#[test]
+fn shutdown_returns_promptly() {
+    use alloc::{collections::VecDeque, vec::Vec};
+    use core::cell::{Cell, RefCell};
+    use core::future::poll_fn;
+    use core::task::Poll;
+    use embassy_futures::{block_on, yield_now};
+
+    // mock channel, pending while empty
+    let queue = RefCell::new(VecDeque::from([1, 2, 3]));
+    let receive = async || {
+        poll_fn(|_| {
+            queue
+                .borrow_mut()
+                .pop_front()
+                .map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    };
+    let signaled = Cell::new(false);
+    let shutdown = async || {
+        poll_fn(|_| {
+            if signaled.get() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    };
+
+    // signaled while the first message is sent, it is still completed, the queued ones are not
+    let sent = RefCell::new(Vec::new());
+    block_on(send_until_shutdown(
+        receive,
+        async |message| {
+            sent.borrow_mut().push((message, "started"));
+            signaled.set(true);
+            yield_now().await;
+            sent.borrow_mut().push((message, "completed"));
+        },
+        shutdown,
+    ));
+    assert_eq!(*sent.borrow(), [(1, "started"), (1, "completed")]);
+    assert_eq!(queue.borrow().len(), 2);
+
+    // signaled while waiting for messages
+    queue.borrow_mut().clear();
+    signaled.set(false);
+    let polls = Cell::new(0);
+    block_on(send_until_shutdown(
+        receive,
+        async |message: i32| panic!("nothing to send, got {message}"),
+        async || {
+            poll_fn(|_| {
+                polls.set(polls.get() + 1);
+                if polls.get() == 3 {
+                    signaled.set(true);
+                }
+                if signaled.get() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await
+        },
+    ));
+    assert_eq!(polls.get(), 3);
+
+    // already signaled
+    block_on(send_until_shutdown(
+        receive,
+        async |_| unreachable!(),
+        shutdown,
+    ));
+}
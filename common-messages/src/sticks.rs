@@ -0,0 +1,50 @@
+/// Target angle in degrees at full roll, pitch or yaw stick deflection
+pub const STICK_MAX_ANGLE: f32 = 30.0;
+/// Thrust at full throttle stick deflection
+pub const STICK_MAX_THRUST: f32 = 1000.0;
+
+/// Translates normalized stick positions of a [`crate::RemoteRequest::Sticks`] into the
+/// target attitude `[roll, pitch, yaw]` and thrust. Positions are clamped to -1.0..=1.0,
+/// the throttle stick spans zero to full thrust over that range.
+///
+/// A NaN from a flaky input device centers the attitude sticks and cuts the throttle.
+pub fn sticks_to_setpoint(roll: f32, pitch: f32, yaw: f32, thrust: f32) -> ([f32; 3], f32) {
+    let stick = |position: f32, fallback: f32| {
+        if position.is_nan() {
+            fallback
+        } else {
+            position.clamp(-1.0, 1.0)
+        }
+    };
+    let target = [roll, pitch, yaw].map(|position| stick(position, 0.0) * STICK_MAX_ANGLE);
+    let thrust = (stick(thrust, -1.0) / 2.0 + 0.5) * STICK_MAX_THRUST;
+    (target, thrust)
+}
+
+#[test]
+fn sticks_translation() {
+    assert_eq!(sticks_to_setpoint(0.0, 0.0, 0.0, -1.0), ([0.0; 3], 0.0));
+    assert_eq!(
+        sticks_to_setpoint(0.5, -0.5, 1.0, 0.0),
+        ([15.0, -15.0, 30.0], 500.0)
+    );
+    assert_eq!(
+        sticks_to_setpoint(1.0, -1.0, 0.25, 1.0),
+        ([30.0, -30.0, 7.5], 1000.0)
+    );
+
+    // Out of range positions are clamped
+    assert_eq!(
+        sticks_to_setpoint(3.0, -7.0, f32::INFINITY, 2.0),
+        ([30.0, -30.0, 30.0], 1000.0)
+    );
+    assert_eq!(
+        sticks_to_setpoint(0.0, 0.0, f32::NEG_INFINITY, -5.0),
+        ([0.0, 0.0, -30.0], 0.0)
+    );
+
+    assert_eq!(
+        sticks_to_setpoint(f32::NAN, 1.0, f32::NAN, f32::NAN),
+        ([0.0, 30.0, 0.0], 0.0)
+    );
+}
@@ -0,0 +1,251 @@
+//! Motor command ranges, from the mixer down to the ESC pulse:
+//!
+//! - thrust: `0.0..=1000.0` per motor out of the mixer, negated for reversed motors
+//! - throttle: `0..=THROTTLE_MAX` with the motor stopped at [`THROTTLE_STOP`], lower throttles
+//!   spin it in reverse (ESCs in bidirectional mode). Sent in [`crate::Telemetry`] and
+//!   [`crate::BlackboxFrame`]s.
+//! - pulse: `PULSE_MIN..=PULSE_MAX`, the one-shot pulse length in ticks of the protocol's
+//!   clock, see [`throttle_to_pulse`]
+
+use crate::MotorOutput;
+
+/// Throttle of a stopped motor, lower throttles spin it in reverse
+pub const THROTTLE_STOP: u16 = 1000;
+/// Throttle at full forward thrust
+pub const THROTTLE_MAX: u16 = 2000;
+
+/// Shortest one-shot pulse in clock ticks, full reverse
+pub const PULSE_MIN: u16 = 1000;
+/// Longest one-shot pulse in clock ticks, full forward thrust
+pub const PULSE_MAX: u16 = 2000;
+
+/// Converts a signed thrust in -1000.0..=1000.0 into the throttle range 0..=[`THROTTLE_MAX`].
+///
+/// Out of range thrusts saturate at the range ends and NaN stops the motor.
+pub fn thrust_to_throttle(thrust: f32) -> u16 {
+    if thrust.is_nan() {
+        return THROTTLE_STOP;
+    }
+    (thrust + THROTTLE_STOP as f32).clamp(0.0, THROTTLE_MAX as f32) as u16
+}
+
+/// Routes the mixer throttles to the esc outputs, see [`crate::ControlConfig::motor_outputs`]
+pub fn map_motor_throttles(throttles: [f32; 4], outputs: &[MotorOutput; 4]) -> [u16; 4] {
+    outputs
+        .map(|output| {
+            // validated on config changes, an unknown motor stays stopped regardless
+            let throttle = throttles
+                .get(output.mixer_index as usize)
+                .copied()
+                .unwrap_or(0.0);
+            if output.reversed { -throttle } else { throttle }
+        })
+        .map(thrust_to_throttle)
+}
+
+/// Maps a throttle onto the one-shot pulse range, [`THROTTLE_STOP`] lands in the middle of it
+/// where a bidirectional ESC stops the motor
+pub fn throttle_to_pulse(throttle: u16) -> u16 {
+    PULSE_MIN + throttle.min(THROTTLE_MAX) / 2
+}
+
+/// Reshapes a thrust in 0..=1000 for finer control around mid throttle.
+///
+/// `expo` in 0.0..=1.0 blends between linear and cubic, the endpoints and mid throttle
+/// stay in place.
+pub fn thrust_expo(thrust: f32, expo: f32) -> f32 {
+    const MID: f32 = 500.0;
+    let expo = expo.clamp(0.0, 1.0);
+    let x = ((thrust - MID) / MID).clamp(-1.0, 1.0);
+    MID + MID * ((1.0 - expo) * x + expo * x * x * x)
+}
+
+/// Keeps a thrust in 0..=1000 out of the range where ESCs stutter between stopped and spinning.
+///
+/// Thrusts below half the `deadband` snap to zero, the rest of the deadband snaps up to
+/// `deadband` as the minimum spin. Outside of the deadband the thrust passes unchanged.
+pub fn throttle_deadband(thrust: f32, deadband: f32) -> f32 {
+    if thrust >= deadband {
+        thrust
+    } else if thrust >= deadband / 2.0 {
+        deadband
+    } else {
+        0.0
+    }
+}
+
+/// Maps the distance of a throttle from [`THROTTLE_STOP`] to 0.0..=1.0, forward and reverse
+/// throttles alike
+pub fn normalized_throttle(throttle: u16) -> f32 {
+    throttle.min(THROTTLE_MAX).abs_diff(THROTTLE_STOP) as f32
+        / (THROTTLE_MAX - THROTTLE_STOP) as f32
+}
+
+/// Limits how fast motors may spin up, in either direction of a bidirectional ESC.
+///
+/// The distance of a throttle from [`THROTTLE_STOP`] rises by at most `max_rate` per second,
+/// independent of how often throttles are sent. Moves towards stop are applied immediately, so
+/// disarming is never delayed, and reversing a motor ramps up again from stop.
+pub struct RampLimiter {
+    max_rate: f32,
+    /// Time in microseconds and signed distances from stop of the last throttles
+    last: Option<(u64, [f32; 4])>,
+}
+
+impl RampLimiter {
+    pub fn new(max_rate: f32) -> Self {
+        Self {
+            max_rate,
+            last: None,
+        }
+    }
+
+    /// Limits `throttles` sent at `now_us`
+    pub fn apply(&mut self, throttles: [u16; 4], now_us: u64) -> [u16; 4] {
+        let targets = throttles.map(|t| t.min(THROTTLE_MAX) as f32 - THROTTLE_STOP as f32);
+        let ramped = match self.last {
+            Some((last_us, last)) => {
+                let max_step = self.max_rate * now_us.saturating_sub(last_us) as f32 / 1e6;
+                core::array::from_fn(|i| {
+                    let same_direction = targets[i] * last[i] >= 0.0;
+                    let limit = if same_direction { last[i].abs() } else { 0.0 } + max_step;
+                    targets[i].clamp(-limit, limit)
+                })
+            }
+            None => targets,
+        };
+        self.last = Some((now_us, ramped));
+        ramped.map(|distance| (THROTTLE_STOP as f32 + distance) as u16)
+    }
+}
+
+#[test]
+fn throttle_pulse_widths() {
+    // one-shot 125 runs the pulse clock at 8MHz, a tick is 0.125µs
+    let oneshot125_us = |thrust| throttle_to_pulse(thrust_to_throttle(thrust)) as f32 / 8.0;
+
+    assert_eq!(thrust_to_throttle(0.0), THROTTLE_STOP);
+    assert_eq!(thrust_to_throttle(500.0), 1500);
+    assert_eq!(thrust_to_throttle(1000.0), THROTTLE_MAX);
+    assert_eq!(oneshot125_us(0.0), 187.5);
+    assert_eq!(oneshot125_us(500.0), 218.75);
+    assert_eq!(oneshot125_us(1000.0), 250.0);
+
+    // reverse and out of range
+    assert_eq!(oneshot125_us(-1000.0), 125.0);
+    assert_eq!(thrust_to_throttle(1500.0), THROTTLE_MAX);
+    assert_eq!(thrust_to_throttle(-1500.0), 0);
+    assert_eq!(thrust_to_throttle(f32::NAN), THROTTLE_STOP);
+    assert_eq!(throttle_to_pulse(u16::MAX), PULSE_MAX);
+
+    assert_eq!(normalized_throttle(thrust_to_throttle(500.0)), 0.5);
+    assert_eq!(normalized_throttle(thrust_to_throttle(-500.0)), 0.5);
+    assert_eq!(normalized_throttle(0), 1.0);
+    assert_eq!(normalized_throttle(u16::MAX), 1.0);
+}
+
+#[test]
+fn ramp_step_response() {
+    const REVERSE: u16 = 0;
+    // sends `throttle` to the first motor at `rate_hz` until it is reached, returns the time it
+    // took in microseconds and the throttles sent on the way
+    let step = |ramp: &mut RampLimiter, now_us: &mut u64, rate_hz: u64, throttle: u16| {
+        let start_us = *now_us;
+        let mut sent = alloc::vec::Vec::new();
+        while sent.last() != Some(&throttle) {
+            *now_us += 1_000_000 / rate_hz;
+            let throttles = [throttle, THROTTLE_STOP, THROTTLE_STOP, THROTTLE_STOP];
+            sent.push(ramp.apply(throttles, *now_us)[0]);
+        }
+        (*now_us - start_us, sent)
+    };
+
+    // the full range from stop takes 125ms at any update rate
+    for rate_hz in [40, 1_600, 4_000] {
+        let mut ramp = RampLimiter::new(8_000.0);
+        let mut now_us = 0;
+        let update_us = 1_000_000 / rate_hz;
+        ramp.apply([THROTTLE_STOP; 4], now_us);
+
+        let (forward_us, sent) = step(&mut ramp, &mut now_us, rate_hz, THROTTLE_MAX);
+        assert_eq!(forward_us, 125_000);
+        assert!(sent.is_sorted());
+        // stopping is immediate
+        assert_eq!(
+            step(&mut ramp, &mut now_us, rate_hz, THROTTLE_STOP).0,
+            update_us
+        );
+
+        let (reverse_us, sent) = step(&mut ramp, &mut now_us, rate_hz, REVERSE);
+        assert_eq!(reverse_us, 125_000);
+        assert!(sent.is_sorted_by(|a, b| a >= b));
+        assert_eq!(
+            step(&mut ramp, &mut now_us, rate_hz, THROTTLE_STOP).0,
+            update_us
+        );
+    }
+
+    // slowing down is immediate, reversing ramps up again from stop
+    let mut ramp = RampLimiter::new(8_000.0);
+    let mut now_us = 0;
+    ramp.apply([THROTTLE_STOP; 4], now_us);
+    step(&mut ramp, &mut now_us, 1_000, THROTTLE_MAX);
+    let slower = step(&mut ramp, &mut now_us, 1_000, 1_200);
+    assert_eq!(slower, (1_000, alloc::vec![1_200]));
+    let (reverse_us, sent) = step(&mut ramp, &mut now_us, 1_000, REVERSE);
+    assert_eq!(reverse_us, 125_000);
+    assert_eq!(sent[0], THROTTLE_STOP - 8);
+
+    // every motor is limited on its own
+    assert_eq!(
+        ramp.apply([THROTTLE_STOP, 1_500, 900, 2_000], now_us + 1_000),
+        [THROTTLE_STOP, 1_008, 992, 1_008]
+    );
+}
+
+#[test]
+fn thrust_expo_shape() {
+    for expo in [0.0, 0.3, 0.7, 1.0] {
+        // endpoints and mid throttle stay in place
+        assert_eq!(thrust_expo(0.0, expo), 0.0);
+        assert_eq!(thrust_expo(500.0, expo), 500.0);
+        assert_eq!(thrust_expo(1000.0, expo), 1000.0);
+
+        let shaped = (0..=1000).map(|t| thrust_expo(t as f32, expo));
+        assert!(shaped.clone().is_sorted());
+        assert!(shaped.clone().all(|t| (0.0..=1000.0).contains(&t)));
+    }
+
+    // linear without expo, flatter around mid throttle with it
+    assert_eq!(thrust_expo(750.0, 0.0), 750.0);
+    assert!(thrust_expo(750.0, 0.5) < 750.0);
+    assert!(thrust_expo(250.0, 0.5) > 250.0);
+
+    // out of range thrusts and expos saturate
+    assert_eq!(thrust_expo(1500.0, 0.5), 1000.0);
+    assert_eq!(thrust_expo(-500.0, 0.5), 0.0);
+    assert_eq!(thrust_expo(750.0, 2.0), thrust_expo(750.0, 1.0));
+    assert_eq!(thrust_expo(750.0, -1.0), 750.0);
+}
+
+#[test]
+fn deadband_threshold() {
+    let deadband = |thrust| throttle_deadband(thrust, 30.0);
+
+    // rising through the deadband: stopped, minimum spin, passed through
+    assert_eq!(deadband(0.0), 0.0);
+    assert_eq!(deadband(14.9), 0.0);
+    assert_eq!(deadband(15.0), 30.0);
+    assert_eq!(deadband(29.9), 30.0);
+    assert_eq!(deadband(30.0), 30.0);
+    assert_eq!(deadband(30.1), 30.1);
+    assert_eq!(deadband(1000.0), 1000.0);
+
+    // falling through it the same way, no stuttering values in between
+    let falling: alloc::vec::Vec<_> = [40.0, 30.0, 20.0, 15.0, 10.0, 0.0].map(deadband).to_vec();
+    assert_eq!(falling, [40.0, 30.0, 30.0, 30.0, 0.0, 0.0]);
+
+    // no deadband
+    assert_eq!(throttle_deadband(0.0, 0.0), 0.0);
+    assert_eq!(throttle_deadband(0.1, 0.0), 0.1);
+}
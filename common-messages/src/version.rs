@@ -0,0 +1,45 @@
+/// Identity of a firmware build, as reported in [`crate::DroneResponse::Version`]
+pub type GitHash = [u8; 20];
+
+/// Parses a 40 digit hex git commit hash as printed by `git rev-parse HEAD`. Anything else,
+/// e.g. a build outside of a git checkout, gives all zeros.
+pub const fn parse_git_hash(hex: &str) -> GitHash {
+    const fn digit(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            b'A'..=b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    let hex = hex.as_bytes();
+    let mut hash = [0; 20];
+    if hex.len() != 2 * hash.len() {
+        return [0; 20];
+    }
+    let mut i = 0;
+    while i < hex.len() {
+        let Some(digit) = digit(hex[i]) else {
+            return [0; 20];
+        };
+        hash[i / 2] = hash[i / 2] << 4 | digit;
+        i += 1;
+    }
+    hash
+}
+
+#[test]
+fn git_hash_parsing() {
+    let hash = parse_git_hash("d4e84da0123456789abcdefABCDEF0123456789f");
+    assert_eq!(hash[..4], [0xd4, 0xe8, 0x4d, 0xa0]);
+    assert_eq!(hash[19], 0x9f);
+    assert_eq!(hash[8..12], [0x9a, 0xbc, 0xde, 0xfa]);
+
+    assert_eq!(parse_git_hash(""), [0; 20]);
+    assert_eq!(parse_git_hash("d4e84da"), [0; 20]);
+    assert_eq!(
+        parse_git_hash("g4e84da0123456789abcdefABCDEF0123456789f"),
+        [0; 20]
+    );
+}
@@ -0,0 +1,27 @@
+use common_messages::{CONFIG_BLOB_LEN, ControlConfig, decode_config, encode_config};
+use embedded_storage::{ReadStorage, Storage};
+
+/// Flash offset of the stored config, the nvs partition of the default partition table
+const OFFSET: u32 = 0x9000;
+
+/// Persists the [`ControlConfig`] in flash, see [`encode_config`] for the layout
+pub struct ConfigStore<S> {
+    storage: S,
+}
+
+impl<S: ReadStorage + Storage> ConfigStore<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    /// Returns `None` if the flash is empty or the stored config is corrupt or outdated
+    pub fn load(&mut self) -> Option<ControlConfig> {
+        let mut blob = [0; CONFIG_BLOB_LEN];
+        self.storage.read(OFFSET, &mut blob).ok()?;
+        decode_config(&blob)
+    }
+
+    pub fn save(&mut self, config: &ControlConfig) -> Result<(), S::Error> {
+        self.storage.write(OFFSET, &encode_config(config))
+    }
+}
@@ -4,7 +4,7 @@ use core::sync::atomic::{AtomicBool, Ordering};
 extern crate alloc;
 use alloc::boxed::Box;
 
-use common_messages::DroneResponse;
+use common_messages::{DroneResponse, LogLevel};
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Sender;
 use embassy_sync::pipe::Pipe;
@@ -65,16 +65,52 @@ impl Encoder {
 pub async fn defmt_data_to_drone_responses(
     drone_res: Sender<'static, CriticalSectionRawMutex, DroneResponse, 64>,
 ) {
-    let mut buffer = [0; 1024];
+    // Read at most one esp-now packet worth of log data at a time
+    let mut buffer = [0; DroneResponse::MAX_LOG_CHUNK];
+    let mut dropped_bytes = 0;
     loop {
         let len = DEFMT_DATA.read(&mut buffer).await;
-        drone_res
-            .send(DroneResponse::Log(Box::from(&buffer[..len])))
-            .await;
+        // Log data is dropped rather than waited for under backpressure, which keeps the pipe
+        // drained and leaves room in the queue for errors, the remote's decoder resyncs
+        let log = DroneResponse::Log(Box::from(&buffer[..len]));
+        if log.admit(drone_res.free_capacity()) {
+            let _ = drone_res.try_send(log);
+        } else {
+            dropped_bytes += len;
+        }
+
+        let dropped = DroneResponse::Event {
+            level: LogLevel::Warn,
+            code: EVENT_LOG_DROPPED,
+            args: [dropped_bytes as f32, 0.0],
+        };
+        if dropped_bytes > 0 && drone_res.try_send(dropped).is_ok() {
+            dropped_bytes = 0;
+        }
         embassy_futures::yield_now().await;
     }
 }
 
+/// [`DroneResponse::Event`] code for log data dropped under backpressure, with the number of
+/// dropped bytes as first argument
+pub const EVENT_LOG_DROPPED: u16 = 0x0401;
+
+/// Sends a numeric event to the remote, which unlike defmt logs can be decoded without the elf.
+/// Warnings and errors wait for room in the queue, lower levels are dropped under backpressure.
+pub async fn emit_event(
+    drone_res: &Sender<'static, CriticalSectionRawMutex, DroneResponse, 64>,
+    level: LogLevel,
+    code: u16,
+    args: [f32; 2],
+) {
+    let event = DroneResponse::Event { level, code, args };
+    if !event.is_droppable() {
+        drone_res.send(event).await;
+    } else if event.admit(drone_res.free_capacity()) {
+        let _ = drone_res.try_send(event);
+    }
+}
+
 static DEFMT_DATA: Pipe<CriticalSectionRawMutex, 1024> = Pipe::new();
 static TAKEN: AtomicBool = AtomicBool::new(false);
 static mut CS_RESTORE: critical_section::RestoreState = critical_section::RestoreState::invalid();
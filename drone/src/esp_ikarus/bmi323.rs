@@ -1,25 +1,20 @@
 use defmt::{Format, debug, error, info, trace, warn};
 use embassy_executor::SpawnToken;
-use embassy_sync::blocking_mutex::raw::NoopRawMutex;
-use esp_hal::{
-    Async,
-    delay::Delay,
-    dma::{DmaChannelFor, DmaRxBuf, DmaTxBuf},
-    gpio::{
-        Input, InputConfig, InputPin, Output, OutputConfig, OutputPin,
-        interconnect::{PeripheralInput, PeripheralOutput},
-    },
-    spi::master::SpiDmaBus,
-    time::Rate,
-};
-
-use crate::ImuSample;
+use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+use embassy_sync::signal::Signal;
+use esp_hal::gpio::{Input, InputConfig, InputPin, OutputPin};
+
+use super::spi::{EspSpi, SharedSpiBus, SpiTransfer};
+use crate::{ImuSample, TickExtender};
 
 const ACC_RANGE: u16 = 0b010 << 4; // +-8g, 4.10 LSB/mg
 const MG_PER_LSB: f32 = 1.0 / 4.10;
 
 const GYR_RANGE: u16 = 0b100 << 4; // +-2000deg/s, 16.4 LSB/deg/s
 const DPS_PER_LSB: f32 = 1.0 / 16.4;
+
+/// Sensor time resolution of 39.0625us, as a fraction
+const SENSOR_TIME_US: (u64, u64) = (625, 16);
 // const MUSEC_PER_LSB: f32 = 39.0625;
 // const SEC_PER_LSB: f32 = MUSEC_PER_LSB / (1000.0 * 1000.0);
 
@@ -58,31 +53,35 @@ const FEATURE_IO1: u8 = 0x11;
 
 const CMD: u8 = 0x7E;
 
-pub struct TxPin<'d>(Output<'d>);
-pub struct Tx<F: FnOnce()>(Option<F>);
+/// FIFO capacity in words
+const FIFO_CAPACITY: u16 = 1024;
 
-impl<F: FnOnce()> Drop for Tx<F> {
-    fn drop(&mut self) {
-        if let Some(f) = self.0.take() {
-            f()
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// Samples in the FIFO before int1 fires. Larger values save SPI transactions at the
+    /// cost of latency.
+    pub fifo_watermark: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { fifo_watermark: 1 }
     }
 }
 
-impl<'d> TxPin<'d> {
-    fn start_tx(&mut self) -> Tx<impl FnOnce()> {
-        self.0.set_low();
-        Tx(Some(|| {
-            self.0.set_high();
-            Delay::new().delay_micros(2);
-        }))
+impl Config {
+    /// FIFO_WATERMARK register value, `None` if the watermark does not fit into the FIFO
+    pub const fn watermark_words(&self) -> Option<u16> {
+        match self.fifo_watermark.checked_mul(WORDS_PER_SAMPLE as u16) {
+            Some(words) if self.fifo_watermark > 0 && words < FIFO_CAPACITY => Some(words),
+            _ => None,
+        }
     }
 }
 
-pub struct BMI323 {
+pub struct BMI323<S = EspSpi<'static>> {
     buf: &'static mut [u8],
-    spi: SpiDmaBus<'static, Async>,
-    cs: TxPin<'static>,
+    spi: S,
     int1: Input<'static>,
 }
 
@@ -91,6 +90,8 @@ pub struct Sample {
     pub gyro: [f32; 3],
     pub accl: [f32; 3],
     pub time: u16,
+    /// Sensor time in microseconds
+    pub timestamp: u64,
     pub dt: f32,
 }
 
@@ -106,6 +107,10 @@ impl ImuSample for Sample {
     fn dt(&self) -> f32 {
         self.dt
     }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -117,6 +122,9 @@ pub struct FifoStatus {
     fifo_watermark: bool,
 }
 
+/// Makes the running [`read_imu`] task log the registers, see [`BMI323::dump_registers`]
+pub static DUMP_REGISTERS: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
 #[embassy_executor::task]
 pub async fn read_imu(
     mut imu: BMI323,
@@ -126,10 +134,16 @@ pub async fn read_imu(
 
     _ = imu.flush_fifo().await;
 
+    let mut sensor_time = TickExtender::default();
+
     loop {
         imu.wait_for_data().await;
         debug!("[BMI323] int1");
 
+        if DUMP_REGISTERS.try_take().is_some() {
+            imu.dump_registers().await;
+        }
+
         while let Ok(FifoStatus {
             unread_words: unread_words @ 1..,
             fifo_full: _,
@@ -160,6 +174,8 @@ pub async fn read_imu(
                 let rz = i16::from_le_bytes(rz) as f32 * DPS_PER_LSB;
 
                 let time = u16::from_le_bytes(time);
+                let (num, den) = SENSOR_TIME_US;
+                let timestamp = sensor_time.extend(time) * num / den;
 
                 // 1s / ODR
                 // ODR = 1600Hz
@@ -169,6 +185,7 @@ pub async fn read_imu(
                     gyro: [rx, ry, rz],
                     accl: [ax, ay, az],
                     time,
+                    timestamp,
                     dt,
                 };
 
@@ -181,56 +198,45 @@ pub async fn read_imu(
 
 impl BMI323 {
     pub fn new(
-        spi: impl esp_hal::spi::master::Instance + 'static,
-        sck: impl OutputPin + 'static,
-        pico: impl PeripheralOutput<'static>,
-        poci: impl PeripheralInput<'static>,
-        dma: impl DmaChannelFor<esp_hal::spi::master::AnySpi<'static>>,
+        bus: &'static SharedSpiBus,
         cs: impl OutputPin + 'static,
         int1: impl InputPin + 'static,
     ) -> Self {
-        let buf = super::SPI_BUF.take();
-
-        let cs = TxPin(Output::new(
-            cs,
-            esp_hal::gpio::Level::High,
-            OutputConfig::default().with_drive_mode(esp_hal::gpio::DriveMode::PushPull),
-        ));
-
         let int1 = Input::new(
             int1,
             InputConfig::default().with_pull(esp_hal::gpio::Pull::Down),
         );
+        // the BMI323 needs chip select released for 2us between transactions
+        let spi = EspSpi::new(bus, cs, 2);
+
+        Self::with_spi(super::SPI_BUF.take(), spi, int1)
+    }
 
-        let spi = {
-            use esp_hal::spi::master::*;
-
-            let (rx_buf, rx_desc, tx_buf, tx_desc) = esp_hal::dma_buffers!(1024);
-
-            Spi::new(
-                spi,
-                Config::default()
-                    .with_frequency(Rate::from_mhz(10))
-                    .with_mode(esp_hal::spi::Mode::_0)
-                    .with_read_bit_order(esp_hal::spi::BitOrder::MsbFirst)
-                    .with_write_bit_order(esp_hal::spi::BitOrder::MsbFirst),
-            )
-            .unwrap()
-            .with_sck(sck)
-            .with_mosi(pico)
-            .with_miso(poci)
-            .with_dma(dma)
-            .with_buffers(
-                DmaRxBuf::new(rx_desc, rx_buf).unwrap(),
-                DmaTxBuf::new(tx_desc, tx_buf).unwrap(),
-            )
-            .into_async()
-        };
-
-        Self { buf, spi, cs, int1 }
+    pub fn start(
+        self,
+        channel: &'static mut embassy_sync::zerocopy_channel::Channel<NoopRawMutex, Sample>,
+    ) -> (
+        embassy_sync::zerocopy_channel::Receiver<'static, NoopRawMutex, Sample>,
+        SpawnToken<impl Sized>,
+    ) {
+        let (tx, rx) = channel.split();
+        (rx, read_imu(self, tx))
+    }
+}
+
+impl<S: SpiTransfer> BMI323<S> {
+    pub fn with_spi(buf: &'static mut [u8], spi: S, int1: Input<'static>) -> Self {
+        Self { buf, spi, int1 }
     }
 
-    pub async fn configure(&mut self) -> Result<(), ConfigurationError> {
+    pub async fn configure(&mut self, config: Config) -> Result<(), ConfigurationError> {
+        let watermark_words =
+            config
+                .watermark_words()
+                .ok_or(ConfigurationError::InvalidFifoWatermark(
+                    config.fifo_watermark,
+                ))?;
+
         // dummy read to trigger switch to SPI
         _ = self
             .read_register(CHIP_ID)
@@ -287,9 +293,7 @@ impl BMI323 {
         self.self_test_and_calibration().await?;
 
         // FIFO config
-        const FIFO_SAMPLES: u16 = 1;
-        const FIFO_WATERMARK_LEVEL: u16 = FIFO_SAMPLES * WORDS_PER_SAMPLE as u16;
-        self.write_verify_register(FIFO_WATERMARK, FIFO_WATERMARK_LEVEL)
+        self.write_verify_register(FIFO_WATERMARK, watermark_words)
             .await
             .map_err(ConfigurationError::Verification)?;
 
@@ -500,17 +504,34 @@ impl BMI323 {
         Ok(())
     }
 
+    /// Logs the configuration and status registers, for diagnosing a failed configuration
+    pub async fn dump_registers(&mut self) {
+        const REGISTERS: [(&str, u8); 8] = [
+            ("CHIP_ID", CHIP_ID),
+            ("ERROR", ERROR),
+            ("STATUS", STATUS),
+            ("FIFO_CONF", FIFO_CONF),
+            ("ACC_CONF", ACC_CONF),
+            ("GYR_CONF", GYR_CONF),
+            ("INT_MAP2", INT_MAP2),
+            ("FEATURE_IO1", FEATURE_IO1),
+        ];
+
+        for (name, reg) in REGISTERS {
+            match self.read_register(reg).await {
+                Ok(value) => info!("[BMI323] {}(0x{:02x}) = 0x{:04x}", name, reg, value),
+                Err(e) => error!("[BMI323] unable to read {}(0x{:02x}): {:?}", name, reg, e),
+            }
+        }
+    }
+
     async fn read_register(&mut self, reg: u8) -> Result<u16, esp_hal::spi::Error> {
         let cmd = [READ | reg, 0, 0, 0];
         self.buf[0..cmd.len()].copy_from_slice(&cmd);
 
-        critical_section::with(|_cs| {
-            let _tx = self.cs.start_tx();
-            self.spi
-                // .transfer_in_place_async(&mut self.buf[0..cmd.len()])
-                // .await?;
-                .transfer_in_place(&mut self.buf[0..cmd.len()])
-        })?;
+        self.spi
+            .transfer_in_place(&mut self.buf[0..cmd.len()])
+            .await?;
 
         let v = u16::from_le_bytes([self.buf[2], self.buf[3]]);
         debug!("[SPI] read(0x{:02x}) => 0x{:04x}", reg, v);
@@ -524,10 +545,7 @@ impl BMI323 {
         let cmd = [WRITE & reg, val0, val1];
         self.buf[0..cmd.len()].copy_from_slice(&cmd);
 
-        let _tx = self.cs.start_tx();
-        // self.spi.write_async(&self.buf[0..cmd.len()]).await?;
-
-        critical_section::with(|_cs| self.spi.write(&self.buf[0..cmd.len()]))
+        self.spi.write(&self.buf[0..cmd.len()]).await
     }
 
     async fn write_verify_register(&mut self, reg: u8, val: u16) -> Result<(), CheckedWriteError> {
@@ -548,17 +566,6 @@ impl BMI323 {
         Ok(())
     }
 
-    pub fn start(
-        self,
-        channel: &'static mut embassy_sync::zerocopy_channel::Channel<NoopRawMutex, Sample>,
-    ) -> (
-        embassy_sync::zerocopy_channel::Receiver<'static, NoopRawMutex, Sample>,
-        SpawnToken<impl Sized>,
-    ) {
-        let (tx, rx) = channel.split();
-        (rx, read_imu(self, tx))
-    }
-
     pub async fn fifo_status(&mut self) -> Result<FifoStatus, esp_hal::spi::Error> {
         let int_status1 = self.read_register(INT_STATUS1).await?;
         let int_status2 = self.read_register(INT_STATUS1).await?;
@@ -604,13 +611,7 @@ impl BMI323 {
         self.buf[0] = READ | FIFO_DATA;
         self.buf[1] = 0;
 
-        critical_section::with(|_cs| {
-            let _tx = self.cs.start_tx();
-            self.spi
-                // .transfer_in_place_async(&mut self.buf[0..len + 2])
-                // .await?;
-                .transfer_in_place(&mut self.buf[0..len + 2])
-        })
+        self.spi.transfer_in_place(&mut self.buf[0..len + 2]).await
     }
 
     pub async fn wait_for_data(&mut self) {
@@ -618,6 +619,26 @@ impl BMI323 {
     }
 }
 
+impl super::Imu for BMI323 {
+    type Config = Config;
+    type Sample = Sample;
+    type Error = ConfigurationError;
+
+    async fn configure(&mut self, config: Config) -> Result<(), ConfigurationError> {
+        BMI323::configure(self, config).await
+    }
+
+    fn start(
+        self,
+        channel: &'static mut embassy_sync::zerocopy_channel::Channel<NoopRawMutex, Sample>,
+    ) -> (
+        embassy_sync::zerocopy_channel::Receiver<'static, NoopRawMutex, Sample>,
+        SpawnToken<impl Sized>,
+    ) {
+        BMI323::start(self, channel)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CheckedWriteError {
     #[error("Spi error: {0:?}")]
@@ -644,6 +665,9 @@ pub enum ConfigurationError {
     #[error("Init error: {0}")]
     Init(u16),
 
+    #[error("FIFO watermark of {0} samples exceeds the FIFO")]
+    InvalidFifoWatermark(u16),
+
     #[error("Chip timed out")]
     Timeout,
 
@@ -653,3 +677,20 @@ pub enum ConfigurationError {
     #[error("Self-Calibration failed: Status: {0:02x}")]
     SelfCalibration(u8),
 }
+
+impl ConfigurationError {
+    /// Stable code reported to the remote in `DroneResponse::Error`
+    pub fn code(&self) -> u16 {
+        match self {
+            ConfigurationError::Spi(_) => 0x0101,
+            ConfigurationError::Verification(_) => 0x0102,
+            ConfigurationError::InvalidChip => 0x0103,
+            ConfigurationError::Internal(_) => 0x0104,
+            ConfigurationError::Init(_) => 0x0105,
+            ConfigurationError::Timeout => 0x0106,
+            ConfigurationError::SelfTest(_) => 0x0107,
+            ConfigurationError::SelfCalibration(_) => 0x0108,
+            ConfigurationError::InvalidFifoWatermark(_) => 0x0109,
+        }
+    }
+}
@@ -4,17 +4,9 @@ use defmt::{error, warn};
 use embassy_executor::SpawnToken;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_time::Instant;
-use esp_hal::{
-    Async,
-    dma::{DmaChannelFor, DmaRxBuf, DmaTxBuf},
-    gpio::{
-        Input, InputConfig, InputPin, Output, OutputConfig, OutputPin,
-        interconnect::{PeripheralInput, PeripheralOutput},
-    },
-    spi::master::SpiDmaBus,
-    time::Rate,
-};
+use esp_hal::gpio::{Input, InputConfig, InputPin, OutputPin};
 
+use super::spi::{EspSpi, SharedSpiBus, SpiTransfer};
 use crate::ImuSample;
 
 const READ: u8 = 0x80;
@@ -54,29 +46,130 @@ pub const FIFO_STATUS4: u8 = 0x3d;
 pub const FIFO_DATA_OUT_L: u8 = 0x3e;
 pub const FIFO_DATA_OUT_H: u8 = 0x3f;
 
-pub struct TxPin<'d>(Output<'d>);
-pub struct Tx<F: FnOnce()>(Option<F>);
+pub struct LSM6DS3<S = EspSpi<'static>> {
+    buf: &'static mut [u8],
+    spi: S,
+    int1: Input<'static>,
+    config: Config,
+}
+
+/// Accelerometer full-scale range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccelRange {
+    G2,
+    G4,
+    G8,
+    G16,
+}
+
+impl AccelRange {
+    /// FS_XL field of CTRL1_XL
+    const fn bits(self) -> u8 {
+        match self {
+            AccelRange::G2 => 0b00 << 2,
+            AccelRange::G4 => 0b10 << 2,
+            AccelRange::G8 => 0b11 << 2,
+            AccelRange::G16 => 0b01 << 2,
+        }
+    }
 
-impl<F: FnOnce()> Drop for Tx<F> {
-    fn drop(&mut self) {
-        if let Some(f) = self.0.take() {
-            f()
+    pub const fn mg_per_lsb(self) -> f32 {
+        match self {
+            AccelRange::G2 => 0.061,
+            AccelRange::G4 => 0.122,
+            AccelRange::G8 => 0.244,
+            AccelRange::G16 => 0.488,
         }
     }
 }
 
-impl<'d> TxPin<'d> {
-    fn start_tx(&mut self) -> Tx<impl FnOnce()> {
-        self.0.set_low();
-        Tx(Some(|| self.0.set_high()))
+/// Gyroscope full-scale range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GyroRange {
+    Dps245,
+    Dps500,
+    Dps1000,
+    Dps2000,
+}
+
+impl GyroRange {
+    /// FS_G field of CTRL2_G
+    const fn bits(self) -> u8 {
+        match self {
+            GyroRange::Dps245 => 0b00 << 2,
+            GyroRange::Dps500 => 0b01 << 2,
+            GyroRange::Dps1000 => 0b10 << 2,
+            GyroRange::Dps2000 => 0b11 << 2,
+        }
+    }
+
+    pub const fn dps_per_lsb(self) -> f32 {
+        match self {
+            GyroRange::Dps245 => 0.00875,
+            GyroRange::Dps500 => 0.0175,
+            GyroRange::Dps1000 => 0.035,
+            GyroRange::Dps2000 => 0.07,
+        }
     }
 }
 
-pub struct LSM6DS3 {
-    buf: &'static mut [u8],
-    spi: SpiDmaBus<'static, Async>,
-    cs: TxPin<'static>,
-    int1: Input<'static>,
+/// Output data rate of accelerometer and gyroscope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataRate {
+    Hz416,
+    Hz833,
+    Hz1660,
+}
+
+impl DataRate {
+    /// ODR_XL / ODR_G field of CTRL1_XL and CTRL2_G
+    const fn bits(self) -> u8 {
+        match self {
+            DataRate::Hz416 => 0b0110 << 4,
+            DataRate::Hz833 => 0b0111 << 4,
+            DataRate::Hz1660 => 0b1000 << 4,
+        }
+    }
+
+    pub const fn hz(self) -> f32 {
+        match self {
+            DataRate::Hz416 => 416.0,
+            DataRate::Hz833 => 833.0,
+            DataRate::Hz1660 => 1660.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub accel_range: AccelRange,
+    pub gyro_range: GyroRange,
+    pub data_rate: DataRate,
+    /// Samples in the FIFO before int1 fires. Larger values save SPI transactions at the
+    /// cost of latency.
+    pub fifo_watermark: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            accel_range: AccelRange::G8,
+            gyro_range: GyroRange::Dps1000,
+            data_rate: DataRate::Hz1660,
+            fifo_watermark: 4,
+        }
+    }
+}
+
+impl Config {
+    /// FIFO threshold in words for FIFO_CTRL1/2, `None` if the watermark does not fit into
+    /// the FIFO
+    pub const fn watermark_words(&self) -> Option<u16> {
+        match self.fifo_watermark.checked_mul(WORDS_PER_SAMPLE as u16) {
+            Some(words) if self.fifo_watermark > 0 && words <= FIFO_THRESHOLD_MAX => Some(words),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -89,17 +182,20 @@ pub struct FifoStatus {
     pub pattern: u16,
 }
 
-const SAMPLES_IN_FIFO: usize = 4;
 const ENTRIES_PER_SAMPLE: usize = 3;
 const WORDS_PER_ENTRY: usize = 3;
+const WORDS_PER_SAMPLE: usize = ENTRIES_PER_SAMPLE * WORDS_PER_ENTRY;
 const BYTES_PER_WORD: usize = 2;
-const FIFO_THRESHOLD: usize = SAMPLES_IN_FIFO * ENTRIES_PER_SAMPLE * WORDS_PER_ENTRY;
+/// FTH field of FIFO_CTRL1/2 is 12 bits wide, the FIFO holds 4096 words
+const FIFO_THRESHOLD_MAX: u16 = 0x0fff;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Sample {
     pub gy: [f32; 3],
     pub xl: [f32; 3],
     pub temp: [f32; 3],
+    /// Estimated from the time the FIFO was read, the FIFO holds no timestamps
+    pub timestamp: u64,
     pub dt: f32,
 }
 
@@ -115,6 +211,10 @@ impl ImuSample for Sample {
     fn dt(&self) -> f32 {
         self.dt
     }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -128,10 +228,18 @@ async fn read_imu_task(
     mut imu: LSM6DS3,
     mut tx: embassy_sync::zerocopy_channel::Sender<'static, NoopRawMutex, SampleEvent>,
 ) {
-    let mut buf = [0u8; 256];
+    // twice the watermark, so a lagging reader still catches up in one read
+    let read_len = (imu.config.watermark_words().unwrap_or(0) as usize * BYTES_PER_WORD * 2)
+        .max(WORDS_PER_SAMPLE * BYTES_PER_WORD * 2)
+        .min(imu.buf.len());
+    let buf = &mut core::mem::take(&mut imu.buf)[..read_len];
     let mut leftover_len = 0usize;
 
-    const PATTERNS: u16 = (ENTRIES_PER_SAMPLE * WORDS_PER_ENTRY) as _;
+    let mg_per_lsb = imu.config.accel_range.mg_per_lsb();
+    let dps_per_lsb = imu.config.gyro_range.dps_per_lsb();
+    let dt = 1.0 / imu.config.data_rate.hz();
+
+    const PATTERNS: u16 = WORDS_PER_SAMPLE as _;
 
     loop {
         imu.wait_for_data().await;
@@ -206,29 +314,30 @@ async fn read_imu_task(
                 words.as_chunks::<{ ENTRIES_PER_SAMPLE * WORDS_PER_ENTRY }>();
             let raw_samples_bytes = raw_samples.as_flattened().len();
 
-            for [rx, ry, rz, ax, ay, az, t0, t1, t2] in raw_samples.iter().copied() {
-                const MG_PER_LSB: f32 = 0.244; // Scale: 8g
-                const MDPS_PER_LSB: f32 = 0.035; // Scale: 1000dps
+            // The last sample was taken right before reading, earlier ones one dt apart
+            let read_at = Instant::now().as_micros();
+            let dt_us = (dt * 1_000_000.0) as u64;
+            let first_at =
+                read_at.saturating_sub(dt_us * raw_samples.len().saturating_sub(1) as u64);
 
-                let rx = i16::from_le_bytes(rx) as f32 * MDPS_PER_LSB;
-                let ry = i16::from_le_bytes(ry) as f32 * MDPS_PER_LSB;
-                let rz = i16::from_le_bytes(rz) as f32 * MDPS_PER_LSB;
-                let ax = i16::from_le_bytes(ax) as f32 * MG_PER_LSB;
-                let ay = i16::from_le_bytes(ay) as f32 * MG_PER_LSB;
-                let az = i16::from_le_bytes(az) as f32 * MG_PER_LSB;
+            for (i, [rx, ry, rz, ax, ay, az, t0, t1, t2]) in raw_samples.iter().copied().enumerate()
+            {
+                let rx = i16::from_le_bytes(rx) as f32 * dps_per_lsb;
+                let ry = i16::from_le_bytes(ry) as f32 * dps_per_lsb;
+                let rz = i16::from_le_bytes(rz) as f32 * dps_per_lsb;
+                let ax = i16::from_le_bytes(ax) as f32 * mg_per_lsb;
+                let ay = i16::from_le_bytes(ay) as f32 * mg_per_lsb;
+                let az = i16::from_le_bytes(az) as f32 * mg_per_lsb;
 
                 let t0 = (i16::from_le_bytes(t0) as f32 / 256.0) + 25.0;
                 let t1 = (i16::from_le_bytes(t1) as f32 / 256.0) + 25.0;
                 let t2 = (i16::from_le_bytes(t2) as f32 / 256.0) + 25.0;
 
-                // dt = 1s / ODR
-                // ODR = 1600Hz
-                let dt = 1.0 / 1600.0;
-
                 let sample = Sample {
                     gy: [rx, ry, rz],
                     xl: [ax, ay, az],
                     temp: [t0, t1, t2],
+                    timestamp: first_at + dt_us * i as u64,
                     dt,
                 };
 
@@ -258,95 +367,61 @@ impl LSM6DS3 {
         (rx, read_imu_task(self, tx))
     }
 
-    pub async fn fifo_status(&mut self) -> Result<FifoStatus, esp_hal::spi::Error> {
-        todo!("use new dma buffer");
-
-        // let _tx = self.cs.start_tx();
-        // let mut buf = [0; 5];
-        // self.spi
-        //     .transfer_async(&mut buf, &[READ | FIFO_STATUS1])
-        //     .await?;
-        // drop(_tx);
-
-        // let buf = &buf[1..];
-        // let status = FifoStatus {
-        //     unread_words: u16::from_le_bytes([buf[0], buf[1] & 0x0f]),
-        //     threshold: buf[1] & (1 << 7) > 0,
-        //     over_run: buf[1] & (1 << 6) > 0,
-        //     full: buf[1] & (1 << 5) > 0,
-        //     empty: buf[1] & (1 << 4) > 0,
-        //     pattern: u16::from_le_bytes([buf[2], buf[3] & 0b11]),
-        // };
-
-        // Ok(status)
-    }
-
-    pub async fn read_fifo(&mut self, buf: &mut [u8]) -> Result<(), esp_hal::spi::Error> {
-        todo!("use new dma buffer");
-
-        // let _tx = self.cs.start_tx();
-
-        // self.spi.write_async(&[READ | FIFO_DATA_OUT_L]).await?;
-        // self.spi.read_async(buf).await?;
-
-        // Ok(())
-    }
-
-    pub async fn wait_for_data(&mut self) {
-        self.int1.wait_for_high().await
-    }
-
     pub fn new(
-        spi: impl esp_hal::spi::master::Instance + 'static,
-        sck: impl OutputPin + 'static,
-        pico: impl PeripheralOutput<'static>,
-        poci: impl PeripheralInput<'static>,
-        dma: impl DmaChannelFor<esp_hal::spi::master::AnySpi<'static>>,
+        bus: &'static SharedSpiBus,
         cs: impl OutputPin + 'static,
         int1: impl InputPin + 'static,
     ) -> Self {
-        let buf = super::SPI_BUF.take();
-
-        let cs = TxPin(Output::new(
-            cs,
-            esp_hal::gpio::Level::High,
-            OutputConfig::default().with_drive_mode(esp_hal::gpio::DriveMode::PushPull),
-        ));
-
         let int1 = Input::new(
             int1,
             InputConfig::default().with_pull(esp_hal::gpio::Pull::Down),
         );
+        let spi = EspSpi::new(bus, cs, 0);
 
-        let spi = {
-            use esp_hal::spi::master::*;
-
-            let (rx_buf, rx_desc, tx_buf, tx_desc) = esp_hal::dma_buffers!(1024);
-
-            Spi::new(
-                spi,
-                Config::default()
-                    .with_frequency(Rate::from_mhz(10))
-                    .with_mode(esp_hal::spi::Mode::_0)
-                    .with_read_bit_order(esp_hal::spi::BitOrder::MsbFirst)
-                    .with_write_bit_order(esp_hal::spi::BitOrder::MsbFirst),
-            )
-            .unwrap()
-            .with_sck(sck)
-            .with_mosi(pico)
-            .with_miso(poci)
-            .with_dma(dma)
-            .with_buffers(
-                DmaRxBuf::new(rx_desc, rx_buf).unwrap(),
-                DmaTxBuf::new(tx_desc, tx_buf).unwrap(),
-            )
-            .into_async()
-        };
-
-        Self { buf, spi, cs, int1 }
+        Self::with_spi(super::SPI_BUF.take(), spi, int1)
+    }
+}
+
+impl<S: SpiTransfer> LSM6DS3<S> {
+    pub fn with_spi(buf: &'static mut [u8], spi: S, int1: Input<'static>) -> Self {
+        Self {
+            buf,
+            spi,
+            int1,
+            config: Config::default(),
+        }
     }
 
-    pub async fn configure(&mut self) -> Result<(), ConfigurationError> {
+    pub async fn fifo_status(&mut self) -> Result<FifoStatus, esp_hal::spi::Error> {
+        let mut buf = [0; 4];
+        self.spi.read(&[READ | FIFO_STATUS1], &mut buf).await?;
+
+        Ok(FifoStatus {
+            unread_words: u16::from_le_bytes([buf[0], buf[1] & 0x0f]),
+            threshold: buf[1] & (1 << 7) > 0,
+            over_run: buf[1] & (1 << 6) > 0,
+            full: buf[1] & (1 << 5) > 0,
+            empty: buf[1] & (1 << 4) > 0,
+            pattern: u16::from_le_bytes([buf[2], buf[3] & 0b11]),
+        })
+    }
+
+    pub async fn read_fifo(&mut self, buf: &mut [u8]) -> Result<(), esp_hal::spi::Error> {
+        self.spi.read(&[READ | FIFO_DATA_OUT_L], buf).await
+    }
+
+    pub async fn wait_for_data(&mut self) {
+        self.int1.wait_for_high().await
+    }
+
+    pub async fn configure(&mut self, config: Config) -> Result<(), ConfigurationError> {
+        let watermark_words =
+            config
+                .watermark_words()
+                .ok_or(ConfigurationError::InvalidFifoWatermark(
+                    config.fifo_watermark,
+                ))?;
+
         let who_am_i = self
             .read_register(WHO_AM_I)
             .await
@@ -378,11 +453,10 @@ impl LSM6DS3 {
 
         let reset_start = Instant::now();
         loop {
-            if let Ok(IF_INC) = self
-                .read_register(CTRL3_C)
-                .await
-                .map_err(ConfigurationError::Spi)
-            {
+            // Reset is complete once SW_RESET cleared and CTRL3_C is back at its default,
+            // read errors are expected while the chip restarts
+            let ctrl3_c = self.read_register(CTRL3_C).await;
+            if matches!(ctrl3_c, Ok(value) if value == IF_INC) {
                 break;
             }
 
@@ -396,18 +470,17 @@ impl LSM6DS3 {
             .await
             .map_err(ConfigurationError::Verification)?;
 
-        const ODR_XL: u8 = 0b1000 << 4;
-        const FS_XL: u8 = 0b11 << 2;
-        const BW_XL: u8 = 0b10 << 2;
-        self.write_verify_register(CTRL1_XL, ODR_XL | FS_XL | BW_XL)
+        let odr = config.data_rate.bits();
+        // BW_XL[1:0] = 0b00: 400Hz anti-aliasing filter
+        const BW_XL: u8 = 0b00;
+        self.write_verify_register(CTRL1_XL, odr | config.accel_range.bits() | BW_XL)
             .await
             .map_err(ConfigurationError::Verification)?;
 
-        const ODR_G: u8 = 0b1000 << 4;
-        const FS_G: u8 = 0b10 << 2;
-        self.write_verify_register(CTRL2_G, ODR_G | FS_G)
+        self.write_verify_register(CTRL2_G, odr | config.gyro_range.bits())
             .await
             .map_err(ConfigurationError::Verification)?;
+        self.config = config;
 
         const XL_BW_SCAL_ODR: u8 = 1 << 7;
         const FIFO_TEMP_EN: u8 = 1 << 4;
@@ -416,7 +489,9 @@ impl LSM6DS3 {
             .await
             .map_err(ConfigurationError::Verification)?;
 
-        const ROUNDING: u8 = 110 << 5;
+        // CTRL5_C ROUNDING[2:0] (bits 7:5) = 0b110: circular burst reads over
+        // gyroscope, accelerometer and FIFO data (registers 22h to 3Eh)
+        const ROUNDING: u8 = 0b110 << 5;
         self.write_verify_register(CTRL5_C, ROUNDING)
             .await
             .map_err(ConfigurationError::Verification)?;
@@ -438,14 +513,12 @@ impl LSM6DS3 {
             .map_err(ConfigurationError::Verification)?;
 
         // Init FIFO
-        const FIFO_THRESHOLD_BYTES: [u8; 2] = (FIFO_THRESHOLD as u16).to_le_bytes();
-        const FIFO_THRESHOLD_BYTES_L: u8 = FIFO_THRESHOLD_BYTES[0];
-        const FIFO_THRESHOLD_BYTES_H: u8 = FIFO_THRESHOLD_BYTES[1] & 0xf;
-        self.write_verify_register(FIFO_CTRL1, FIFO_THRESHOLD_BYTES_L)
+        let [threshold_l, threshold_h] = watermark_words.to_le_bytes();
+        self.write_verify_register(FIFO_CTRL1, threshold_l)
             .await
             .map_err(ConfigurationError::Verification)?;
 
-        self.write_verify_register(FIFO_CTRL2, FIFO_THRESHOLD_BYTES_H)
+        self.write_verify_register(FIFO_CTRL2, threshold_h & 0xf)
             .await
             .map_err(ConfigurationError::Verification)?;
 
@@ -481,25 +554,13 @@ impl LSM6DS3 {
     }
 
     async fn read_register(&mut self, reg: u8) -> Result<u8, esp_hal::spi::Error> {
-        todo!("use new dma buffer");
-
-        // let _tx = self.cs.start_tx();
-
-        // self.spi.write_async(&[READ | reg]).await?;
-        // let mut buf = [0];
-        // self.spi.read_async(&mut buf).await?;
-
-        // Ok(buf[0])
+        let mut buf = [0];
+        self.spi.read(&[READ | reg], &mut buf).await?;
+        Ok(buf[0])
     }
 
     async fn write_register(&mut self, reg: u8, val: u8) -> Result<(), esp_hal::spi::Error> {
-        todo!("use new dma buffer");
-
-        // let _tx = self.cs.start_tx();
-
-        // self.spi.write_async(&[WRITE & reg, val]).await?;
-
-        // Ok(())
+        self.spi.write(&[WRITE & reg, val]).await
     }
 
     async fn write_verify_register(&mut self, reg: u8, val: u8) -> Result<(), CheckedWriteError> {
@@ -521,6 +582,26 @@ impl LSM6DS3 {
     }
 }
 
+impl super::Imu for LSM6DS3 {
+    type Config = Config;
+    type Sample = SampleEvent;
+    type Error = ConfigurationError;
+
+    async fn configure(&mut self, config: Config) -> Result<(), ConfigurationError> {
+        LSM6DS3::configure(self, config).await
+    }
+
+    fn start(
+        self,
+        channel: &'static mut embassy_sync::zerocopy_channel::Channel<NoopRawMutex, SampleEvent>,
+    ) -> (
+        embassy_sync::zerocopy_channel::Receiver<'static, NoopRawMutex, SampleEvent>,
+        SpawnToken<impl Sized>,
+    ) {
+        LSM6DS3::start(self, channel)
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CheckedWriteError {
     #[error("Spi error: {0:?}")]
@@ -543,4 +624,20 @@ pub enum ConfigurationError {
 
     #[error("Chip timed out")]
     Timeout,
+
+    #[error("FIFO watermark of {0} samples exceeds the FIFO")]
+    InvalidFifoWatermark(u16),
+}
+
+impl ConfigurationError {
+    /// Stable code reported to the remote in `DroneResponse::Error`
+    pub fn code(&self) -> u16 {
+        match self {
+            ConfigurationError::Spi(_) => 0x0201,
+            ConfigurationError::Verification(_) => 0x0202,
+            ConfigurationError::InvalidChip => 0x0203,
+            ConfigurationError::Timeout => 0x0206,
+            ConfigurationError::InvalidFifoWatermark(_) => 0x0207,
+        }
+    }
 }
@@ -1,7 +1,31 @@
+use embassy_executor::SpawnToken;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::zerocopy_channel::{Channel, Receiver};
 use static_cell::ConstStaticCell;
 
 pub mod bmi323;
 pub mod lsm6ds3;
+pub mod spi;
 
 const SPI_BUF_LEN: usize = 8192;
 static SPI_BUF: ConstStaticCell<[u8; SPI_BUF_LEN]> = ConstStaticCell::new([0u8; SPI_BUF_LEN]);
+
+/// Common interface of the IMU drivers, so swapping sensors is a type change
+#[allow(async_fn_in_trait)]
+pub trait Imu: Sized {
+    type Config: Default;
+    /// Item produced by the read task
+    type Sample: 'static;
+    type Error: core::fmt::Display;
+
+    async fn configure(&mut self, config: Self::Config) -> Result<(), Self::Error>;
+
+    /// Returns the receiving end of `channel` and the task filling it, which has to be spawned
+    fn start(
+        self,
+        channel: &'static mut Channel<NoopRawMutex, Self::Sample>,
+    ) -> (
+        Receiver<'static, NoopRawMutex, Self::Sample>,
+        SpawnToken<impl Sized>,
+    );
+}
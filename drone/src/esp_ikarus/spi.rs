@@ -0,0 +1,135 @@
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::Mutex;
+use esp_hal::{
+    Async,
+    delay::Delay,
+    dma::{DmaChannelFor, DmaRxBuf, DmaTxBuf},
+    gpio::{
+        Level, Output, OutputConfig, OutputPin,
+        interconnect::{PeripheralInput, PeripheralOutput},
+    },
+    spi::{
+        Error,
+        master::{AnySpi, Instance, SpiDmaBus},
+    },
+    time::Rate,
+};
+use static_cell::StaticCell;
+
+/// SPI transactions to a single device, chip select is asserted for the whole transaction.
+///
+/// Decouples the IMU drivers from the concrete esp-hal bus.
+#[allow(async_fn_in_trait)]
+pub trait SpiTransfer {
+    /// Clocks out `buf` and replaces it with the bytes clocked in at the same time
+    async fn transfer_in_place(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+    async fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+    /// Clocks out `cmd`, then reads `buf.len()` bytes
+    async fn read(&mut self, cmd: &[u8], buf: &mut [u8]) -> Result<(), Error>;
+}
+
+/// SPI bus shared by the devices on it. A transaction holds the lock for its whole chip
+/// select window, so transactions of different devices never interleave.
+pub type SharedSpiBus = Mutex<CriticalSectionRawMutex, SpiDmaBus<'static, Async>>;
+
+/// Sets up the DMA backed bus the [`EspSpi`] devices are attached to.
+///
+/// The ESP32-C6 has a single general purpose SPI peripheral, so there is only one shared bus.
+/// Panics if called twice.
+pub fn shared_bus(
+    spi: impl Instance + 'static,
+    sck: impl OutputPin + 'static,
+    pico: impl PeripheralOutput<'static>,
+    poci: impl PeripheralInput<'static>,
+    dma: impl DmaChannelFor<AnySpi<'static>>,
+) -> &'static SharedSpiBus {
+    use esp_hal::spi::master::*;
+
+    static BUS: StaticCell<SharedSpiBus> = StaticCell::new();
+
+    let (rx_buf, rx_desc, tx_buf, tx_desc) = esp_hal::dma_buffers!(1024);
+
+    let bus = Spi::new(
+        spi,
+        Config::default()
+            .with_frequency(Rate::from_mhz(10))
+            .with_mode(esp_hal::spi::Mode::_0)
+            .with_read_bit_order(esp_hal::spi::BitOrder::MsbFirst)
+            .with_write_bit_order(esp_hal::spi::BitOrder::MsbFirst),
+    )
+    .unwrap()
+    .with_sck(sck)
+    .with_mosi(pico)
+    .with_miso(poci)
+    .with_dma(dma)
+    .with_buffers(
+        DmaRxBuf::new(rx_desc, rx_buf).unwrap(),
+        DmaTxBuf::new(tx_desc, tx_buf).unwrap(),
+    )
+    .into_async();
+
+    BUS.init(Mutex::new(bus))
+}
+
+/// Device on a [`SharedSpiBus`] with a software chip select
+pub struct EspSpi<'d> {
+    bus: &'d SharedSpiBus,
+    cs: Output<'d>,
+    /// Time chip select stays released after a transaction
+    cs_release_us: u32,
+}
+
+impl EspSpi<'static> {
+    pub fn new(
+        bus: &'static SharedSpiBus,
+        cs: impl OutputPin + 'static,
+        cs_release_us: u32,
+    ) -> Self {
+        let cs = Output::new(
+            cs,
+            Level::High,
+            OutputConfig::default().with_drive_mode(esp_hal::gpio::DriveMode::PushPull),
+        );
+
+        Self {
+            bus,
+            cs,
+            cs_release_us,
+        }
+    }
+}
+
+impl EspSpi<'_> {
+    /// Runs `f` with the bus locked and chip select asserted
+    async fn transaction<R>(&mut self, f: impl FnOnce(&mut SpiDmaBus<'static, Async>) -> R) -> R {
+        let mut bus = self.bus.lock().await;
+        critical_section::with(|_cs| {
+            self.cs.set_low();
+            let res = f(&mut bus);
+            self.cs.set_high();
+            // released while still holding the bus, the next device can't start early
+            if self.cs_release_us > 0 {
+                Delay::new().delay_micros(self.cs_release_us);
+            }
+            res
+        })
+    }
+}
+
+impl SpiTransfer for EspSpi<'_> {
+    async fn transfer_in_place(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.transaction(|bus| bus.transfer_in_place(buf)).await
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.transaction(|bus| bus.write(data)).await
+    }
+
+    async fn read(&mut self, cmd: &[u8], buf: &mut [u8]) -> Result<(), Error> {
+        self.transaction(|bus| {
+            bus.write(cmd)?;
+            bus.read(buf)
+        })
+        .await
+    }
+}
@@ -1,11 +1,32 @@
 #![no_std]
+pub mod config_store;
 pub mod defmt;
 pub mod esp_ikarus;
 pub mod motors;
-pub mod sensor_fusion;
+#[cfg(feature = "sim")]
+pub mod sim;
 
-pub trait ImuSample {
-    fn gyro(&self) -> [f32; 3];
-    fn accel(&self) -> [f32; 3];
-    fn dt(&self) -> f32;
+#[cfg(feature = "sim")]
+extern crate std;
+
+/// Extends a wrapping 16 bit hardware counter into a monotonic 64 bit count
+#[derive(Default, Clone, Copy)]
+pub struct TickExtender {
+    last: Option<u16>,
+    wraps: u64,
+}
+
+impl TickExtender {
+    /// Must be called at least once per counter period to not miss wraps
+    pub fn extend(&mut self, ticks: u16) -> u64 {
+        if let Some(last) = self.last
+            && ticks < last
+        {
+            self.wraps += 1;
+        }
+        self.last = Some(ticks);
+        (self.wraps << 16) | ticks as u64
+    }
 }
+
+pub use common_messages::{ImuSample, MergedSample};
@@ -7,27 +7,42 @@
 )]
 
 extern crate alloc;
+use core::cell::RefCell;
 use core::iter::zip;
+use core::sync::atomic::{AtomicBool, Ordering};
 
-use drone::defmt::defmt_data_to_drone_responses;
-use drone::{motors, sensor_fusion};
+use drone::config_store::ConfigStore;
+use drone::defmt::{defmt_data_to_drone_responses, emit_event};
+use drone::motors::{self, MotorDriver};
+use drone::{ImuSample, MergedSample};
 use embassy_futures::select::{Either, select};
+use embassy_futures::yield_now;
 use embassy_sync::{channel, zerocopy_channel};
 use embassy_time::{Duration, Instant, Ticker};
 use esp_backtrace as _;
 
 use alloc::format;
+use alloc::vec::Vec;
 use defmt::{error, info, warn};
-use drone::esp_ikarus::bmi323;
+use drone::esp_ikarus::{Imu, bmi323, spi};
 use embassy_executor::Spawner;
+use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
-use embassy_sync::channel::{Receiver, Sender};
+use embassy_sync::channel::{Receiver, Sender, TrySendError};
+use embassy_sync::signal::Signal;
 use esp_hal::clock::CpuClock;
 use esp_hal::peripherals::{Peripherals, SW_INTERRUPT, TIMG0, WIFI};
 use esp_hal::timer::timg::TimerGroup;
+use esp_storage::FlashStorage;
 
 use common_esp::{mpmc_channel, spsc_channel};
-use common_messages::{DroneResponse, PingTarget, RemoteRequest, Telemetry};
+use common_messages::{
+    AltitudeEstimator, Blackbox, BlackboxFrame, ComplementaryFilterFusion, ControlConfig,
+    ControlMode, DroneResponse, EspNowChannel, GitHash, HeartbeatTimer, ImuOffsets, LogLevel,
+    MotorOutput, Peer, PingTarget, RateLimiter, RateMonitor, RemoteRequest, SCHEMA_VERSION,
+    SelfTestChecks, THROTTLE_STOP, Telemetry, drain_latest, map_motor_throttles, motor_test,
+    parse_git_hash, send_drop_oldest, sticks_to_setpoint,
+};
 
 // This creates a default app-descriptor required by the esp-idf bootloader.
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
@@ -36,23 +51,91 @@ esp_bootloader_esp_idf::esp_app_desc!();
 // Restart the system on panic
 #[unsafe(no_mangle)]
 pub fn custom_halt() -> ! {
+    // Silence the escs first, GPIO19 is the motor data line passed to `Motors::oneshot125`
+    let _data = motors::hold_data_line_low(unsafe { esp_hal::peripherals::GPIO19::steal() });
     esp_hal::system::software_reset()
 }
 
-const MOTOR_FRONT_LEFT_IDX: usize = 1;
-const MOTOR_FRONT_LEFT_REV: bool = false;
-
-const MOTOR_FRONT_RIGHT_IDX: usize = 2;
-const MOTOR_FRONT_RIGHT_REV: bool = true;
-
-const MOTOR_BACK_RIGHT_IDX: usize = 3;
-const MOTOR_BACK_RIGHT_REV: bool = false;
-
-const MOTOR_BACK_LEFT_IDX: usize = 0;
-const MOTOR_BACK_LEFT_REV: bool = true;
-
-const UNCONFIRMED_ARM_TIME: Duration = Duration::from_millis(500);
-const IDLE_THRUST: f32 = 70.0;
+const DEFAULT_CONFIG: ControlConfig = ControlConfig {
+    kp: [25.0; 3],
+    ki: [0.0; 3],
+    kd: [10.0; 3],
+    alpha: 0.95,
+    // smoothing against motor vibrations
+    accel_lowpass: 0.1,
+    idle_thrust: 70.0,
+    thrust_expo: 0.0,
+    arm_timeout_ms: 500,
+    settle_ms: 2000,
+    max_tilt_deg: 60.0,
+    control_rate_hz: 1600,
+    throttle_deadband: 30.0,
+    // esc outputs front left, front right, back right, back left
+    motor_outputs: [
+        MotorOutput {
+            mixer_index: 1,
+            reversed: false,
+        },
+        MotorOutput {
+            mixer_index: 2,
+            reversed: true,
+        },
+        MotorOutput {
+            mixer_index: 3,
+            reversed: false,
+        },
+        MotorOutput {
+            mixer_index: 0,
+            reversed: true,
+        },
+    ],
+};
+/// Bounds of [`ControlConfig::control_rate_hz`]
+const CONTROL_RATE_HZ: core::ops::RangeInclusive<u32> = 50..=4000;
+/// Maximum throttle increase per second, a motor spins up from stop to full throttle in 125ms
+/// regardless of the control rate
+const MOTOR_RAMP_RATE: f32 = 8_000.0;
+const MOTOR_TEST_TIME: Duration = Duration::from_secs(2);
+/// [`DroneResponse::Error`] code for non-finite values in the control loop
+const ERROR_NON_FINITE: u16 = 0x0301;
+/// [`DroneResponse::Error`] code for a disarm caused by exceeding the tilt limit
+const ERROR_TILT_CUTOFF: u16 = 0x0302;
+/// [`DroneResponse::Event`] code for arming, with the thrust at that time as first argument
+const EVENT_ARMED: u16 = 0x0402;
+/// [`DroneResponse::Event`] code for disarming, on request or by the control loop
+const EVENT_DISARMED: u16 = 0x0403;
+/// [`DroneResponse::Event`] code for the failsafe disarm after a missed arm confirmation
+const EVENT_ARM_TIMEOUT: u16 = 0x0404;
+
+/// Time constant in seconds of the barometer correction in the altitude estimate
+const ALTITUDE_TIME_CONSTANT: f32 = 2.0;
+/// kp, ki and kd of the altitude hold, thrust per meter
+const ALTITUDE_GAINS: [f32; 3] = [50.0, 0.01, 0.0];
+
+/// Commit the firmware was built from, see [`DroneResponse::Version`]
+const GIT_HASH: GitHash = parse_git_hash(env!("GIT_HASH"));
+
+/// Window over which the achieved control loop rate is averaged
+const LOOP_RATE_WINDOW: Duration = Duration::from_secs(1);
+
+const DEFAULT_TELEMETRY_INTERVAL: Duration = Duration::from_millis(250);
+const MIN_TELEMETRY_INTERVAL: Duration = Duration::from_millis(20);
+static TELEMETRY_INTERVAL: Signal<CriticalSectionRawMutex, Duration> = Signal::new();
+/// Set by a disarm request to abort the ESC arming sequence
+static ABORT_ARMING: AtomicBool = AtomicBool::new(false);
+/// Set by the control loop when it disarmed on its own, the request handler then requires a
+/// new arm request
+static FORCE_DISARMED: AtomicBool = AtomicBool::new(false);
+/// Latched by [`RemoteRequest::EmergencyStop`], only a reset clears it
+static EMERGENCY_STOP: AtomicBool = AtomicBool::new(false);
+
+/// Control loop frames kept for post-crash analysis, ~0.6s at 1.6kHz
+const BLACKBOX_FRAMES: usize = 1024;
+static BLACKBOX: Mutex<CriticalSectionRawMutex, RefCell<Blackbox<BLACKBOX_FRAMES>>> =
+    Mutex::new(RefCell::new(Blackbox::new()));
+/// Set while the blackbox is sent, recording is paused meanwhile to keep the dump consistent
+static BLACKBOX_DUMPING: AtomicBool = AtomicBool::new(false);
+static DUMP_BLACKBOX: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) -> ! {
@@ -71,8 +154,9 @@ async fn main(spawner: Spawner) -> ! {
             remote.sender(),
         ));
         spawner.must_spawn(defmt_data_to_drone_responses(drone.sender()));
+        spawner.must_spawn(watch_link());
 
-        (remote.receiver(), drone.sender())
+        (remote.receiver(), drone)
     };
 
     let mut imu_data = {
@@ -96,115 +180,365 @@ async fn main(spawner: Spawner) -> ! {
 
         embassy_time::Timer::after_millis(500).await;
 
-        let mut imu = bmi323::BMI323::new(imu_spi, sck, pico, poci, imu_dma, imu_cs, imu_int1);
-        if let Err(err) = imu.configure().await {
-            error!("{}", format!("{err}"));
+        // further sensors attach to the same bus with their own chip select
+        let spi_bus = spi::shared_bus(imu_spi, sck, pico, poci, imu_dma);
+        let mut imu = bmi323::BMI323::new(spi_bus, imu_cs, imu_int1);
+        if let Err(err) = Imu::configure(&mut imu, Default::default()).await {
+            let detail = format!("{err}");
+            error!("{}", detail);
+            imu.dump_registers().await;
+            drone_responses
+                .send(DroneResponse::error(err.code(), &detail))
+                .await;
+            // give esp-now a chance to deliver the error before resetting
+            embassy_time::Timer::after_millis(200).await;
             panic!("{}", err);
         }
 
         info!("IMU initialized!");
 
-        let (imu_data_tx, imu_data_rx) = spsc_channel!(bmi323::Sample, 32).split();
-        spawner.must_spawn(bmi323::read_imu(imu, imu_data_tx));
+        let (imu_data_rx, read_imu) = imu.start(spsc_channel!(bmi323::Sample, 32));
+        spawner.must_spawn(read_imu);
 
         imu_data_rx
     };
 
+    let mut config_store = ConfigStore::new(FlashStorage::new(peripherals.FLASH));
+    let config = config_store
+        .load()
+        .filter(ControlConfig::motor_outputs_valid)
+        .unwrap_or_else(|| {
+            warn!("no valid config stored, using defaults");
+            DEFAULT_CONFIG
+        });
+
+    let mut inputs = {
+        let (tx, rx) = spsc_channel!(Input, 16).split();
+        spawner.must_spawn(handle_remote_requests(
+            remote_reqests,
+            drone_responses,
+            tx,
+            config_store,
+            config,
+        ));
+        rx
+    };
+
     let mut motors = motors::Motors::oneshot125(
         peripherals.RMT,
         peripherals.GPIO19,
         (peripherals.GPIO6, peripherals.GPIO20),
     )
-    .await;
-    motors.arm_oneshot().await;
-
-    let mut fusion = sensor_fusion::ComplementaryFilterFusion::new(
-        0.95, [0.0; 3], [0.0; 3], [25.0; 3], [0.0; 3], [10.0; 3],
+    .await
+    .with_ramp_limit(MOTOR_RAMP_RATE);
+    // disarmed motors are held at idle by the control loop below
+    motors.arm(&ABORT_ARMING).await;
+
+    let mut fusion = ComplementaryFilterFusion::builder()
+        .alpha(config.alpha)
+        .kp(config.kp)
+        .ki(config.ki)
+        .kd(config.kd)
+        .accel_lowpass(config.accel_lowpass)
+        .build();
+    let mut altitude = AltitudeEstimator::new(
+        ALTITUDE_TIME_CONSTANT,
+        ALTITUDE_GAINS[0],
+        ALTITUDE_GAINS[1],
+        ALTITUDE_GAINS[2],
     );
 
     let mut telemetry = {
         let (tx, rx) = spsc_channel!(Telemetry, 1).split();
         spawner.must_spawn(log_send_telementry(rx, drone_responses));
+        spawner.must_spawn(send_blackbox(drone_responses));
         tx
     };
 
-    let mut inputs = {
-        let (tx, rx) = spsc_channel!(Input, 16).split();
-        spawner.must_spawn(handle_remote_requests(remote_reqests, drone_responses, tx));
-        rx
-    };
-
     let mut thrust = 0.0;
+    let mut idle_thrust = config.idle_thrust;
+    let mut thrust_expo = config.thrust_expo;
+    let mut throttle_deadband = config.throttle_deadband;
+    let mut motor_outputs = config.motor_outputs;
+    let mut max_tilt = config.max_tilt_deg;
     let mut armed = false;
+    let mut dry_run = false;
     let mut motors_saturated = false;
+    let mut motor_test: Option<(usize, f32, Instant)> = None;
+    let mut self_test: Option<SelfTestChecks> = None;
+    let mut non_finite = false;
+    let mut report_calibration = false;
+    // motors stay off until the filter has settled after boot
+    let boot = Instant::now();
+    let mut settle_until = boot.saturating_add(Duration::from_millis(config.settle_ms.into()));
+    let mut live = false;
+    let mut heartbeat = HeartbeatTimer::default();
+    let mut loop_rate = RateMonitor::new(LOOP_RATE_WINDOW.as_micros());
+    let mut telemetry_rate = RateLimiter::new(DEFAULT_TELEMETRY_INTERVAL.as_micros());
+    let control_ticker = |rate_hz: u32| {
+        Ticker::every(Duration::from_hz(
+            rate_hz.clamp(*CONTROL_RATE_HZ.start(), *CONTROL_RATE_HZ.end()) as u64,
+        ))
+    };
+    let mut control = control_ticker(config.control_rate_hz);
 
     loop {
-        if let Some(input) = inputs.try_receive() {
+        // everything pending, a batch of requests takes effect at once
+        while let Some(input) = inputs.try_receive() {
             match input {
                 Input::Armed(true) => {
                     armed = true;
+                    motor_test = None;
                     info!("armed main");
                 }
                 Input::Armed(false) => {
                     armed = false;
                     info!("disarmed main");
                 }
+                Input::MotorTest { index, throttle } => {
+                    let end = Instant::now().saturating_add(MOTOR_TEST_TIME);
+                    motor_test = Some((*index, *throttle, end));
+                    info!("testing motor {} at {}", index, throttle);
+                }
+                Input::SelfTest { link_ms } => {
+                    self_test = Some(SelfTestChecks::new(*link_ms, boot.elapsed().as_micros()));
+                    info!("self test started");
+                }
                 Input::Target(new_target) => fusion.set_target(*new_target),
-                Input::Thrust(new_thrust) => thrust = *new_thrust,
+                Input::ControlMode(mode) => {
+                    fusion.set_mode(*mode);
+                    info!("control mode: {}", mode);
+                }
+                Input::Alpha(alpha) => {
+                    if !fusion.set_alpha(*alpha) {
+                        warn!("invalid filter alpha {}, keeping {}", alpha, fusion.alpha());
+                    }
+                }
+                Input::Thrust(new_thrust) if new_thrust.is_finite() => thrust = *new_thrust,
+                Input::Thrust(new_thrust) => warn!("ignoring non-finite thrust {}", new_thrust),
                 Input::Tune { kp, ki, kd } => {
                     for i in 0..3 {
-                        fusion.pid[i].k_p = kp[i];
-                        fusion.pid[i].k_i = ki[i];
-                        // reset sum for integral term
+                        fusion.pid[i].set_gains_bumpless(kp[i], ki[i], kd[i]);
+                    }
+                }
+                Input::Config(config) => {
+                    for i in 0..3 {
+                        fusion.pid[i].k_p = config.kp[i];
+                        fusion.pid[i].k_i = config.ki[i];
                         fusion.pid[i].sum = 0.0;
-                        fusion.pid[i].k_d = kd[i];
+                        fusion.pid[i].k_d = config.kd[i];
+                    }
+                    if !fusion.set_alpha(config.alpha) {
+                        warn!(
+                            "invalid filter alpha {}, keeping {}",
+                            config.alpha,
+                            fusion.alpha()
+                        );
                     }
+                    fusion.set_accel_lowpass(config.accel_lowpass);
+                    idle_thrust = config.idle_thrust;
+                    thrust_expo = config.thrust_expo;
+                    throttle_deadband = config.throttle_deadband;
+                    motor_outputs = config.motor_outputs;
+                    max_tilt = config.max_tilt_deg;
+                    control = control_ticker(config.control_rate_hz);
+                    settle_until =
+                        boot.saturating_add(Duration::from_millis(config.settle_ms.into()));
+                    info!("config applied");
+                }
+                Input::CalibrateGyro => {
+                    fusion.calibrate_gyro();
+                    info!("gyro calibration started");
+                }
+                Input::Calibrate => {
+                    fusion.calibrate();
+                    report_calibration = true;
+                    info!("calibration started");
+                }
+                Input::DryRun(enabled) => {
+                    dry_run = *enabled;
+                    info!("dry run: {}", dry_run);
                 }
             }
             inputs.receive_done();
         }
 
-        let imu_sample = imu_data.receive().await;
+        // Run at the control rate on everything the imu delivered since the last update
+        control.next().await;
+        loop_rate.record(boot.elapsed().as_micros());
+        let mut imu_sample = MergedSample::new(*imu_data.receive().await);
+        imu_data.receive_done();
+        while let Some(sample) = imu_data.try_receive() {
+            imu_sample.merge(*sample);
+            imu_data.receive_done();
+        }
+        let [gx, gy, gz] = imu_sample.gyro();
+        let [ax, ay, az] = imu_sample.accel();
         defmt::debug!(
-            "imu: roll={:02}, \tpitch={:02}, \tyaw={:02}, \t\tax={:02}, \tay={:02}, \taz={:02}, \ttime={}",
-            imu_sample.gyro[0],
-            imu_sample.gyro[1],
-            imu_sample.gyro[2],
-            imu_sample.accl[0],
-            imu_sample.accl[1],
-            imu_sample.accl[2],
-            imu_sample.time,
+            "imu: roll={:02}, \tpitch={:02}, \tyaw={:02}, \t\tax={:02}, \tay={:02}, \taz={:02}, \ttime={}, \tsamples={}",
+            gx,
+            gy,
+            gz,
+            ax,
+            ay,
+            az,
+            imu_sample.timestamp(),
+            imu_sample.count(),
         );
-        let [roll, pitch, yaw] = fusion.advance(*imu_sample, motors_saturated);
-        imu_data.receive_done();
+        let was_calibrating = fusion.gyro_bias().is_calibrating();
+        let altitude_correction = altitude.advance(imu_sample);
+        let [roll, pitch, yaw] = fusion.advance(imu_sample, motors_saturated);
+        let fusion_non_finite = fusion.take_non_finite();
+        if was_calibrating && !fusion.gyro_bias().is_calibrating() {
+            let offsets = ImuOffsets {
+                gyro: fusion.gyro_bias().bias(),
+                accel: fusion.gyro_bias().accel_offset(),
+            };
+            info!(
+                "gyro bias calibrated: {}, accel offset: {}",
+                offsets.gyro, offsets.accel
+            );
+            if core::mem::take(&mut report_calibration) {
+                // best effort, never block the control loop
+                let _ = drone_responses.try_send(DroneResponse::CalibrationResult(Some(offsets)));
+            }
+        }
 
+        if armed && fusion.tilt_cutoff(max_tilt) {
+            armed = false;
+            FORCE_DISARMED.store(true, Ordering::Relaxed);
+            let [roll, pitch, _] = fusion.orientation();
+            error!(
+                "tilt cutoff at roll={} pitch={}, limit {}, disarming",
+                roll, pitch, max_tilt
+            );
+            // best effort, never block the control loop
+            let _ =
+                drone_responses.try_send(DroneResponse::error(ERROR_TILT_CUTOFF, "tilt cutoff"));
+        }
+
+        let shaped_thrust = common_messages::thrust_expo(thrust, thrust_expo) + altitude_correction;
         let motor_throttles = [
-            thrust - roll - pitch + yaw,
-            thrust + roll - pitch - yaw,
-            thrust + roll + pitch + yaw,
-            thrust - roll + pitch - yaw,
+            shaped_thrust - roll - pitch + yaw,
+            shaped_thrust + roll - pitch - yaw,
+            shaped_thrust + roll + pitch + yaw,
+            shaped_thrust - roll + pitch - yaw,
         ];
 
-        let clamped_throttles = motor_throttles
-            // .map(|f| f.clamp(-1000.0, 1000.0));
-            .map(|f| f.clamp(IDLE_THRUST, 1000.0));
+        let mixer_non_finite = motor_throttles.iter().any(|f| !f.is_finite());
+        // only report the first of consecutive occurrences
+        let was_non_finite =
+            core::mem::replace(&mut non_finite, fusion_non_finite || mixer_non_finite);
+        if non_finite && !was_non_finite {
+            error!(
+                "non-finite value in control loop, fusion: {}, mixer: {}",
+                fusion_non_finite, mixer_non_finite
+            );
+            // best effort, never block the control loop
+            let _ = drone_responses.try_send(DroneResponse::error(
+                ERROR_NON_FINITE,
+                if mixer_non_finite {
+                    "mixer NaN"
+                } else {
+                    "fusion NaN"
+                },
+            ));
+        }
+
+        let clamped_throttles = if mixer_non_finite {
+            [idle_thrust; 4]
+        } else {
+            motor_throttles
+                // .map(|f| f.clamp(-1000.0, 1000.0));
+                .map(|f| f.clamp(idle_thrust, 1000.0))
+        };
 
         motors_saturated =
             zip(motor_throttles, clamped_throttles).any(|(raw, clamped)| raw > clamped);
 
-        let mapped_motor_throttles = map_motor_throttles(clamped_throttles);
-        if armed {
+        if !live && Instant::now() >= settle_until {
+            live = true;
+            info!(
+                "settled after {}ms, controller live",
+                boot.elapsed().as_millis()
+            );
+        }
+
+        if EMERGENCY_STOP.load(Ordering::Relaxed) {
+            armed = false;
+            motor_test = None;
+        }
+
+        if let Some(checks) = self_test.as_mut() {
+            let now_us = boot.elapsed().as_micros();
+            let finite = imu_sample
+                .gyro()
+                .iter()
+                .chain(&imu_sample.accel())
+                .all(|v| v.is_finite());
+            checks.record_sample(imu_sample.timestamp(), finite);
+            // arming or an emergency stop end the test, failing the motors not spun yet
+            let aborted = armed || !live || EMERGENCY_STOP.load(Ordering::Relaxed);
+            match checks.wiggle(now_us).filter(|_| !aborted) {
+                Some(index) => {
+                    let end = Instant::now()
+                        .saturating_add(Duration::from_micros(SelfTestChecks::WIGGLE_US));
+                    motor_test = Some((index, SelfTestChecks::WIGGLE_THROTTLE, end));
+                }
+                None => {
+                    let result = checks.result(now_us);
+                    info!("self test finished: {}", result);
+                    send_latest(drone_responses, result);
+                    self_test = None;
+                    motor_test = None;
+                }
+            }
+        }
+
+        let mapped_motor_throttles = map_motor_throttles(
+            clamped_throttles.map(|t| common_messages::throttle_deadband(t, throttle_deadband)),
+            &motor_outputs,
+        );
+        if !BLACKBOX_DUMPING.load(Ordering::Relaxed) {
+            let frame = BlackboxFrame {
+                timestamp: boot.elapsed().as_micros(),
+                orientation: fusion.orientation(),
+                target: fusion.target(),
+                throttles: mapped_motor_throttles,
+                armed,
+            };
+            BLACKBOX.lock(|blackbox| blackbox.borrow_mut().record(frame));
+        }
+        if !live {
+            motor_test = None;
+            motors.send_throttles([THROTTLE_STOP; 4]);
+        } else if armed && !dry_run {
             motors.send_throttles(mapped_motor_throttles);
+        } else if armed {
+            // the intended throttles still go to telemetry and the blackbox
+            motors.send_throttles([THROTTLE_STOP; 4]);
+        } else if let Some((index, throttle, end)) = motor_test
+            && Instant::now() <= end
+        {
+            let mut test_throttles = [0.0; 4];
+            test_throttles[index] = common_messages::throttle_deadband(throttle, throttle_deadband);
+            motors.send_throttles(map_motor_throttles(test_throttles, &motor_outputs));
         } else {
-            motors.send_throttles([1000; 4]);
+            motor_test = None;
+            motors.send_throttles([THROTTLE_STOP; 4]);
         }
 
-        if !armed || thrust < IDLE_THRUST {
+        if !live || !armed || thrust < idle_thrust {
             // reset PID integrator when disarmed or low thrust
             fusion.pid.iter_mut().for_each(|pid| pid.sum = 0.0);
         }
 
-        if let Some(msg) = telemetry.try_send() {
+        if let Some(interval) = TELEMETRY_INTERVAL.try_take() {
+            telemetry_rate.set_interval(interval.as_micros());
+        }
+        if telemetry_rate.ready(boot.elapsed().as_micros())
+            && let Some(msg) = telemetry.try_send()
+        {
             *msg = Telemetry {
                 timestamp: Instant::now().as_millis(),
                 orientation: fusion.orientation(),
@@ -212,9 +546,17 @@ async fn main(spawner: Spawner) -> ! {
                 armed,
                 output: [roll, pitch, yaw],
                 throttles: mapped_motor_throttles,
+                rssi: common_esp::with_peers(|peers| peers.latest().map(Peer::telemetry_rssi)),
+                live,
+                loop_rate_hz: loop_rate.rate_hz(),
             };
             telemetry.send_done();
         };
+
+        if let Some(beat) = heartbeat.poll(boot.elapsed().as_millis()) {
+            // best effort, never block the control loop
+            let _ = drone_responses.try_send(beat);
+        }
     }
 }
 
@@ -227,19 +569,48 @@ enum Input {
         kd: [f32; 3],
     },
     Armed(bool),
+    MotorTest {
+        index: usize,
+        throttle: f32,
+    },
+    Alpha(f32),
+    Config(ControlConfig),
+    CalibrateGyro,
+    Calibrate,
+    DryRun(bool),
+    SelfTest {
+        link_ms: Option<u16>,
+    },
+    ControlMode(ControlMode),
 }
 
 #[embassy_executor::task]
 async fn handle_remote_requests(
     remote_requests: channel::Receiver<'static, CriticalSectionRawMutex, RemoteRequest, 64>,
-    drone_responses: channel::Sender<'static, CriticalSectionRawMutex, DroneResponse, 64>,
+    drone_responses: &'static DroneResponses,
     mut inputs: zerocopy_channel::Sender<'static, NoopRawMutex, Input>,
+    mut config_store: ConfigStore<FlashStorage<'static>>,
+    mut config: ControlConfig,
 ) -> ! {
     let mut armed = false;
-    let mut arm_ticker = Ticker::every(UNCONFIRMED_ARM_TIME);
+    let mut arm_ticker = Ticker::every(Duration::from_millis(config.arm_timeout_ms.into()));
     let mut thrust = 0.0;
+    // sticks are scaled to angles or rates before they reach the control loop
+    let mut control_mode = ControlMode::Angle;
 
     loop {
+        if FORCE_DISARMED.swap(false, Ordering::Relaxed) && armed {
+            armed = false;
+            send_latest(drone_responses, DroneResponse::ArmState(armed));
+            emit_event(
+                &drone_responses.sender(),
+                LogLevel::Info,
+                EVENT_DISARMED,
+                [0.0; 2],
+            )
+            .await;
+        }
+
         let Either::First(remote_req) = select(remote_requests.receive(), arm_ticker.next()).await
         else {
             if armed {
@@ -247,122 +618,378 @@ async fn handle_remote_requests(
                 armed = false;
                 *inputs.send().await = Input::Armed(false);
                 inputs.send_done();
+                emit_event(
+                    &drone_responses.sender(),
+                    LogLevel::Warn,
+                    EVENT_ARM_TIMEOUT,
+                    [0.0; 2],
+                )
+                .await;
             }
 
             // Not armed, ignoring
             continue;
         };
 
-        match remote_req {
-            RemoteRequest::Ping(target @ PingTarget::Drone, id) => {
-                drone_responses.send(DroneResponse::Pong(target, id)).await;
+        // handle the whole burst now instead of one request per wakeup
+        let pending = core::iter::once(remote_req)
+            .chain(core::iter::from_fn(|| remote_requests.try_receive().ok()));
+        for remote_req in drain_latest(pending) {
+            if let RemoteRequest::EmergencyStop = remote_req {
+                error!("emergency stop");
+                EMERGENCY_STOP.store(true, Ordering::Relaxed);
+                ABORT_ARMING.store(true, Ordering::Relaxed);
+                armed = false;
+                send_latest(drone_responses, DroneResponse::ArmState(armed));
+                continue;
             }
-            RemoteRequest::SetArm(true) => {
-                if thrust > 10.0 {
-                    warn!("drone may not arm when thrust not zero");
-                } else {
-                    info!("armed");
-                    armed = true;
-                    arm_ticker.reset();
-                    *inputs.send().await = Input::Armed(true);
-                    inputs.send_done();
-                }
-
-                drone_responses.send(DroneResponse::ArmState(armed)).await;
+            if EMERGENCY_STOP.load(Ordering::Relaxed) && !matches!(remote_req, RemoteRequest::Reset)
+            {
+                warn!(
+                    "emergency stop latched, ignoring {} until reset",
+                    remote_req
+                );
+                continue;
             }
-            RemoteRequest::SetArm(false) => {
-                info!("disarmed");
-                armed = false;
-                *inputs.send().await = Input::Armed(false);
-                inputs.send_done();
 
-                drone_responses.send(DroneResponse::ArmState(armed)).await;
-            }
-            RemoteRequest::ArmConfirm => {
-                if armed {
-                    arm_ticker.reset();
-                } else {
-                    warn!("tried to arm confirm unarmed drone");
+            let (single, batch) = match remote_req {
+                RemoteRequest::Batch(batch) if RemoteRequest::is_valid_batch(&batch) => {
+                    // the control loop drains all inputs at once, so starting from an empty queue
+                    // the whole batch is applied within one iteration
+                    while !inputs.is_empty() {
+                        yield_now().await;
+                    }
+                    (None, batch)
                 }
-            }
-            RemoteRequest::SetThrust(new_thrust) => {
-                thrust = new_thrust;
-                *inputs.send().await = Input::Thrust(new_thrust);
-                inputs.send_done();
-                *inputs.send().await = Input::Thrust(new_thrust);
-                inputs.send_done();
-            }
-            RemoteRequest::SetTarget(target) => {
-                *inputs.send().await = Input::Target(target);
-                inputs.send_done();
-            }
-            RemoteRequest::SetTune { kp, ki, kd } => {
-                *inputs.send().await = Input::Tune { kp, ki, kd };
-                inputs.send_done();
-            }
-            RemoteRequest::Reset => {
-                if armed && thrust > 10.0 {
-                    warn!("tried to reset armed and active drone");
+                RemoteRequest::Batch(batch) => {
+                    warn!(
+                        "rejecting batch of {} requests, too long or nested",
+                        batch.len()
+                    );
+                    continue;
+                }
+                req => (Some(req), Vec::new()),
+            };
+
+            for remote_req in single.into_iter().chain(batch) {
+                match remote_req {
+                    RemoteRequest::Ping(target @ PingTarget::Drone, id) => {
+                        send_latest(drone_responses, DroneResponse::Pong(target, id));
+                    }
+                    RemoteRequest::SetArm(true) => {
+                        if thrust > 10.0 {
+                            warn!("drone may not arm when thrust not zero");
+                        } else {
+                            info!("armed");
+                            armed = true;
+                            arm_ticker.reset();
+                            *inputs.send().await = Input::Armed(true);
+                            inputs.send_done();
+                            emit_event(
+                                &drone_responses.sender(),
+                                LogLevel::Info,
+                                EVENT_ARMED,
+                                [thrust, 0.0],
+                            )
+                            .await;
+                        }
+
+                        send_latest(drone_responses, DroneResponse::ArmState(armed));
+                    }
+                    RemoteRequest::SetArm(false) => {
+                        info!("disarmed");
+                        ABORT_ARMING.store(true, Ordering::Relaxed);
+                        armed = false;
+                        *inputs.send().await = Input::Armed(false);
+                        inputs.send_done();
+                        emit_event(
+                            &drone_responses.sender(),
+                            LogLevel::Info,
+                            EVENT_DISARMED,
+                            [0.0; 2],
+                        )
+                        .await;
+
+                        send_latest(drone_responses, DroneResponse::ArmState(armed));
+                    }
+                    RemoteRequest::ArmConfirm => {
+                        if armed {
+                            arm_ticker.reset();
+                        } else {
+                            warn!("tried to arm confirm unarmed drone");
+                        }
+                    }
+                    RemoteRequest::SetThrust(new_thrust) => {
+                        thrust = new_thrust;
+                        *inputs.send().await = Input::Thrust(new_thrust);
+                        inputs.send_done();
+                        *inputs.send().await = Input::Thrust(new_thrust);
+                        inputs.send_done();
+                    }
+                    RemoteRequest::SetTarget(target) => {
+                        *inputs.send().await = Input::Target(target);
+                        inputs.send_done();
+                    }
+                    RemoteRequest::Sticks {
+                        roll,
+                        pitch,
+                        yaw,
+                        thrust: throttle,
+                    } => {
+                        let (target, new_thrust) = sticks_to_setpoint(roll, pitch, yaw, throttle);
+                        let target = control_mode.stick_target(target);
+                        thrust = new_thrust;
+                        *inputs.send().await = Input::Target(target);
+                        inputs.send_done();
+                        *inputs.send().await = Input::Thrust(new_thrust);
+                        inputs.send_done();
+                    }
+                    RemoteRequest::SetTune { kp, ki, kd } => {
+                        (config.kp, config.ki, config.kd) = (kp, ki, kd);
+                        *inputs.send().await = Input::Tune { kp, ki, kd };
+                        inputs.send_done();
+                    }
+                    RemoteRequest::MotorTest { index, throttle } => {
+                        match motor_test(index, throttle, armed) {
+                            Ok((index, throttle)) => {
+                                *inputs.send().await = Input::MotorTest { index, throttle };
+                                inputs.send_done();
+                            }
+                            Err(rejected) => warn!("motor test rejected: {}", rejected),
+                        }
+                    }
+                    RemoteRequest::SetAlpha(alpha) => {
+                        if (0.0..=1.0).contains(&alpha) {
+                            config.alpha = alpha;
+                        }
+                        *inputs.send().await = Input::Alpha(alpha);
+                        inputs.send_done();
+                    }
+                    RemoteRequest::SetConfig(new_config) => {
+                        let motor_outputs = config.motor_outputs;
+                        config = new_config;
+                        if !(0.0..=1.0).contains(&config.alpha) {
+                            config.alpha = DEFAULT_CONFIG.alpha;
+                        }
+                        if !config.motor_outputs_valid() {
+                            warn!(
+                                "invalid motor outputs {}, keeping {}",
+                                config.motor_outputs, motor_outputs
+                            );
+                            config.motor_outputs = motor_outputs;
+                        }
+                        arm_ticker =
+                            Ticker::every(Duration::from_millis(config.arm_timeout_ms.into()));
+                        *inputs.send().await = Input::Config(config);
+                        inputs.send_done();
+
+                        drone_responses.send(DroneResponse::Config(config)).await;
+                    }
+                    RemoteRequest::GetConfig => {
+                        drone_responses.send(DroneResponse::Config(config)).await;
+                    }
+                    RemoteRequest::SaveConfig => {
+                        // flash writes stall the cpu, never do that in flight
+                        let saved = if armed {
+                            warn!("config may not be saved while armed");
+                            false
+                        } else if let Err(err) = config_store.save(&config) {
+                            error!("unable to save config: {}", defmt::Debug2Format(&err));
+                            false
+                        } else {
+                            info!("config saved");
+                            true
+                        };
+                        drone_responses
+                            .send(DroneResponse::ConfigSaved(saved))
+                            .await;
+                    }
+                    RemoteRequest::CalibrateGyro => {
+                        if armed {
+                            warn!("gyro calibration rejected while armed");
+                        } else {
+                            *inputs.send().await = Input::CalibrateGyro;
+                            inputs.send_done();
+                        }
+                    }
+                    RemoteRequest::Calibrate => {
+                        if armed {
+                            warn!("calibration rejected while armed");
+                            drone_responses
+                                .send(DroneResponse::CalibrationResult(None))
+                                .await;
+                        } else {
+                            *inputs.send().await = Input::Calibrate;
+                            inputs.send_done();
+                        }
+                    }
+                    RemoteRequest::SetDryRun(false) if armed => {
+                        warn!("dry run may not be left while armed");
+                    }
+                    RemoteRequest::SetDryRun(enabled) => {
+                        *inputs.send().await = Input::DryRun(enabled);
+                        inputs.send_done();
+                    }
+                    RemoteRequest::DumpBlackbox => {
+                        if armed {
+                            warn!("blackbox dump rejected while armed");
+                        } else {
+                            DUMP_BLACKBOX.signal(());
+                        }
+                    }
+                    RemoteRequest::GetVersion => {
+                        drone_responses
+                            .send(DroneResponse::Version {
+                                git_hash: GIT_HASH,
+                                schema: SCHEMA_VERSION,
+                            })
+                            .await;
+                    }
+                    RemoteRequest::SetControlMode(mode) => {
+                        control_mode = mode;
+                        *inputs.send().await = Input::ControlMode(mode);
+                        inputs.send_done();
+                    }
+                    RemoteRequest::SelfTest { link_ms } => {
+                        if armed {
+                            warn!("self test rejected while armed");
+                        } else {
+                            *inputs.send().await = Input::SelfTest { link_ms };
+                            inputs.send_done();
+                        }
+                    }
+                    RemoteRequest::DumpImu => {
+                        if armed {
+                            warn!("imu register dump rejected while armed");
+                        } else {
+                            bmi323::DUMP_REGISTERS.signal(());
+                        }
+                    }
+                    RemoteRequest::SetTelemetryInterval(interval_ms) => {
+                        let interval =
+                            Duration::from_millis(interval_ms as u64).max(MIN_TELEMETRY_INTERVAL);
+                        info!("telemetry interval set to {}ms", interval.as_millis());
+                        TELEMETRY_INTERVAL.signal(interval);
+                    }
+                    RemoteRequest::Reset => {
+                        if armed && thrust > 10.0 {
+                            warn!("tried to reset armed and active drone");
+                        }
+                        esp_hal::system::software_reset();
+                    }
+                    req => warn!("unknown remote request received: {}", req),
                 }
-                esp_hal::system::software_reset();
             }
-            req => warn!("unknown remote request received: {}", req),
         }
     }
 }
 
+#[embassy_executor::task]
+async fn send_blackbox(drone_responses: &'static DroneResponses) -> ! {
+    loop {
+        DUMP_BLACKBOX.wait().await;
+        BLACKBOX_DUMPING.store(true, Ordering::Relaxed);
+
+        let len = BLACKBOX.lock(|blackbox| blackbox.borrow().len());
+        if len == 0 {
+            warn!("blackbox is empty");
+        }
+        info!("sending {} blackbox frames", len);
+        for i in 0..len {
+            let Some(frame) = BLACKBOX.lock(|blackbox| blackbox.borrow().get(i)) else {
+                break;
+            };
+            let remaining = (len - i - 1).min(u16::MAX as usize) as u16;
+            drone_responses
+                .send(DroneResponse::BlackboxFrame { remaining, frame })
+                .await;
+        }
+
+        BLACKBOX_DUMPING.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Responses queued for esp-now
+type DroneResponses = channel::Channel<CriticalSectionRawMutex, DroneResponse, 64>;
+
+/// Queues a latency sensitive response without waiting on a congested radio, evicting the
+/// oldest queued responses instead. Bulk and one-off responses wait for room with `send`.
+fn send_latest(drone_responses: &DroneResponses, res: DroneResponse) {
+    let dropped = send_drop_oldest(
+        res,
+        |res| {
+            drone_responses
+                .try_send(res)
+                .map_err(|TrySendError::Full(res)| res)
+        },
+        || drone_responses.try_receive().ok(),
+    );
+    if let Some(dropped) = dropped {
+        defmt::debug!("response queue full, dropped {}", dropped);
+    }
+}
+
 #[embassy_executor::task]
 async fn log_send_telementry(
     mut telemetry: zerocopy_channel::Receiver<'static, NoopRawMutex, Telemetry>,
-    drone_responses: channel::Sender<'static, CriticalSectionRawMutex, DroneResponse, 64>,
+    drone_responses: &'static DroneResponses,
 ) -> ! {
-    let mut ticker = Ticker::every(Duration::from_millis(250));
     loop {
-        ticker.next().await;
-
-        telemetry.clear();
+        // rate limited by the control loop
         let received = *telemetry.receive().await;
-        drone_responses
-            .send(DroneResponse::Telemetry(received))
-            .await;
+        send_latest(drone_responses, DroneResponse::Telemetry(received));
+        send_latest(
+            drone_responses,
+            DroneResponse::motors_state(received.throttles),
+        );
         telemetry.receive_done();
     }
 }
 
-fn map_motor_throttles(throttles: [f32; 4]) -> [u16; 4] {
-    [
-        if MOTOR_FRONT_LEFT_REV {
-            -throttles[MOTOR_FRONT_LEFT_IDX]
-        } else {
-            throttles[MOTOR_FRONT_LEFT_IDX]
-        },
-        if MOTOR_FRONT_RIGHT_REV {
-            -throttles[MOTOR_FRONT_RIGHT_IDX]
-        } else {
-            throttles[MOTOR_FRONT_RIGHT_IDX]
-        },
-        if MOTOR_BACK_RIGHT_REV {
-            -throttles[MOTOR_BACK_RIGHT_IDX]
-        } else {
-            throttles[MOTOR_BACK_RIGHT_IDX]
-        },
-        if MOTOR_BACK_LEFT_REV {
-            -throttles[MOTOR_BACK_LEFT_IDX]
-        } else {
-            throttles[MOTOR_BACK_LEFT_IDX]
-        },
-    ]
-    .map(|t| t + 1000.0)
-    .map(|t| t as u16)
+/// Signal to restart esp-now, see [`watch_link`]
+static RESTART_ESP_NOW: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Outgoing messages dropped in a row after which the radio is re-initialized
+const LINK_FAILURE_LIMIT: u32 = 20;
+const LINK_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Restarts esp-now once sending fails persistently, e.g. after the radio got stuck
+#[embassy_executor::task]
+async fn watch_link() -> ! {
+    let mut check = Ticker::every(LINK_CHECK_INTERVAL);
+    loop {
+        check.next().await;
+        let failures = common_esp::consecutive_send_failures();
+        if failures >= LINK_FAILURE_LIMIT {
+            error!(
+                "{} messages in a row failed to send, restarting esp-now",
+                failures
+            );
+            RESTART_ESP_NOW.signal(());
+            // the count starts over once esp-now is running again
+            while common_esp::consecutive_send_failures() >= LINK_FAILURE_LIMIT {
+                check.next().await;
+            }
+        }
+    }
 }
 
 #[embassy_executor::task]
 async fn esp_now_communicate(
-    wifi: WIFI<'static>,
+    mut wifi: WIFI<'static>,
     outgoing: Receiver<'static, CriticalSectionRawMutex, DroneResponse, 64>,
     incoming: Sender<'static, CriticalSectionRawMutex, RemoteRequest, 64>,
 ) {
-    common_esp::communicate(wifi, outgoing, incoming).await;
+    loop {
+        common_esp::communicate(
+            wifi.reborrow(),
+            EspNowChannel::DEFAULT,
+            common_esp::SEND_RETRY,
+            outgoing,
+            incoming,
+            &RESTART_ESP_NOW,
+        )
+        .await;
+    }
 }
 
 async fn init_esp() -> Peripherals {
@@ -1,6 +1,11 @@
-use core::marker::PhantomData;
+use core::{
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use defmt::error;
+use common_messages::{RampLimiter, THROTTLE_STOP, throttle_to_pulse};
+use defmt::{error, warn};
+use embassy_futures::yield_now;
 use embassy_time::{Duration, Instant};
 use esp_hal::{
     Blocking,
@@ -14,14 +19,15 @@ pub trait Protocol {
     const RATE: Rate;
     const CLK_DIV: u8;
 
-    /// transforms a throttle from 0..=2000 into protocol range
+    /// Transforms a throttle in 0..=[`common_messages::THROTTLE_MAX`] into the protocol range
     fn throttle_transform(throttle: u16) -> u16;
     fn encode_pulse(value: u16) -> impl AsRef<[PulseCode]>;
 }
 
 pub trait OneShot: Protocol {
+    /// Pulse length in ticks of [`Protocol::RATE`], see [`throttle_to_pulse`]
     fn throttle_transform(throttle: u16) -> u16 {
-        (throttle / 2).min(1000) + 1000
+        throttle_to_pulse(throttle)
     }
 
     fn encode_oneshot_pulse(value: u16) -> impl AsRef<[PulseCode]> {
@@ -39,8 +45,9 @@ pub struct OneShot125;
 impl OneShot for OneShot125 {}
 impl Protocol for OneShot125 {
     // 8 MHz -> 0.125µs
-    // throttle = 1000 => pulse of 125µs which is 0 for OneShot125
-    // throttle = 2000 => pulse of 250µs which is full throttle for OneShot125
+    // pulse = 1000 => 125µs which is full reverse for OneShot125
+    // pulse = 1500 => 187.5µs which is stop, reached with THROTTLE_STOP
+    // pulse = 2000 => 250µs which is full throttle for OneShot125
     const RATE: Rate = Rate::from_mhz(8);
     const CLK_DIV: u8 = 1;
 
@@ -57,8 +64,9 @@ pub struct OneShot42;
 impl OneShot for OneShot42 {}
 impl Protocol for OneShot42 {
     // 24 MHz -> ~0.042µs
-    // throttle = 1000 => pulse of 42µs which is 0 for OneShot42
-    // throttle = 2000 => pulse of 48µs which is full throttle for OneShot42
+    // pulse = 1000 => ~42µs which is full reverse for OneShot42
+    // pulse = 1500 => 62.5µs which is stop, reached with THROTTLE_STOP
+    // pulse = 2000 => ~83µs which is full throttle for OneShot42
     const RATE: Rate = Rate::from_mhz(24);
     const CLK_DIV: u8 = 1;
 
@@ -71,9 +79,26 @@ impl Protocol for OneShot42 {
     }
 }
 
+/// Common interface of motor backends so the control loop does not depend on the protocol
+#[allow(async_fn_in_trait)]
+pub trait MotorDriver {
+    /// Sends throttles in the range 0..=[`common_messages::THROTTLE_MAX`] to the four motors,
+    /// see [`THROTTLE_STOP`]
+    async fn send_throttles(&mut self, throttles: [u16; 4]);
+    /// Runs the ESC arming sequence, aborting early once `cancel` is set. `cancel` is cleared
+    /// first, so an abort of an earlier sequence or a later disarm doesn't carry over.
+    ///
+    /// Returns whether the sequence completed.
+    async fn arm(&mut self, cancel: &AtomicBool) -> bool;
+}
+
+/// Time a single esc pulse may take to transmit, well above the longest oneshot pulse
+const TRANSMIT_TIMEOUT: Duration = Duration::from_millis(1);
+
 pub struct Motors<Protocol> {
     data: Channel<'static, Blocking, Tx>,
     mux_slct: [Output<'static>; 2],
+    ramp: Option<RampLimiter>,
     protocol: PhantomData<Protocol>,
 }
 
@@ -103,54 +128,99 @@ impl<Proto: Protocol> Motors<Proto> {
         Self {
             data: channel,
             mux_slct: [mux_slct0, mux_slct1],
+            ramp: None,
             protocol: Default::default(),
         }
     }
 
+    /// Limits how fast the motors spin up to `max_rate` throttle per second, see [`RampLimiter`]
+    pub fn with_ramp_limit(mut self, max_rate: f32) -> Self {
+        self.ramp = Some(RampLimiter::new(max_rate));
+        self
+    }
+
     fn send_esc_value(&mut self, value: u16) {
         let pulse = Proto::encode_pulse(value);
 
         let channel = self.data.reborrow();
 
-        if let Err(e) = channel
-            .transmit(pulse.as_ref())
-            .map(|tx| tx.wait().map_err(|(e, _)| e))
-        {
+        let mut tx = match channel.transmit(pulse.as_ref()) {
+            Ok(tx) => tx,
+            Err(e) => {
+                error!("unable to transmit rmt pulse: {:?}", e);
+                return;
+            }
+        };
+        // polled instead of waited on, so a wedged peripheral can't hang the control loop
+        let deadline = Instant::now().saturating_add(TRANSMIT_TIMEOUT);
+        while !tx.poll() {
+            if Instant::now() > deadline {
+                error!("rmt pulse not transmitted within {}", TRANSMIT_TIMEOUT);
+                return;
+            }
+        }
+        if let Err((e, _)) = tx.wait() {
             error!("unable to transmit rmt pulse: {:?}", e)
         }
     }
 
+    /// Routes the rmt data line to the esc of motor `idx` via the 2-bit mux
+    fn select_motor(&mut self, idx: usize) {
+        self.mux_slct[0].set_level(Level::from(idx & 0b10 != 0));
+        self.mux_slct[1].set_level(Level::from(idx & 0b01 != 0));
+    }
+
     pub fn send_esc_values(&mut self, values: [u16; 4]) {
         critical_section::with(|_cs| {
-            self.mux_slct[0].set_low();
-            self.mux_slct[1].set_low();
-            self.send_esc_value(values[0]);
-
-            self.mux_slct[0].set_low();
-            self.mux_slct[1].set_high();
-            self.send_esc_value(values[1]);
-
-            self.mux_slct[0].set_high();
-            self.mux_slct[1].set_low();
-            self.send_esc_value(values[2]);
-
-            self.mux_slct[1].set_high();
-            self.mux_slct[1].set_high();
-            self.send_esc_value(values[3]);
+            for (idx, value) in values.into_iter().enumerate() {
+                self.select_motor(idx);
+                self.send_esc_value(value);
+            }
         })
     }
 
     pub fn send_throttles(&mut self, throttles: [u16; 4]) {
+        let throttles = match &mut self.ramp {
+            Some(ramp) => ramp.apply(throttles, Instant::now().as_micros()),
+            None => throttles,
+        };
         self.send_esc_values(throttles.map(Proto::throttle_transform))
     }
 }
 
+/// Takes the esc data line away from the rmt and drives it low, so no further pulses reach
+/// the escs. Meant for panics, where the [`Motors`] instance is out of reach.
+///
+/// The line is held low for as long as the returned pin is kept alive.
+#[must_use]
+pub fn hold_data_line_low(data_pin: impl OutputPin + 'static) -> Output<'static> {
+    Output::new(data_pin, Level::Low, OutputConfig::default())
+}
+
 impl<Proto: OneShot> Motors<Proto> {
-    pub async fn arm_oneshot(&mut self) {
+    pub async fn arm_oneshot(&mut self, cancel: &AtomicBool) -> bool {
+        cancel.store(false, Ordering::Relaxed);
         let end = Instant::now().saturating_add(Duration::from_secs(3));
         while Instant::now() <= end {
-            self.send_throttles([1000; 4]);
+            if cancel.load(Ordering::Relaxed) {
+                warn!("arming aborted");
+                return false;
+            }
+            self.send_throttles([THROTTLE_STOP; 4]);
+            // let the remote request handler run so an abort can arrive
+            yield_now().await;
         }
+        true
+    }
+}
+
+impl<Proto: OneShot> MotorDriver for Motors<Proto> {
+    async fn send_throttles(&mut self, throttles: [u16; 4]) {
+        Motors::send_throttles(self, throttles)
+    }
+
+    async fn arm(&mut self, cancel: &AtomicBool) -> bool {
+        self.arm_oneshot(cancel).await
     }
 }
 
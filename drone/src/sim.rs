@@ -0,0 +1,95 @@
+//! Software-in-the-loop model of the quadcopter, producing synthetic IMU samples from motor
+//! throttles so the fusion and PID pipeline can be exercised without hardware.
+//!
+//! Motors are in mixer order: 0 and 3 lower roll, 1 and 2 raise it, 0 and 1 lower pitch,
+//! 2 and 3 raise it, 0 and 2 spin one way and 1 and 3 the other.
+
+use crate::ImuSample;
+
+/// Angular acceleration in deg/s² for a full throttle difference across an axis
+const TORQUE_GAIN: f32 = 4000.0;
+/// Yaw authority relative to roll and pitch
+const YAW_TORQUE_GAIN: f32 = 800.0;
+/// Aerodynamic rate damping in 1/s
+const RATE_DAMPING: f32 = 2.0;
+/// Magnitude of gravity in mg, matching the IMU drivers
+const GRAVITY_MG: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimSample {
+    gyro: [f32; 3],
+    accel: [f32; 3],
+    dt: f32,
+    timestamp: u64,
+}
+
+impl ImuSample for SimSample {
+    fn gyro(&self) -> [f32; 3] {
+        self.gyro
+    }
+
+    fn accel(&self) -> [f32; 3] {
+        self.accel
+    }
+
+    fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+/// Rigid body rotating about its center, translation is not modelled
+#[derive(Debug, Clone, Default)]
+pub struct Sim {
+    /// roll, pitch and yaw in degrees
+    pub orientation: [f32; 3],
+    /// roll, pitch and yaw rates in deg/s
+    pub rates: [f32; 3],
+    /// Constant external angular acceleration in deg/s², e.g. an off-center mass
+    pub disturbance: [f32; 3],
+    time_us: u64,
+}
+
+impl Sim {
+    pub fn new(orientation: [f32; 3]) -> Self {
+        Self {
+            orientation,
+            ..Default::default()
+        }
+    }
+
+    /// Advances the model by `dt` seconds with the motors at `throttles` (1000..=2000) and
+    /// returns the IMU sample at the end of the step
+    pub fn step(&mut self, throttles: [u16; 4], dt: f32) -> SimSample {
+        let [m0, m1, m2, m3] = throttles.map(|t| (t.clamp(1000, 2000) - 1000) as f32 / 1000.0);
+        let torque = [
+            TORQUE_GAIN * ((m1 + m2) - (m0 + m3)) / 2.0,
+            TORQUE_GAIN * ((m2 + m3) - (m0 + m1)) / 2.0,
+            YAW_TORQUE_GAIN * ((m0 + m2) - (m1 + m3)) / 2.0,
+        ];
+
+        for axis in 0..3 {
+            let accel = torque[axis] + self.disturbance[axis] - RATE_DAMPING * self.rates[axis];
+            self.rates[axis] += accel * dt;
+            self.orientation[axis] += self.rates[axis] * dt;
+        }
+        self.time_us += (dt * 1_000_000.0) as u64;
+
+        let [roll, pitch, _] = self.orientation.map(f32::to_radians);
+        let [roll_rate, pitch_rate, yaw_rate] = self.rates;
+        // Sensor frame, the fusion flips the x axis
+        SimSample {
+            gyro: [-roll_rate, pitch_rate, yaw_rate],
+            accel: [
+                -GRAVITY_MG * pitch.sin(),
+                -GRAVITY_MG * roll.sin() * pitch.cos(),
+                GRAVITY_MG * roll.cos() * pitch.cos(),
+            ],
+            dt,
+            timestamp: self.time_us,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use egui_plot::PlotPoint;
+
+/// Reduces a series to the minimum and maximum of every `factor` samples, so a plot buffer
+/// covers a longer time window while spikes stay visible
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    factor: usize,
+    count: usize,
+    min: Option<PlotPoint>,
+    max: Option<PlotPoint>,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Envelope {
+    pub fn new(factor: usize) -> Self {
+        Self {
+            factor: factor.max(1),
+            count: 0,
+            min: None,
+            max: None,
+        }
+    }
+
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Returns the points to plot once a bucket is complete: its minimum and maximum in
+    /// sample order, a single point if they coincide
+    pub fn push(&mut self, point: PlotPoint) -> [Option<PlotPoint>; 2] {
+        if self.min.is_none_or(|min| point.y < min.y) {
+            self.min = Some(point);
+        }
+        if self.max.is_none_or(|max| point.y > max.y) {
+            self.max = Some(point);
+        }
+        self.count += 1;
+        if self.count < self.factor {
+            return [None, None];
+        }
+
+        self.count = 0;
+        match (self.min.take(), self.max.take()) {
+            (Some(min), Some(max)) if min == max => [Some(min), None],
+            (Some(min), Some(max)) if min.x <= max.x => [Some(min), Some(max)],
+            (min, max) => [max, min],
+        }
+    }
+}
+
+#[test]
+fn min_max_envelope() {
+    let series = [
+        0.0, 3.0, -1.0, 2.0, 5.0, 5.0, 4.0, 1.0, 7.0, 7.0, 7.0, 7.0, 9.0,
+    ];
+    let decimate = |factor| {
+        let mut envelope = Envelope::new(factor);
+        series
+            .iter()
+            .enumerate()
+            .flat_map(|(x, &y)| envelope.push(PlotPoint::new(x as f64, y)))
+            .flatten()
+            .map(|point| [point.x, point.y])
+            .collect::<Vec<_>>()
+    };
+
+    // Buckets [0, 3, -1, 2], [5, 5, 4, 1] and [7, 7, 7, 7], the trailing partial bucket is
+    // held back until it completes
+    assert_eq!(
+        decimate(4),
+        [[1.0, 3.0], [2.0, -1.0], [4.0, 5.0], [7.0, 1.0], [8.0, 7.0]]
+    );
+    assert_eq!(
+        decimate(1),
+        series
+            .iter()
+            .enumerate()
+            .map(|(x, &y)| [x as f64, y])
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(decimate(0), decimate(1));
+    assert_eq!(decimate(13).len(), 2);
+    assert!(decimate(14).is_empty());
+}
@@ -1,16 +1,21 @@
 #![feature(file_buffered, trim_prefix_suffix)]
 
-use std::{collections::VecDeque, io::BufRead};
+use std::collections::VecDeque;
 
 use base64::Engine;
 
-#[derive(Debug, Clone, Copy)]
+mod decimate;
+mod plot_settings;
+mod record;
+mod serial;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 enum SampleEvent {
     Ok(Sample),
     Lagged(Sample),
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 struct Sample {
     idx: u64,
     gy: [f32; 3],
@@ -19,8 +24,13 @@ struct Sample {
 }
 
 impl Sample {
-    fn from_bytes(bytes: &[u8]) -> Self {
-        assert_eq!(bytes.len(), 8 + 3 * 3 * 4);
+    /// Size of a serialized sample: idx + gy, xl and temp
+    const SIZE: usize = size_of::<u64>() + 3 * 3 * size_of::<f32>();
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::SIZE {
+            return None;
+        }
 
         let (u64_chunks, _) = bytes.as_chunks::<8>();
         let idx = u64::from_le_bytes(*u64_chunks.first().unwrap());
@@ -29,7 +39,7 @@ impl Sample {
         let (le_floats, _) = bytes.as_chunks::<4>();
         let mut floats = le_floats.iter().map(|&b| f32::from_le_bytes(b));
 
-        Sample {
+        Some(Sample {
             idx,
             gy: [
                 floats.next().unwrap(),
@@ -46,16 +56,64 @@ impl Sample {
                 floats.next().unwrap(),
                 floats.next().unwrap(),
             ],
+        })
+    }
+}
+
+impl SampleEvent {
+    /// Size of a serialized event: tag + sample
+    const SIZE: usize = 1 + Sample::SIZE;
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&tag, sample_bytes) = bytes.split_first()?;
+        let Some(sample) = Sample::from_bytes(sample_bytes) else {
+            println!(
+                "[!] skipping sample with invalid length {}, expected {}",
+                bytes.len(),
+                Self::SIZE
+            );
+            return None;
+        };
+
+        match tag {
+            b'O' => Some(SampleEvent::Ok(sample)),
+            b'L' => Some(SampleEvent::Lagged(sample)),
+            tag => {
+                println!("[!] skipping sample with unknown tag 0x{tag:02x}");
+                None
+            }
         }
     }
 }
 
+enum Source {
+    Serial(String),
+    Replay(String),
+}
+
 fn main() -> eyre::Result<()> {
-    let path = std::env::args()
-        .nth(1)
-        .unwrap_or("/dev/ttyACM0".to_string());
+    let mut source = Source::Serial("/dev/ttyACM0".to_string());
+    let mut record = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => {
+                record = Some(args.next().ok_or(eyre::eyre!("--record expects a path"))?);
+            }
+            "--replay" => {
+                source = Source::Replay(args.next().ok_or(eyre::eyre!("--replay expects a path"))?);
+            }
+            _ => source = Source::Serial(arg),
+        }
+    }
+
+    let recorder = record
+        .map(|path| record::Recorder::create(&path))
+        .transpose()?;
+
     let (ctx_tx, ctx_rx) = std::sync::mpsc::sync_channel(1);
-    let (data_pump, sample_rx) = data_pump(path, ctx_rx);
+    let (data_pump, sample_rx) = data_pump(source, recorder, ctx_rx);
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([350.0, 200.0]),
@@ -70,9 +128,17 @@ fn main() -> eyre::Result<()> {
 
             Ok(Box::new(ImuVis {
                 sample_rx,
+                stats: Default::default(),
+                envelopes: Default::default(),
                 gy: Default::default(),
                 xl: Default::default(),
                 temp: Default::default(),
+                show_spectrum: false,
+                fft_planner: rustfft::FftPlanner::new(),
+                gy_spectrum: Default::default(),
+                plot_settings: std::fs::read_to_string(PLOT_SETTINGS_PATH)
+                    .map(|text| plot_settings::load(&text))
+                    .unwrap_or_default(),
             }))
         }),
     )
@@ -83,7 +149,8 @@ fn main() -> eyre::Result<()> {
 }
 
 fn data_pump(
-    path: String,
+    source: Source,
+    mut recorder: Option<record::Recorder<std::io::BufWriter<std::fs::File>>>,
     egui_ctx_rx: std::sync::mpsc::Receiver<egui::Context>,
 ) -> (
     std::thread::JoinHandle<()>,
@@ -95,27 +162,46 @@ fn data_pump(
         let egui_ctx = egui_ctx_rx.recv().unwrap();
         drop(egui_ctx_rx);
 
-        let mut stream = std::fs::File::open_buffered(path).unwrap().lines();
-        while let Some(Ok(line)) = stream.next() {
-            println!("[esp32] {line}");
-
-            if let Some(Ok(sample_bytes)) = line.split_once("B64:").map(|(_, b64)| {
-                base64::prelude::BASE64_STANDARD_NO_PAD.decode(b64.trim_suffix("\u{1b}[0m"))
-            }) && sample_bytes.len() == 45
+        let mut emit = |event: SampleEvent| {
+            if let Some(recorder) = &mut recorder
+                && let Err(e) = recorder.record(&event)
             {
-                let tag = sample_bytes[0];
-                let sample = Sample::from_bytes(&sample_bytes[1..45]);
-
-                let event = match tag {
-                    b'O' => SampleEvent::Ok(sample),
-                    b'L' => SampleEvent::Lagged(sample),
-                    _ => unreachable!(),
-                };
+                println!("[!] unable to record sample: {e}");
+            }
 
-                if tx.send(event).is_err() {
-                    return;
+            if tx.send(event).is_err() {
+                return false;
+            }
+            egui_ctx.request_repaint();
+            true
+        };
+
+        match source {
+            Source::Serial(path) => {
+                serial::stream_lines(
+                    || std::fs::File::open_buffered(&path),
+                    std::thread::sleep,
+                    |line| {
+                        println!("[esp32] {line}");
+
+                        if let Some(Ok(sample_bytes)) = line.split_once("B64:").map(|(_, b64)| {
+                            base64::prelude::BASE64_STANDARD_NO_PAD
+                                .decode(b64.trim_suffix("\u{1b}[0m"))
+                        }) && let Some(event) = SampleEvent::from_bytes(&sample_bytes)
+                        {
+                            return emit(event);
+                        }
+                        true
+                    },
+                );
+                return;
+            }
+            Source::Replay(path) => {
+                for event in record::replay(std::fs::File::open_buffered(path).unwrap()) {
+                    if !emit(event) {
+                        return;
+                    }
                 }
-                egui_ctx.request_repaint();
             }
         }
 
@@ -127,49 +213,133 @@ fn data_pump(
 
 struct ImuVis {
     sample_rx: std::sync::mpsc::Receiver<SampleEvent>,
+    stats: SampleStats,
+    /// Decimation of the gyro, accelerometer and temperature series, recordings keep the
+    /// full rate as they're written before decimation
+    envelopes: [decimate::Envelope; 7],
 
     gy: [VecDeque<egui_plot::PlotPoint>; 3],
     xl: [VecDeque<egui_plot::PlotPoint>; 3],
     temp: [VecDeque<egui_plot::PlotPoint>; 1],
+
+    show_spectrum: bool,
+    fft_planner: rustfft::FftPlanner<f64>,
+    gy_spectrum: [VecDeque<egui_plot::PlotPoint>; 3],
+
+    /// Gyro, accelerometer, temperature and spectrum plot, in drawing order
+    plot_settings: [plot_settings::PlotSettings; 4],
+}
+
+/// Plot settings are kept across runs in this file, one line per plot
+const PLOT_SETTINGS_PATH: &str = "imu-proc-plots.txt";
+
+#[derive(Debug, Default, PartialEq)]
+struct SampleStats {
+    total: u64,
+    lagged: u64,
+}
+
+impl SampleStats {
+    fn record(&mut self, event: &SampleEvent) {
+        self.total += 1;
+        if let SampleEvent::Lagged(_) = event {
+            self.lagged += 1;
+        }
+    }
+
+    fn lagged_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.lagged as f64 / self.total as f64 * 100.0
+    }
+}
+
+/// Number of most recent samples used for the spectrum
+const FFT_SIZE: usize = 1024;
+
+/// Ticks of `Sample::idx` per second, used to infer the sample rate
+const IDX_PER_SECOND: f64 = 1_000_000.0;
+
+fn hann_window(i: usize, n: usize) -> f64 {
+    if n < 2 {
+        return 1.0;
+    }
+    0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / (n - 1) as f64).cos()
+}
+
+/// Single sided amplitude spectrum of a hann windowed signal
+fn spectrum(
+    planner: &mut rustfft::FftPlanner<f64>,
+    samples: &[f64],
+    sample_rate: f64,
+) -> Vec<egui_plot::PlotPoint> {
+    use rustfft::num_complex::Complex;
+
+    let n = samples.len();
+    let window = (0..n).map(|i| hann_window(i, n)).collect::<Vec<_>>();
+    let window_sum = window.iter().sum::<f64>();
+
+    let mut buffer = std::iter::zip(samples, &window)
+        .map(|(sample, w)| Complex::new(sample * w, 0.0))
+        .collect::<Vec<_>>();
+    planner.plan_fft_forward(n).process(&mut buffer);
+
+    buffer
+        .iter()
+        .take(n / 2 + 1)
+        .enumerate()
+        .map(|(bin, c)| {
+            egui_plot::PlotPoint::new(
+                bin as f64 * sample_rate / n as f64,
+                2.0 * c.norm() / window_sum,
+            )
+        })
+        .collect()
 }
 
 impl eframe::App for ImuVis {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         while let Ok(sample) = self.sample_rx.try_recv() {
+            self.stats.record(&sample);
             let sample = match sample {
                 SampleEvent::Ok(sample) | SampleEvent::Lagged(sample) => sample,
             };
 
             const MAX_POINTS: usize = 1600 * 10;
 
-            for i in 0..self.gy.len() {
-                if self.gy[i].len() > MAX_POINTS {
-                    _ = self.gy[i].pop_front();
+            let series = (self.gy.iter_mut().zip(sample.gy))
+                .chain(self.xl.iter_mut().zip(sample.xl))
+                .chain(self.temp.iter_mut().zip(sample.temp));
+            for ((points, value), envelope) in series.zip(&mut self.envelopes) {
+                let point = egui_plot::PlotPoint::new(sample.idx as f64, value);
+                for point in envelope.push(point).into_iter().flatten() {
+                    if points.len() > MAX_POINTS {
+                        _ = points.pop_front();
+                    }
+                    points.push_back(point);
                 }
-
-                self.gy[i].push_back(egui_plot::PlotPoint::new(sample.idx as f64, sample.gy[i]));
-                self.gy[i].make_contiguous();
-            }
-            for i in 0..self.xl.len() {
-                if self.xl[i].len() > MAX_POINTS {
-                    _ = self.xl[i].pop_front();
-                }
-
-                self.xl[i].push_back(egui_plot::PlotPoint::new(sample.idx as f64, sample.xl[i]));
-                self.xl[i].make_contiguous();
+                points.make_contiguous();
             }
-            for i in 0..self.temp.len() {
-                if self.temp[i].len() > MAX_POINTS {
-                    _ = self.temp[i].pop_front();
-                }
+        }
 
-                self.temp[i]
-                    .push_back(egui_plot::PlotPoint::new(sample.idx as f64, sample.temp[i]));
-                self.temp[i].make_contiguous();
-            }
+        if ctx.input(|i| i.key_pressed(egui::Key::F)) {
+            self.show_spectrum = !self.show_spectrum;
+        }
+        if self.show_spectrum {
+            self.update_spectrum();
         }
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "samples: {}, lagged: {} ({:.2}%)",
+                    self.stats.total,
+                    self.stats.lagged,
+                    self.stats.lagged_percent()
+                ));
+                self.draw_decimation(ui);
+            });
             egui::Grid::new("plot_grid")
                 .num_columns(2)
                 .min_row_height(420.0)
@@ -179,19 +349,96 @@ impl eframe::App for ImuVis {
 }
 
 impl ImuVis {
-    fn draw_plots(&self, ui: &mut egui::Ui) {
-        self.draw_plot(ui, "Gyro", &self.gy);
-        self.draw_plot(ui, "Accelerometer", &self.xl);
-        self.draw_plot(ui, "Temperature", &self.temp);
+    fn draw_decimation(&mut self, ui: &mut egui::Ui) {
+        let mut factor = self.envelopes[0].factor();
+        let drag = egui::DragValue::new(&mut factor)
+            .range(1..=64)
+            .prefix("min/max of every ")
+            .suffix(" samples");
+        if ui.add(drag).changed() {
+            // The series are cleared as they'd otherwise mix sample spacings
+            self.envelopes = [decimate::Envelope::new(factor); 7];
+            for points in self.gy.iter_mut().chain(&mut self.xl).chain(&mut self.temp) {
+                points.clear();
+            }
+            self.gy_spectrum = Default::default();
+        }
+    }
+
+    fn update_spectrum(&mut self) {
+        // The envelope isn't evenly spaced in time
+        if self.envelopes[0].factor() > 1 {
+            return;
+        }
+        for i in 0..self.gy.len() {
+            let len = self.gy[i].len();
+            if len < FFT_SIZE {
+                continue;
+            }
+
+            let (points, _) = self.gy[i].as_slices();
+            let points = &points[len - FFT_SIZE..];
+
+            let idx_spacing = (points[FFT_SIZE - 1].x - points[0].x) / (FFT_SIZE - 1) as f64;
+            if idx_spacing <= 0.0 {
+                continue;
+            }
+            let sample_rate = IDX_PER_SECOND / idx_spacing;
+
+            let samples = points.iter().map(|p| p.y).collect::<Vec<_>>();
+            self.gy_spectrum[i] = spectrum(&mut self.fft_planner, &samples, sample_rate).into();
+        }
+    }
+
+    fn draw_plots(&mut self, ui: &mut egui::Ui) {
+        let before = self.plot_settings;
+        let [gy, xl, temp, gy_spectrum] = &mut self.plot_settings;
+
+        Self::draw_plot(ui, "Gyro", &self.gy, gy);
+        Self::draw_plot(ui, "Accelerometer", &self.xl, xl);
+        Self::draw_plot(ui, "Temperature", &self.temp, temp);
+        if self.show_spectrum {
+            Self::draw_plot(ui, "Gyro Spectrum", &self.gy_spectrum, gy_spectrum);
+        } else {
+            ui.label("Press F to show the gyro spectrum");
+            ui.end_row();
+        }
+
+        if self.plot_settings != before
+            && let Err(e) =
+                std::fs::write(PLOT_SETTINGS_PATH, plot_settings::save(&self.plot_settings))
+        {
+            println!("[!] unable to save plot settings: {e}");
+        }
     }
 
-    fn draw_plot(&self, ui: &mut egui::Ui, name: &str, data: &[VecDeque<egui_plot::PlotPoint>]) {
-        ui.label(name);
+    fn draw_plot(
+        ui: &mut egui::Ui,
+        name: &str,
+        data: &[VecDeque<egui_plot::PlotPoint>],
+        settings: &mut plot_settings::PlotSettings,
+    ) {
+        ui.vertical(|ui| {
+            ui.label(name);
+            settings.draw(ui, data.len());
+        });
+
+        let fixed = match settings.y_scale {
+            plot_settings::YScale::Auto => None,
+            plot_settings::YScale::Fixed { min, max } => Some(min..=max),
+        };
         egui_plot::Plot::new(name.to_lowercase().replace(' ', "_"))
             .legend(egui_plot::Legend::default().position(egui_plot::Corner::LeftTop))
+            .auto_bounds([true, fixed.is_none()])
             .show(ui, |plot_ui| {
+                if let Some(bounds) = fixed {
+                    plot_ui.set_plot_bounds_y(bounds);
+                }
                 let labels = ["x", "y", "z"].into_iter();
-                for (label, data) in std::iter::zip(labels, data.iter()) {
+                for ((label, data), _) in std::iter::zip(labels, data.iter())
+                    .zip(settings.visible)
+                    .filter(|(_, visible)| *visible)
+                {
                     plot_ui.line(egui_plot::Line::new(
                         label,
                         egui_plot::PlotPoints::Borrowed(data.as_slices().0),
@@ -201,3 +448,94 @@ impl ImuVis {
         ui.end_row();
     }
 }
+
+#[test]
+fn sample_length_validation() {
+    let mut bytes = vec![0; Sample::SIZE];
+    bytes[..8].copy_from_slice(&42u64.to_le_bytes());
+    bytes[8..12].copy_from_slice(&1.5f32.to_le_bytes());
+
+    let sample = Sample::from_bytes(&bytes).unwrap();
+    assert_eq!(sample.idx, 42);
+    assert_eq!(sample.gy[0], 1.5);
+
+    assert!(Sample::from_bytes(&bytes[..Sample::SIZE - 1]).is_none());
+    bytes.push(0);
+    assert!(Sample::from_bytes(&bytes).is_none());
+    assert!(Sample::from_bytes(&[]).is_none());
+}
+
+#[test]
+fn unknown_sample_tag() {
+    let mut bytes = vec![0; SampleEvent::SIZE];
+
+    bytes[0] = b'O';
+    assert!(matches!(
+        SampleEvent::from_bytes(&bytes),
+        Some(SampleEvent::Ok(_))
+    ));
+    bytes[0] = b'L';
+    assert!(matches!(
+        SampleEvent::from_bytes(&bytes),
+        Some(SampleEvent::Lagged(_))
+    ));
+    bytes[0] = b'X';
+    assert!(SampleEvent::from_bytes(&bytes).is_none());
+    assert!(SampleEvent::from_bytes(&[]).is_none());
+}
+
+#[test]
+fn sample_stats() {
+    let sample = Sample::default();
+    let mut stats = SampleStats::default();
+    assert_eq!(stats.lagged_percent(), 0.0);
+
+    for event in [
+        SampleEvent::Ok(sample),
+        SampleEvent::Lagged(sample),
+        SampleEvent::Ok(sample),
+        SampleEvent::Ok(sample),
+        SampleEvent::Lagged(sample),
+        SampleEvent::Lagged(sample),
+        SampleEvent::Ok(sample),
+        SampleEvent::Ok(sample),
+    ] {
+        stats.record(&event);
+    }
+
+    assert_eq!(
+        stats,
+        SampleStats {
+            total: 8,
+            lagged: 3
+        }
+    );
+    assert_eq!(stats.lagged_percent(), 37.5);
+}
+
+#[test]
+fn spectrum_of_sine() {
+    const SAMPLE_RATE: f64 = 1024.0;
+    const FREQ: f64 = 128.0;
+    const AMPLITUDE: f64 = 3.0;
+
+    let samples = (0..FFT_SIZE)
+        .map(|i| {
+            let t = i as f64 / SAMPLE_RATE;
+            AMPLITUDE * (2.0 * std::f64::consts::PI * FREQ * t).sin()
+        })
+        .collect::<Vec<_>>();
+
+    let bins = spectrum(&mut rustfft::FftPlanner::new(), &samples, SAMPLE_RATE);
+
+    assert_eq!(bins.len(), FFT_SIZE / 2 + 1);
+    assert_eq!(bins[1].x, SAMPLE_RATE / FFT_SIZE as f64);
+    assert_eq!(bins[FFT_SIZE / 2].x, SAMPLE_RATE / 2.0);
+
+    let peak = bins.iter().max_by(|a, b| a.y.total_cmp(&b.y)).unwrap();
+    assert_eq!(peak.x, FREQ);
+    assert!((peak.y - AMPLITUDE).abs() < 0.05 * AMPLITUDE);
+
+    assert_eq!(hann_window(0, FFT_SIZE), 0.0);
+    assert!((hann_window((FFT_SIZE - 1) / 2, FFT_SIZE) - 1.0).abs() < 1e-5);
+}
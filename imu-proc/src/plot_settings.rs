@@ -0,0 +1,154 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Y axis range of a plot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum YScale {
+    /// Follows the visible data
+    Auto,
+    /// Stays at the given bounds, so separate runs can be compared
+    Fixed { min: f64, max: f64 },
+}
+
+/// Display state of a single plot, stored as a line of text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlotSettings {
+    /// Whether each axis is drawn, in x, y, z order
+    pub visible: [bool; 3],
+    pub y_scale: YScale,
+}
+
+impl Default for PlotSettings {
+    fn default() -> Self {
+        Self {
+            visible: [true; 3],
+            y_scale: YScale::Auto,
+        }
+    }
+}
+
+impl PlotSettings {
+    /// Bounds used when switching from auto to a fixed scale
+    pub const DEFAULT_FIXED: YScale = YScale::Fixed {
+        min: -1.0,
+        max: 1.0,
+    };
+
+    pub fn draw(&mut self, ui: &mut egui::Ui, axes: usize) {
+        ui.horizontal(|ui| {
+            for (visible, label) in self.visible.iter_mut().zip(["x", "y", "z"]).take(axes) {
+                ui.checkbox(visible, label);
+            }
+        });
+
+        let mut fixed = matches!(self.y_scale, YScale::Fixed { .. });
+        if ui.checkbox(&mut fixed, "fixed Y").changed() {
+            self.y_scale = if fixed {
+                Self::DEFAULT_FIXED
+            } else {
+                YScale::Auto
+            };
+        }
+        if let YScale::Fixed { min, max } = &mut self.y_scale {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(min).prefix("min "));
+                ui.add(egui::DragValue::new(max).prefix("max "));
+            });
+            *max = max.max(*min);
+        }
+    }
+}
+
+/// `<x> <y> <z> auto` or `<x> <y> <z> fixed <min> <max>`, visibility as 0 or 1
+impl Display for PlotSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [x, y, z] = self.visible.map(u8::from);
+        write!(f, "{x} {y} {z} ")?;
+        match self.y_scale {
+            YScale::Auto => write!(f, "auto"),
+            YScale::Fixed { min, max } => write!(f, "fixed {min} {max}"),
+        }
+    }
+}
+
+impl FromStr for PlotSettings {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+
+        let mut visible = [true; 3];
+        for visible in &mut visible {
+            *visible = match fields.next() {
+                Some("0") => false,
+                Some("1") => true,
+                _ => return Err(()),
+            };
+        }
+
+        let y_scale = match fields.next() {
+            Some("auto") => YScale::Auto,
+            Some("fixed") => {
+                let mut bound = || fields.next()?.parse::<f64>().ok();
+                let (Some(min), Some(max)) = (bound(), bound()) else {
+                    return Err(());
+                };
+                if min.is_nan() || max.is_nan() || min > max {
+                    return Err(());
+                }
+                YScale::Fixed { min, max }
+            }
+            _ => return Err(()),
+        };
+
+        match fields.next() {
+            Some(_) => Err(()),
+            None => Ok(Self { visible, y_scale }),
+        }
+    }
+}
+
+/// Reads one [`PlotSettings`] per line, plots without a valid line keep the defaults
+pub fn load<const N: usize>(text: &str) -> [PlotSettings; N] {
+    let mut lines = text.lines();
+    std::array::from_fn(|_| {
+        lines
+            .next()
+            .and_then(|line| line.parse().ok())
+            .unwrap_or_default()
+    })
+}
+
+pub fn save(settings: &[PlotSettings]) -> String {
+    settings.iter().map(|s| format!("{s}\n")).collect()
+}
+
+#[test]
+fn settings_roundtrip() {
+    let settings = [
+        PlotSettings::default(),
+        PlotSettings {
+            visible: [false, true, false],
+            y_scale: YScale::Fixed {
+                min: -2000.0,
+                max: 2000.5,
+            },
+        },
+        PlotSettings {
+            visible: [true, false, true],
+            y_scale: YScale::Auto,
+        },
+    ];
+
+    let text = save(&settings);
+    assert_eq!(text, "1 1 1 auto\n0 1 0 fixed -2000 2000.5\n1 0 1 auto\n");
+    assert_eq!(load::<3>(&text), settings);
+
+    // Malformed or missing lines fall back to the defaults
+    let loaded = load::<4>("0 0 0 auto\n1 1 auto\n1 1 1 fixed 5 -5\n");
+    assert_eq!(loaded[0].visible, [false; 3]);
+    assert_eq!(loaded[1..], [PlotSettings::default(); 3]);
+    assert!("1 1 1 fixed 0".parse::<PlotSettings>().is_err());
+    assert!("1 1 1 auto 0".parse::<PlotSettings>().is_err());
+    assert!("1 1 1 fixed NaN 1".parse::<PlotSettings>().is_err());
+}
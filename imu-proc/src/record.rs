@@ -0,0 +1,140 @@
+use std::io::{BufRead, Write};
+
+use crate::{Sample, SampleEvent};
+
+const HEADER: &str = "tag,idx,gy_x,gy_y,gy_z,xl_x,xl_y,xl_z,temp_0,temp_1,temp_2";
+
+/// Samples written between flushes
+const FLUSH_INTERVAL: usize = 256;
+
+/// Writes sample events as csv rows
+pub struct Recorder<W: Write> {
+    writer: W,
+    unflushed: usize,
+}
+
+impl Recorder<std::io::BufWriter<std::fs::File>> {
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        Self::new(std::io::BufWriter::new(std::fs::File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W) -> std::io::Result<Self> {
+        writeln!(writer, "{HEADER}")?;
+        Ok(Self {
+            writer,
+            unflushed: 0,
+        })
+    }
+
+    pub fn record(&mut self, event: &SampleEvent) -> std::io::Result<()> {
+        let (tag, s) = match event {
+            SampleEvent::Ok(sample) => ('O', sample),
+            SampleEvent::Lagged(sample) => ('L', sample),
+        };
+        writeln!(
+            self.writer,
+            "{tag},{},{},{},{},{},{},{},{},{},{}",
+            s.idx,
+            s.gy[0],
+            s.gy[1],
+            s.gy[2],
+            s.xl[0],
+            s.xl[1],
+            s.xl[2],
+            s.temp[0],
+            s.temp[1],
+            s.temp[2],
+        )?;
+
+        self.unflushed += 1;
+        if self.unflushed >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.unflushed = 0;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for Recorder<W> {
+    fn drop(&mut self) {
+        _ = self.writer.flush();
+    }
+}
+
+fn parse_row(row: &str) -> Option<SampleEvent> {
+    let mut fields = row.trim().split(',');
+    let tag = fields.next()?;
+    let idx = fields.next()?.parse().ok()?;
+
+    let mut floats = [0.0; 9];
+    for float in &mut floats {
+        *float = fields.next()?.parse().ok()?;
+    }
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let sample = Sample {
+        idx,
+        gy: [floats[0], floats[1], floats[2]],
+        xl: [floats[3], floats[4], floats[5]],
+        temp: [floats[6], floats[7], floats[8]],
+    };
+    match tag {
+        "O" => Some(SampleEvent::Ok(sample)),
+        "L" => Some(SampleEvent::Lagged(sample)),
+        _ => None,
+    }
+}
+
+/// Reads sample events from a recording, skipping malformed rows
+pub fn replay(reader: impl BufRead) -> impl Iterator<Item = SampleEvent> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .filter(|row| row != HEADER)
+        .filter_map(|row| {
+            let event = parse_row(&row);
+            if event.is_none() {
+                println!("[!] skipping malformed row: {row}");
+            }
+            event
+        })
+}
+
+#[test]
+fn csv_roundtrip() {
+    let sample = |idx| Sample {
+        idx,
+        gy: [0.1, -2.5, 1e-7],
+        xl: [1000.0, f32::MAX, -0.0],
+        temp: [25.125, 25.0, 24.9],
+    };
+    let events = vec![
+        SampleEvent::Ok(sample(0)),
+        SampleEvent::Ok(sample(625)),
+        SampleEvent::Lagged(sample(1250)),
+        SampleEvent::Ok(sample(u64::MAX)),
+    ];
+
+    let mut buf = Vec::new();
+    {
+        let mut recorder = Recorder::new(&mut buf).unwrap();
+        for event in &events {
+            recorder.record(event).unwrap();
+        }
+    }
+
+    let csv = String::from_utf8(buf.clone()).unwrap();
+    assert!(csv.starts_with(HEADER));
+    assert_eq!(csv.lines().count(), events.len() + 1);
+
+    let replayed = replay(buf.as_slice()).collect::<Vec<_>>();
+    assert_eq!(replayed, events);
+}
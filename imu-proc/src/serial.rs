@@ -0,0 +1,87 @@
+use std::io::BufRead;
+use std::time::Duration;
+
+/// Delay before reopening the port after it disconnected
+pub const REOPEN_BACKOFF: Duration = Duration::from_millis(100);
+/// Upper bound of the reopen delay, which doubles on every failed attempt
+pub const REOPEN_MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Passes every line read from the port to `on_line` until it returns false.
+///
+/// An ESP reset drops the serial device, so on EOF or a read error the port is reopened with
+/// `open`, waiting with exponential backoff in between. The backoff starts over once the port
+/// yields data again.
+pub fn stream_lines<R: BufRead>(
+    mut open: impl FnMut() -> std::io::Result<R>,
+    mut sleep: impl FnMut(Duration),
+    mut on_line: impl FnMut(&str) -> bool,
+) {
+    let mut backoff = REOPEN_BACKOFF;
+    loop {
+        match open() {
+            Ok(reader) => {
+                for line in reader.lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    backoff = REOPEN_BACKOFF;
+                    if !on_line(&line) {
+                        return;
+                    }
+                }
+                println!("[!] data EOF, reopening in {backoff:?}");
+            }
+            Err(e) => println!("[!] unable to open port: {e}, retrying in {backoff:?}"),
+        }
+
+        sleep(backoff);
+        backoff = (backoff * 2).min(REOPEN_MAX_BACKOFF);
+    }
+}
+
+#[test]
+fn reopen_with_backoff() {
+    use std::io::{Error, ErrorKind};
+
+    let ms = Duration::from_millis;
+    // Disconnected twice, fails to open until the device is back, then resets once more
+    let mut opens = vec![
+        Ok(&b"a\nb\n"[..]),
+        Ok(&b""[..]),
+        Err(Error::from(ErrorKind::NotFound)),
+        Err(Error::from(ErrorKind::NotFound)),
+        Err(Error::from(ErrorKind::NotFound)),
+        Err(Error::from(ErrorKind::NotFound)),
+        Err(Error::from(ErrorKind::NotFound)),
+        Ok(&b"c\n"[..]),
+        Ok(&b"d\nstop\ne\n"[..]),
+    ]
+    .into_iter();
+
+    let mut sleeps = Vec::new();
+    let mut lines = Vec::new();
+    stream_lines(
+        || opens.next().expect("stopped reading after `stop`"),
+        |delay| sleeps.push(delay),
+        |line| {
+            lines.push(line.to_string());
+            line != "stop"
+        },
+    );
+
+    assert_eq!(lines, ["a", "b", "c", "d", "stop"]);
+    assert_eq!(
+        sleeps,
+        [
+            ms(100),
+            ms(200),
+            ms(400),
+            ms(800),
+            ms(1600),
+            ms(2000),
+            ms(2000),
+            ms(100)
+        ]
+    );
+    assert_eq!(opens.count(), 0);
+}
@@ -11,17 +11,21 @@ use esp_alloc as _;
 use esp_backtrace as _;
 use esp_println as _;
 
-use defmt::info;
+use defmt::{debug, error, info, warn};
 use embassy_executor::Spawner;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::{Receiver, Sender};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Ticker};
 use esp_hal::peripherals::{Peripherals, SW_INTERRUPT, TIMG0};
 use esp_hal::timer::timg::TimerGroup;
 use esp_hal::{clock::CpuClock, peripherals::WIFI};
 use rtt_target::{rtt_init, set_defmt_channel};
 
 use common_esp::mpmc_channel;
-use common_messages::{DroneResponse, Frame, FrameStreamDecoder, PingTarget, RemoteRequest};
+use common_messages::{
+    DroneResponse, EspNowChannel, FrameStreamDecoder, RemoteRequest, relay_step,
+};
 
 // This creates a default app-descriptor required by the esp-idf bootloader.
 // For more information see: <https://docs.espressif.com/projects/esp-idf/en/stable/esp32/api-reference/system/app_image_format.html#application-description>
@@ -68,6 +72,7 @@ async fn main(spawner: Spawner) -> ! {
             remote.receiver(),
             drone.sender(),
         ));
+        spawner.must_spawn(watch_link());
 
         (drone.receiver(), remote.sender())
     };
@@ -77,35 +82,77 @@ async fn main(spawner: Spawner) -> ! {
     let mut downchannel = channels.down.0;
 
     loop {
+        let responses = core::iter::from_fn(|| drone_res.try_receive().ok())
+            .inspect(|res| info!("Relaying(to remote): {}", res));
+        let requests = relay_step(
+            &mut req_decoder,
+            |buffer| downchannel.read(buffer),
+            responses,
+            |frame| {
+                upchannel.write(frame);
+            },
+        );
+
         // Relay outgoing requests to drone
-        req_decoder.receive(|buffer| downchannel.read(buffer));
-        for req in &mut req_decoder {
-            if let RemoteRequest::Ping(target @ PingTarget::Relay, ping_id) = &req {
-                let res = DroneResponse::Pong(*target, *ping_id);
-                upchannel.write(&Frame::encode(&res).unwrap());
-                continue;
-            }
+        for req in requests {
             info!("Relaying(to drone): {}", &req);
             remote_req.send(req).await;
         }
-
-        // Relay incoming responses to remote
-        while let Ok(res) = drone_res.try_receive() {
-            info!("Relaying(to remote): {}", res);
-            upchannel.write(&Frame::encode(&res).unwrap());
+        if req_decoder.take_version_mismatch() {
+            warn!("Dropped remote requests with mismatching schema version");
+        }
+        if req_decoder.take_unknown_variant() {
+            debug!("Ignored remote requests of a variant unknown to this relay");
         }
 
         embassy_futures::yield_now().await;
     }
 }
 
+/// Signal to restart esp-now, see [`watch_link`]
+static RESTART_ESP_NOW: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Outgoing messages dropped in a row after which the radio is re-initialized
+const LINK_FAILURE_LIMIT: u32 = 20;
+const LINK_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Restarts esp-now once sending fails persistently, e.g. after the radio got stuck
+#[embassy_executor::task]
+async fn watch_link() -> ! {
+    let mut check = Ticker::every(LINK_CHECK_INTERVAL);
+    loop {
+        check.next().await;
+        let failures = common_esp::consecutive_send_failures();
+        if failures >= LINK_FAILURE_LIMIT {
+            error!(
+                "{} messages in a row failed to send, restarting esp-now",
+                failures
+            );
+            RESTART_ESP_NOW.signal(());
+            // the count starts over once esp-now is running again
+            while common_esp::consecutive_send_failures() >= LINK_FAILURE_LIMIT {
+                check.next().await;
+            }
+        }
+    }
+}
+
 #[embassy_executor::task]
 async fn esp_now_communicate(
-    wifi: WIFI<'static>,
+    mut wifi: WIFI<'static>,
     outgoing: Receiver<'static, CriticalSectionRawMutex, RemoteRequest, 64>,
     incoming: Sender<'static, CriticalSectionRawMutex, DroneResponse, 64>,
 ) {
-    common_esp::communicate(wifi, outgoing, incoming).await
+    loop {
+        common_esp::communicate(
+            wifi.reborrow(),
+            EspNowChannel::DEFAULT,
+            common_esp::SEND_RETRY,
+            outgoing,
+            incoming,
+            &RESTART_ESP_NOW,
+        )
+        .await;
+    }
 }
 
 async fn init_esp() -> Peripherals {
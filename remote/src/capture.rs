@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+
+use anyhow::{Result as AnyResult, anyhow};
+use bevy::ecs::resource::Resource;
+
+/// Identifies capture files, followed by records of channel (u8), length (u32 LE) and data
+const MAGIC: [u8; 4] = *b"QRTT";
+
+/// Writes raw RTT channel data in the capture format
+pub struct CaptureWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(mut inner: W) -> AnyResult<Self> {
+        inner.write_all(&MAGIC)?;
+        Ok(Self { inner })
+    }
+
+    pub fn record(&mut self, channel: u8, data: &[u8]) -> AnyResult<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let len = u32::try_from(data.len())?;
+        self.inner.write_all(&[channel])?;
+        self.inner.write_all(&len.to_le_bytes())?;
+        self.inner.write_all(data)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> AnyResult<()> {
+        Ok(self.inner.flush()?)
+    }
+}
+
+/// Reads records written by a [`CaptureWriter`]
+pub struct CaptureReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut inner: R) -> AnyResult<Self> {
+        let mut magic = [0; MAGIC.len()];
+        inner.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(anyhow!("Not an RTT capture file"));
+        }
+        Ok(Self { inner })
+    }
+
+    /// Next record, `None` at the end of the capture
+    pub fn next_record(&mut self) -> AnyResult<Option<(u8, Box<[u8]>)>> {
+        let mut header = [0; 5];
+        match self.inner.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let [channel, len @ ..] = header;
+
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        self.inner
+            .read_exact(&mut data)
+            .map_err(|err| anyhow!("Truncated capture record: {err}"))?;
+        Ok(Some((channel, data.into_boxed_slice())))
+    }
+}
+
+/// Dumps all received RTT channel data to a file
+#[derive(Resource)]
+pub struct Capture(pub CaptureWriter<BufWriter<File>>);
+
+impl Capture {
+    pub fn create(path: &str) -> AnyResult<Self> {
+        Ok(Self(CaptureWriter::new(BufWriter::new(File::create(
+            path,
+        )?))?))
+    }
+}
+
+/// Feeds a capture file through the decoders instead of attaching to a probe
+#[derive(Resource)]
+pub struct Replay {
+    reader: CaptureReader<BufReader<File>>,
+    pub finished: bool,
+}
+
+impl Replay {
+    pub fn open(path: &str) -> AnyResult<Self> {
+        Ok(Self {
+            reader: CaptureReader::new(BufReader::new(File::open(path)?))?,
+            finished: false,
+        })
+    }
+
+    /// Data of the next record as relay and drone channel data
+    pub fn next_tick(&mut self) -> AnyResult<(Box<[u8]>, Box<[u8]>)> {
+        let empty = Box::<[u8]>::default;
+        Ok(match self.reader.next_record()? {
+            Some((0, data)) => (data, empty()),
+            Some((1, data)) => (empty(), data),
+            Some((channel, _)) => return Err(anyhow!("Unknown channel {channel} in capture")),
+            None => {
+                self.finished = true;
+                (empty(), empty())
+            }
+        })
+    }
+}
+
+#[test]
+fn capture_replays_drone_responses() {
+    use common_messages::{DroneResponse, Frame, FrameStreamDecoder};
+
+    let responses = [
+        DroneResponse::ArmState(true),
+        DroneResponse::Log(vec![0x00, 0xff, 1, 2, 3].into_boxed_slice()),
+        DroneResponse::ConfigSaved(true),
+        DroneResponse::error(0x0101, "chip id"),
+    ];
+    let stream: Vec<u8> = responses
+        .iter()
+        .flat_map(|res| Frame::encode(res).unwrap())
+        .collect();
+
+    // Capture the stream split across records, interleaved with relay data
+    let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+    for chunk in stream.chunks(7) {
+        writer.record(1, chunk).unwrap();
+        writer.record(0, b"relay").unwrap();
+        writer.record(0, &[]).unwrap();
+    }
+    let captured = writer.inner;
+
+    let mut reader = CaptureReader::new(captured.as_slice()).unwrap();
+    let mut decoder = FrameStreamDecoder::<DroneResponse>::default();
+    let mut decoded = Vec::new();
+    let mut relay_records = 0;
+    while let Some((channel, data)) = reader.next_record().unwrap() {
+        if channel == 0 {
+            assert_eq!(&*data, b"relay");
+            relay_records += 1;
+            continue;
+        }
+        decoder.receive(|buffer| {
+            buffer[..data.len()].copy_from_slice(&data);
+            data.len()
+        });
+        decoded.extend(&mut decoder);
+    }
+
+    assert_eq!(relay_records, stream.len().div_ceil(7));
+    assert_eq!(decoded, responses);
+
+    assert!(CaptureReader::new(&b"nope"[..]).is_err());
+    assert!(
+        CaptureReader::new(&b"QRTT\x01\x10\x00\x00\x00ab"[..])
+            .unwrap()
+            .next_record()
+            .is_err()
+    );
+}
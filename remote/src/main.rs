@@ -19,56 +19,109 @@ use bevy::input_focus::tab_navigation::TabNavigationPlugin;
 use bevy::log::{Level, debug, error, error_once, info, trace, warn};
 use bevy::time::Time;
 use bevy_egui::EguiPrimaryContextPass;
-use common_messages::{DroneResponse, PingId, PingTarget, RemoteRequest};
+use common_messages::{
+    ControlMode, DroneResponse, PingId, PingTarget, RemoteRequest, sticks_to_setpoint,
+};
 
+mod capture;
 mod rtt;
 use rtt::{
-    DroneMessage, DroneTag, ElfResource, LogMessage, RelayTag, RemoteMessage,
+    Chip, DroneMessage, DroneTag, ElfResource, LogMessage, RelayTag, RemoteMessage,
     rtt_communication_system,
 };
 
-use crate::rtt::{LogSource, log_error_system};
-use crate::ui::ui_system;
+use crate::capture::{Capture, Replay};
+use crate::rtt::{LogSource, RttStatus, log_error_system};
+use crate::ui::{ActiveTab, ui_system};
 
 mod ui;
 
 fn main() -> AnyResult<()> {
+    let mut active_tab = ActiveTab::default();
+    let mut capture = None;
+    let mut replay = None;
+    let mut positional = Vec::new();
     let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tab" => {
+                let Some(name) = args.next() else {
+                    return Err(anyhow!("Expected tab name after --tab"));
+                };
+                active_tab = ActiveTab::parse(&name)?;
+            }
+            "--capture" => {
+                let Some(path) = args.next() else {
+                    return Err(anyhow!("Expected file path after --capture"));
+                };
+                capture = Some(Capture::create(&path)?);
+            }
+            "--replay" => {
+                let Some(path) = args.next() else {
+                    return Err(anyhow!("Expected file path after --replay"));
+                };
+                replay = Some(Replay::open(&path)?);
+            }
+            _ => match arg.strip_prefix("--tab=") {
+                Some(name) => active_tab = ActiveTab::parse(name)?,
+                None => positional.push(arg),
+            },
+        }
+    }
+    if capture.is_some() && replay.is_some() {
+        return Err(anyhow!("--capture and --replay can't be combined"));
+    }
+
+    let mut args = positional.into_iter();
     let Some(relay_elf_path) = args.next() else {
         return Err(anyhow!("Expected path to relay elf as first argument"));
     };
     let Some(drone_elf_path) = args.next() else {
         return Err(anyhow!("Expected path to drone elf as second argument"));
     };
+    let chip = args
+        .next()
+        .or_else(|| std::env::var("PROBE_RS_CHIP").ok())
+        .unwrap_or(Chip::DEFAULT.to_owned());
 
-    App::new()
-        .add_plugins((
-            DefaultPlugins,
-            InputDispatchPlugin,
-            TabNavigationPlugin,
-            bevy_egui::EguiPlugin::default(),
-        ))
-        .insert_resource(KeepArmed(false))
-        .insert_resource(ElfResource::<RelayTag>::new(relay_elf_path)?)
-        .insert_resource(ElfResource::<DroneTag>::new(drone_elf_path)?)
-        .insert_resource(GamepadStatus::default())
-        .insert_resource(PingStatus::default())
-        .add_message::<RemoteMessage>()
-        .add_message::<DroneMessage>()
-        .add_message::<LogMessage>()
-        .add_systems(Startup, setup_camera_system)
-        .add_systems(EguiPrimaryContextPass, ui_system)
-        .add_systems(Update, (keyboard_input_system, gamepad_input_system))
-        .add_systems(
-            FixedUpdate,
-            (
-                rtt_communication_system.pipe(log_error_system),
-                keep_armed_system,
-                ping_pong_system,
-            ),
-        )
-        // .add_systems(FixedPostUpdate, log_logs)
-        .run();
+    let mut app = App::new();
+    if let Some(capture) = capture {
+        app.insert_resource(capture);
+    }
+    if let Some(replay) = replay {
+        app.insert_resource(replay);
+    }
+
+    app.add_plugins((
+        DefaultPlugins,
+        InputDispatchPlugin,
+        TabNavigationPlugin,
+        bevy_egui::EguiPlugin::default(),
+    ))
+    .insert_resource(KeepArmed(false))
+    .insert_resource(active_tab)
+    .insert_resource(Chip::new(chip)?)
+    .insert_resource(ElfResource::<RelayTag>::new(relay_elf_path)?)
+    .insert_resource(ElfResource::<DroneTag>::new(drone_elf_path)?)
+    .insert_resource(GamepadStatus::default())
+    .insert_resource(PingStatus::default())
+    .insert_resource(RttStatus::default())
+    .add_message::<RemoteMessage>()
+    .add_message::<DroneMessage>()
+    .add_message::<LogMessage>()
+    .add_systems(Startup, setup_camera_system)
+    .add_systems(EguiPrimaryContextPass, ui_system)
+    .add_systems(Update, (keyboard_input_system, gamepad_input_system))
+    .add_systems(
+        FixedUpdate,
+        (
+            rtt_communication_system.pipe(log_error_system),
+            keep_armed_system,
+            ping_pong_system,
+        ),
+    )
+    // .add_systems(FixedPostUpdate, log_logs)
+    .run();
 
     Ok(())
 }
@@ -122,6 +175,8 @@ struct PingStatus {
     roundtrip_drone: Option<Duration>,
     pings_drone: Vec<(PingId, Duration)>,
     next_id: PingId,
+    /// Time the latest heartbeat was received
+    last_heartbeat: Option<Duration>,
 }
 
 fn ping_pong_system(
@@ -134,8 +189,14 @@ fn ping_pong_system(
     let current = time.elapsed();
 
     for DroneMessage(res) in drone_msgs.read() {
-        let DroneResponse::Pong(pong_src, pong_id) = res else {
-            continue;
+        let (pong_src, pong_id) = match res {
+            DroneResponse::Pong(pong_src, pong_id) => (pong_src, pong_id),
+            DroneResponse::Heartbeat { uptime_ms } => {
+                trace!("heartbeat, drone uptime {uptime_ms}ms");
+                ping_status.last_heartbeat = Some(current);
+                continue;
+            }
+            _ => continue,
         };
 
         let pings = match pong_src {
@@ -197,6 +258,11 @@ fn keyboard_input_system(
 struct GamepadStatus {
     connected: bool,
     had_input: bool,
+    /// Send the sticks as a single [`RemoteRequest::Sticks`] instead of a target and thrust,
+    /// which firmware without `Sticks` ignores
+    sticks_request: bool,
+    /// Mode last requested from the drone, scales the sticks sent as a target
+    control_mode: ControlMode,
 }
 
 fn gamepad_input_system(
@@ -233,19 +299,29 @@ fn gamepad_input_system(
     let yaw = gamepad.get(GamepadAxis::RightStickX).unwrap();
     let thrust = gamepad.get(GamepadAxis::LeftZ).unwrap();
 
-    let pitch = pitch * 30.0;
-    let roll = roll * 30.0;
-    let yaw = yaw * 30.0;
-    let thrust = ((thrust / 2.0) + 0.5) * 1000.0;
-
-    debug!(
-        "pitch={}, roll={}, yaw={}, thrust={}",
-        pitch, roll, yaw, thrust
-    );
-    remote_msgs.write_batch([
-        RemoteMessage(RemoteRequest::SetTarget([roll, pitch, yaw])),
-        RemoteMessage(RemoteRequest::SetThrust(thrust)),
-    ]);
+    if gamepad_status.sticks_request {
+        debug!(
+            "sticks: pitch={}, roll={}, yaw={}, thrust={}",
+            pitch, roll, yaw, thrust
+        );
+        remote_msgs.write(RemoteMessage(RemoteRequest::Sticks {
+            roll,
+            pitch,
+            yaw,
+            thrust,
+        }));
+    } else {
+        let (target, thrust) = sticks_to_setpoint(roll, pitch, yaw, thrust);
+        let [roll, pitch, yaw] = gamepad_status.control_mode.stick_target(target);
+        debug!(
+            "pitch={}, roll={}, yaw={}, thrust={}",
+            pitch, roll, yaw, thrust
+        );
+        remote_msgs.write_batch([
+            RemoteMessage(RemoteRequest::SetTarget([roll, pitch, yaw])),
+            RemoteMessage(RemoteRequest::SetThrust(thrust)),
+        ]);
+    }
 
     let armed_left = gamepad
         .get(GamepadButton::North)
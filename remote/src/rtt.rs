@@ -1,6 +1,7 @@
 use std::fmt::Display;
 use std::marker::PhantomData;
 use std::str::FromStr;
+use std::time::Duration;
 
 use anyhow::{Result as AnyResult, anyhow};
 use bevy::ecs::error::Result as BevyResult;
@@ -8,13 +9,18 @@ use bevy::ecs::message::{Message, MessageReader, MessageWriter};
 use bevy::ecs::resource::Resource;
 use bevy::ecs::system::{In, Local};
 use bevy::ecs::world::{FromWorld, World};
-use bevy::log::error;
-use bevy::prelude::Res;
-use common_messages::{DroneResponse, Frame, FrameStreamDecoder, RemoteRequest};
+use bevy::log::{debug, error};
+use bevy::prelude::{Res, ResMut};
+use bevy::time::Time;
+use common_messages::{DroneResponse, Frame, FrameStreamDecoder, LogLevel, RemoteRequest};
 use defmt_decoder::DecodeError;
 use defmt_parser::Level;
+use probe_rs::config::Registry;
+use probe_rs::probe::{DebugProbeInfo, list::Lister};
 use probe_rs::rtt::{Rtt, find_rtt_control_block_in_raw_file};
-use probe_rs::{Permissions, Session, probe::list::Lister};
+use probe_rs::{Permissions, Session};
+
+use crate::capture::{Capture, Replay};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 #[repr(u8)]
@@ -42,29 +48,148 @@ pub struct LogMessage(pub LogSource, pub bevy::log::Level, pub String);
 pub struct RelayTag;
 pub struct DroneTag;
 
+/// Target chip name passed to probe-rs when attaching
+#[derive(Resource)]
+pub struct Chip(String);
+
+impl Chip {
+    pub const DEFAULT: &str = "esp32c6";
+
+    pub fn new(name: String) -> AnyResult<Self> {
+        Registry::from_builtin_families()
+            .get_target_by_name(&name)
+            .map_err(|err| {
+                anyhow!("Unknown chip '{name}' ({err}), see `probe-rs chip list` for valid names")
+            })?;
+        Ok(Self(name))
+    }
+}
+
+#[derive(Resource)]
+pub struct RttStatus {
+    /// Last error while connecting to the probe
+    pub error: Option<String>,
+    /// Try to (re)connect to the probe on the next update
+    pub retry: bool,
+    backoff: Backoff,
+}
+
+impl Default for RttStatus {
+    fn default() -> Self {
+        Self {
+            error: None,
+            retry: true,
+            backoff: Backoff::default(),
+        }
+    }
+}
+
+/// Exponential backoff between connection attempts
+#[derive(Default)]
+pub struct Backoff {
+    failures: u32,
+    next_attempt: Duration,
+}
+
+impl Backoff {
+    const MIN_DELAY: Duration = Duration::from_millis(250);
+    const MAX_DELAY: Duration = Duration::from_secs(8);
+
+    /// Runs `connect` if the backoff delay has passed, returns `None` otherwise
+    pub fn attempt<T>(
+        &mut self,
+        now: Duration,
+        connect: impl FnOnce() -> AnyResult<T>,
+    ) -> Option<AnyResult<T>> {
+        if now < self.next_attempt {
+            return None;
+        }
+
+        let res = connect();
+        match res {
+            Ok(_) => self.reset(),
+            Err(_) => self.failed(now),
+        }
+        Some(res)
+    }
+
+    pub fn failed(&mut self, now: Duration) {
+        self.next_attempt = now + self.delay();
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+        self.next_attempt = Duration::ZERO;
+    }
+
+    /// Time left until the next attempt
+    pub fn retry_in(&self, now: Duration) -> Duration {
+        self.next_attempt.saturating_sub(now)
+    }
+
+    pub fn delay(&self) -> Duration {
+        Self::MIN_DELAY
+            .saturating_mul(1 << self.failures.min(16))
+            .min(Self::MAX_DELAY)
+    }
+}
+
 pub fn rtt_communication_system(
+    time: Res<Time>,
+    chip: Res<Chip>,
     relay_elf: Res<ElfResource<RelayTag>>,
+    mut rtt_status: ResMut<RttStatus>,
     mut relay_defmt: Local<DefmtState<RelayTag>>,
     mut drone_defmt: Local<DefmtState<DroneTag>>,
     mut rtt_state: Local<Option<RttState>>,
+    mut replay: Option<ResMut<Replay>>,
+    mut capture: Option<ResMut<Capture>>,
     mut drone_res_decoder: Local<FrameStreamDecoder<DroneResponse>>,
     mut remote_msgs: MessageReader<RemoteMessage>,
     mut drone_msgs: MessageWriter<DroneMessage>,
     mut logs: MessageWriter<LogMessage>,
 ) -> BevyResult<()> {
-    if rtt_state.is_none() {
-        *rtt_state = Some(RttState::new(&relay_elf.data)?);
+    let now = time.elapsed();
+
+    let (relay_data, drone_data) = if let Some(replay) = replay.as_mut() {
+        if replay.finished {
+            return Ok(());
+        }
+        // Nothing to send requests to
+        remote_msgs.clear();
+        let data = replay.next_tick()?;
+        if replay.finished {
+            logs.write(LogMessage(
+                LogSource::Relay,
+                bevy::log::Level::INFO,
+                "Replay finished".to_owned(),
+            ));
+        }
+        data
+    } else {
+        let Some(data) = communicate(
+            now,
+            &chip,
+            &relay_elf,
+            &mut rtt_status,
+            &mut rtt_state,
+            &mut remote_msgs,
+            &mut logs,
+        ) else {
+            return Ok(());
+        };
+        data
     };
-    let rtt_state = rtt_state.as_mut().unwrap();
 
-    // Send remote requests
-    for (msg, _) in remote_msgs.par_read() {
-        rtt_state.send(0, &Frame::encode(&msg.0)?)?;
+    if let Some(capture) = capture.as_mut() {
+        capture.0.record(0, &relay_data)?;
+        capture.0.record(1, &drone_data)?;
+        capture.0.flush()?;
     }
 
-    // Receive, decode relay logs
-    let data = rtt_state.receive(0)?;
-    relay_defmt.decoder.received(&data);
+    // Decode relay logs
+    relay_defmt.decoder.received(&relay_data);
     let lines = relay_defmt.decode_all()?;
     logs.write_batch(lines.into_iter().map(|(level, message)| {
         LogMessage(
@@ -74,20 +199,45 @@ pub fn rtt_communication_system(
         )
     }));
 
-    // Receive, decode drone responses
-    let data = rtt_state.receive(1)?;
+    // Decode drone responses
     drone_res_decoder.receive(|buffer| {
-        let len = data.len().min(buffer.len());
-        buffer[..len].copy_from_slice(&data[..len]);
+        let len = drone_data.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&drone_data[..len]);
         len
     });
     for res in &mut drone_res_decoder {
-        if let DroneResponse::Log(data) = &res {
-            drone_defmt.decoder.received(data);
-        } else {
-            drone_msgs.write(DroneMessage(res));
+        match res {
+            DroneResponse::Log(data) => drone_defmt.decoder.received(&data),
+            DroneResponse::Event { level, code, args } => {
+                let level = match level {
+                    LogLevel::Trace => bevy::log::Level::TRACE,
+                    LogLevel::Debug => bevy::log::Level::DEBUG,
+                    LogLevel::Info => bevy::log::Level::INFO,
+                    LogLevel::Warn => bevy::log::Level::WARN,
+                    LogLevel::Error => bevy::log::Level::ERROR,
+                };
+                logs.write(LogMessage(
+                    LogSource::Drone,
+                    level,
+                    format!("event {code}: {args:?}"),
+                ));
+            }
+            res => {
+                drone_msgs.write(DroneMessage(res));
+            }
         }
     }
+    if drone_res_decoder.take_version_mismatch() {
+        logs.write(LogMessage(
+            LogSource::Relay,
+            bevy::log::Level::ERROR,
+            "Dropped drone responses with a different schema version, reflash relay and drone"
+                .to_owned(),
+        ));
+    }
+    if drone_res_decoder.take_unknown_variant() {
+        debug!("Ignored drone responses of a variant unknown to this remote");
+    }
     let lines = drone_defmt.decode_all()?;
     logs.write_batch(lines.into_iter().map(|(level, message)| {
         LogMessage(
@@ -100,6 +250,77 @@ pub fn rtt_communication_system(
     Ok(())
 }
 
+/// Exchanges data with the probe, (re)connecting if needed. Returns the relay and drone channel
+/// data, or `None` while not connected.
+fn communicate(
+    now: Duration,
+    chip: &Chip,
+    relay_elf: &ElfResource<RelayTag>,
+    rtt_status: &mut RttStatus,
+    rtt_state: &mut Option<RttState>,
+    remote_msgs: &mut MessageReader<RemoteMessage>,
+    logs: &mut MessageWriter<LogMessage>,
+) -> Option<(Box<[u8]>, Box<[u8]>)> {
+    if rtt_state.is_none() {
+        if rtt_status.retry {
+            rtt_status.retry = false;
+            rtt_status.backoff.reset();
+        }
+
+        match rtt_status
+            .backoff
+            .attempt(now, || RttState::new(&relay_elf.data, &chip.0))
+        {
+            None => return None,
+            Some(Ok(state)) => {
+                *rtt_state = Some(state);
+                rtt_status.error = None;
+                logs.write(LogMessage(
+                    LogSource::Relay,
+                    bevy::log::Level::INFO,
+                    "Probe connected".to_owned(),
+                ));
+            }
+            Some(Err(err)) => {
+                rtt_status.error = Some(err.to_string());
+                logs.write(LogMessage(
+                    LogSource::Relay,
+                    bevy::log::Level::WARN,
+                    format!(
+                        "Unable to connect to probe, retrying in {}ms: {err}",
+                        rtt_status.backoff.retry_in(now).as_millis()
+                    ),
+                ));
+                return None;
+            }
+        }
+    };
+    let rtt = rtt_state.as_mut().unwrap();
+
+    // Send remote requests, receive raw channel data
+    let io = (|| -> AnyResult<_> {
+        for (msg, _) in remote_msgs.par_read() {
+            rtt.send(0, &Frame::encode(&msg.0)?)?;
+        }
+        Ok((rtt.receive(0)?, rtt.receive(1)?))
+    })();
+    match io {
+        Ok(data) => Some(data),
+        Err(err) => {
+            // Probe is most likely gone, tear down and re-attach
+            *rtt_state = None;
+            rtt_status.error = Some(err.to_string());
+            rtt_status.backoff.failed(now);
+            logs.write(LogMessage(
+                LogSource::Relay,
+                bevy::log::Level::WARN,
+                format!("Lost connection to probe: {err}"),
+            ));
+            None
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct ElfResource<Tag> {
     data: Box<[u8]>,
@@ -127,13 +348,16 @@ pub struct RttState {
 }
 
 impl RttState {
-    pub fn new(elf: &[u8]) -> AnyResult<Self> {
-        let probes = Lister::new().list_all();
+    pub fn new(elf: &[u8], chip: &str) -> AnyResult<Self> {
+        Self::with_probes(&Lister::new().list_all(), elf, chip)
+    }
+
+    pub fn with_probes(probes: &[DebugProbeInfo], elf: &[u8], chip: &str) -> AnyResult<Self> {
         let probe = probes
-            .get(0)
+            .first()
             .ok_or(anyhow!("No probe connected!"))?
             .open()?;
-        let mut session = probe.attach("esp32c6", Permissions::default())?;
+        let mut session = probe.attach(chip, Permissions::default())?;
         let mut core = session.core(0)?;
 
         let rtt_pointer = find_rtt_control_block_in_raw_file(elf)?
@@ -218,3 +442,56 @@ pub fn log_error_system(In(res): In<BevyResult<()>>) {
         error!("{}", err);
     }
 }
+
+#[test]
+fn no_probe_connected() {
+    let err = RttState::with_probes(&[], &[], Chip::DEFAULT)
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "No probe connected!");
+}
+
+#[test]
+fn backoff_retries_until_connected() {
+    let mut backoff = Backoff::default();
+    let mut failures_left = 3;
+    let mut connect = || {
+        if failures_left > 0 {
+            failures_left -= 1;
+            Err(anyhow!("probe gone"))
+        } else {
+            Ok(())
+        }
+    };
+
+    let mut now = Duration::ZERO;
+    let mut attempts = Vec::new();
+    loop {
+        match backoff.attempt(now, &mut connect) {
+            Some(Ok(())) => break,
+            Some(Err(_)) => attempts.push((now, backoff.retry_in(now))),
+            None => {}
+        }
+        now += Duration::from_millis(50);
+    }
+
+    // delays double after every failure
+    let ms = Duration::from_millis;
+    assert_eq!(
+        attempts,
+        [(ms(0), ms(250)), (ms(250), ms(500)), (ms(750), ms(1000))]
+    );
+    assert_eq!(now, Duration::from_millis(1750));
+
+    // delay is reset after a successful connection
+    assert_eq!(backoff.delay(), Backoff::MIN_DELAY);
+    assert!(backoff.attempt(now, || Ok(())).is_some());
+}
+
+#[test]
+fn unknown_chip() {
+    assert!(Chip::new(Chip::DEFAULT.to_owned()).is_ok());
+
+    let err = Chip::new("esp32c42".to_owned()).err().unwrap();
+    assert!(err.to_string().starts_with("Unknown chip 'esp32c42'"));
+}
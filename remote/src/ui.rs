@@ -1,88 +1,228 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{Result as AnyResult, anyhow};
 use bevy::ecs::message::{MessageReader, MessageWriter};
+use bevy::ecs::resource::Resource;
 use bevy::ecs::system::{Res, ResMut};
 use bevy::ecs::{prelude::Result as BevyResult, system::Local};
 use bevy::log::Level;
 use bevy::time::Time;
 use bevy_egui::EguiContexts;
 use bevy_egui::egui::{self, Button, Color32, Label, RichText, ScrollArea, Ui};
-use common_messages::{DroneResponse, RemoteRequest};
+use common_messages::{
+    BlackboxFrame, ControlConfig, ControlMode, DroneResponse, GitHash, HeartbeatTimer, MotorOutput,
+    RemoteRequest, SCHEMA_VERSION, SelfTestChecks, Telemetry, parse_git_hash,
+};
 use egui_plot::PlotPoint;
 
-use crate::rtt::{DroneMessage, LogMessage, LogSource, RemoteMessage};
+use crate::rtt::{DroneMessage, LogMessage, LogSource, RemoteMessage, RttStatus};
 use crate::{GamepadStatus, KeepArmed, PingStatus};
 
+/// Navbar labels and the matching names accepted by `--tab`
+const TABS: [(&str, &str); 5] = [
+    ("Telemetry", "telemetry"),
+    ("Preview", "preview"),
+    ("Relay Logs", "relay"),
+    ("Drone Logs", "drone"),
+    ("Motors", "motors"),
+];
+
+#[derive(Resource, Default, Debug, PartialEq)]
+pub struct ActiveTab(usize);
+
+impl ActiveTab {
+    pub fn parse(name: &str) -> AnyResult<Self> {
+        TABS.iter()
+            .position(|&(_, tab_name)| tab_name.eq_ignore_ascii_case(name))
+            .map(ActiveTab)
+            .ok_or_else(|| {
+                let names: Vec<_> = TABS.iter().map(|&(_, tab_name)| tab_name).collect();
+                anyhow!(
+                    "Unknown tab '{name}', expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
 pub fn ui_system(
     // External state
     time: Res<Time>,
     mut contexts: EguiContexts,
     ping_status: Res<PingStatus>,
-    gamepad_status: Res<GamepadStatus>,
+    mut gamepad_status: ResMut<GamepadStatus>,
+    mut rtt_status: ResMut<RttStatus>,
     mut keep_armed: ResMut<KeepArmed>,
+    mut active_tab: ResMut<ActiveTab>,
 
     // Internal state
-    mut active_tab: Local<usize>,
-    mut settings: Local<Settings>,
+    (mut settings, mut motor_wizard): (Local<Settings>, Local<MotorWizard>),
     mut telemetry: Local<CollectedTelemetry>,
-    mut relay_logs: Local<Vec<(bevy::log::Level, String)>>,
-    mut drone_logs: Local<Vec<(bevy::log::Level, String)>>,
+    // grouped, systems take at most 16 parameters
+    (mut relay_logs, mut drone_logs): (Local<Logs>, Local<Logs>),
+    mut drone_error: Local<Option<String>>,
+    mut blackbox: Local<Vec<BlackboxFrame>>,
+    (mut drone_armed, mut arm_key): (Local<Option<bool>>, Local<ArmKey>),
 
     // Messages
     mut drone_msgs: MessageReader<DroneMessage>,
-    remote_msgs: MessageWriter<RemoteMessage>,
+    mut remote_msgs: MessageWriter<RemoteMessage>,
     mut log_msgs: MessageReader<LogMessage>,
 ) -> BevyResult {
     for DroneMessage(drone_res) in drone_msgs.read() {
-        if let &DroneResponse::Telemetry(sample) = drone_res {
-            let t = time.elapsed().as_millis() as f64;
-
-            for i in 0..3 {
-                telemetry.orientation[i].push(PlotPoint::new(t, sample.orientation[i] as f64));
+        match drone_res {
+            &DroneResponse::Telemetry(sample) => {
+                if telemetry.latest.is_none() {
+                    remote_msgs.write(RemoteMessage(RemoteRequest::GetVersion));
+                }
+                telemetry.push(time.elapsed().as_millis() as f64, sample);
             }
-            telemetry
-                .thrust
-                .push(PlotPoint::new(t, sample.thrust as f64));
-            telemetry.armed.push(PlotPoint::new(
-                t,
-                sample.armed.then_some(1.0).unwrap_or(0.0),
-            ));
-            for i in 0..3 {
-                telemetry.output[i].push(PlotPoint::new(t, sample.output[i] as f64));
+            &DroneResponse::Version { git_hash, schema } => {
+                let (level, report) = version_report(git_hash, schema);
+                drone_logs.push(level, report);
+            }
+            &DroneResponse::SelfTestResult {
+                imu_ok,
+                motors_ok,
+                link_ms,
+            } => {
+                let (level, report) = self_test_report(imu_ok, motors_ok, link_ms);
+                drone_logs.push(level, report);
             }
-            for i in 0..4 {
-                telemetry.throttles[i].push(PlotPoint::new(t, sample.throttles[i] as f64));
+            &DroneResponse::MotorsState(motors) => telemetry.motors = Some(motors),
+            DroneResponse::Config(config) => {
+                settings.kp = config.kp;
+                settings.ki = config.ki;
+                settings.kd = config.kd;
+                settings.alpha = config.alpha;
+                motor_wizard.config = Some(*config);
+                drone_logs.push(Level::INFO, format!("Active config: {config:?}"));
             }
+            &DroneResponse::ArmState(armed) => {
+                *drone_armed = Some(armed);
+                if !armed && keep_armed.0 {
+                    // disarmed by the drone itself, arming again needs a new request
+                    keep_armed.0 = false;
+                    drone_logs.push(Level::WARN, "Drone disarmed itself".to_owned());
+                }
+            }
+            DroneResponse::ConfigSaved(true) => {
+                drone_logs.push(Level::INFO, "Config saved to flash".to_owned());
+            }
+            DroneResponse::ConfigSaved(false) => {
+                drone_logs.push(Level::WARN, "Unable to save config to flash".to_owned());
+            }
+            DroneResponse::CalibrationResult(Some(offsets)) => {
+                drone_logs.push(
+                    Level::INFO,
+                    format!(
+                        "Calibrated, gyro bias: {:?}, accel offset: {:?}",
+                        offsets.gyro, offsets.accel
+                    ),
+                );
+            }
+            DroneResponse::CalibrationResult(None) => {
+                drone_logs.push(Level::WARN, "Calibration refused while armed".to_owned());
+            }
+            &DroneResponse::BlackboxFrame { remaining, frame } => {
+                blackbox.push(frame);
+                if remaining == 0 {
+                    save_blackbox(&mut blackbox, &mut drone_logs);
+                }
+            }
+            DroneResponse::Error { code, detail } => {
+                let error = format!(
+                    "Drone error {code:#06x}: {}",
+                    DroneResponse::error_detail(detail)
+                );
+                drone_logs.push(Level::ERROR, error.clone());
+                *drone_error = Some(error);
+            }
+            _ => {}
         }
     }
+    let now = time.elapsed();
     for LogMessage(src, level, message) in log_msgs.read() {
-        match src {
-            LogSource::Relay => relay_logs.push((*level, message.to_owned())),
-            LogSource::Drone => drone_logs.push((*level, message.to_owned())),
-        }
+        let logs = match src {
+            LogSource::Relay => &mut relay_logs,
+            LogSource::Drone => &mut drone_logs,
+        };
+        logs.push(*level, message.to_owned());
+        logs.last_received = Some(now);
     }
 
     let ctx = contexts.ctx_mut()?;
 
+    if !ctx.wants_keyboard_input()
+        && ctx.input(|i| i.key_pressed(egui::Key::Space))
+        && let Some(arm) = arm_key.press(now, keep_armed.0)
+    {
+        keep_armed.0 = arm;
+        remote_msgs.write(RemoteMessage(RemoteRequest::SetArm(arm)));
+    }
+
+    if let Some(error) = drone_error.as_ref() {
+        let mut dismissed = false;
+        egui::TopBottomPanel::new(egui::panel::TopBottomSide::Top, "panel_drone_error").show(
+            ctx,
+            |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(error).color(Color32::RED).strong().size(16.0));
+                    dismissed = ui.button("Dismiss").clicked();
+                });
+            },
+        );
+        if dismissed {
+            *drone_error = None;
+        }
+    }
+
     egui::TopBottomPanel::new(egui::panel::TopBottomSide::Bottom, "panel_bottom")
         .show_separator_line(true)
-        .show(ctx, |ui| draw_statusbar(ui, &ping_status, &gamepad_status));
+        .show(ctx, |ui| {
+            draw_statusbar(
+                ui,
+                now,
+                (*drone_armed, arm_key.is_pending(now)),
+                &ping_status,
+                [relay_logs.last_received, drone_logs.last_received],
+                &gamepad_status,
+                &mut rtt_status,
+            )
+        });
 
     egui::SidePanel::new(egui::panel::Side::Right, "panel_right")
         .resizable(false)
         .exact_width(300.0)
         .show(ctx, |ui| {
             ui.take_available_width();
-            draw_settings(ui, &mut settings, &mut keep_armed.0, remote_msgs);
+            draw_telemetry_summary(ui, &telemetry, &ping_status);
+            draw_settings(
+                ui,
+                &mut settings,
+                &mut keep_armed.0,
+                &mut gamepad_status,
+                (&mut *blackbox, &mut *drone_logs),
+                ping_status.roundtrip_drone,
+                &mut remote_msgs,
+            );
         });
 
     egui::TopBottomPanel::new(egui::panel::TopBottomSide::Top, "panel_top")
         .show_separator_line(false)
-        .show(ctx, |ui| draw_navbar(ui, &mut active_tab));
+        .show(ctx, |ui| draw_navbar(ui, &mut active_tab.0));
 
-    egui::CentralPanel::default().show(ctx, |ui| match *active_tab {
+    egui::CentralPanel::default().show(ctx, |ui| match active_tab.0 {
         0 => draw_telemetry(ui, &telemetry),
         1 => {}
-        2 => draw_logs(ui, &relay_logs),
-        3 => draw_logs(ui, &drone_logs),
+        2 => draw_logs(ui, &mut relay_logs),
+        3 => draw_logs(ui, &mut drone_logs),
+        4 => {
+            if let Some(req) = draw_motor_wizard(ui, &mut motor_wizard, keep_armed.0) {
+                remote_msgs.write(RemoteMessage(req));
+            }
+        }
         _ => {}
     });
 
@@ -92,10 +232,10 @@ pub fn ui_system(
 pub fn draw_navbar(ui: &mut Ui, active_tab: &mut usize) {
     ui.add_space(8.);
     ui.horizontal(|ui| {
-        let clicked = ["Telemetry", "Preview", "Relay Logs", "Drone Logs"]
+        let clicked = TABS
             .iter()
             .enumerate()
-            .map(|(i, &label)| {
+            .map(|(i, &(label, _))| {
                 let text = RichText::new(label).strong().size(14.0);
                 let button = if *active_tab == i {
                     Button::new(text.color(Color32::WHITE)).fill(Color32::from_rgb(86, 79, 173))
@@ -115,8 +255,383 @@ pub fn draw_navbar(ui: &mut Ui, active_tab: &mut usize) {
     });
 }
 
-pub fn draw_statusbar(ui: &mut Ui, ping_status: &PingStatus, gamepad_status: &GamepadStatus) {
+/// Mixer motor positions in mixer order, as seen from above with the front pointing up
+const MIXER_POSITIONS: [&str; 4] = ["back left", "front left", "front right", "back right"];
+/// Throttle in 0..=1000 the motors are spun at while identifying them
+const MOTOR_WIZARD_THROTTLE: u16 = 100;
+
+#[derive(Default, Debug, PartialEq)]
+enum MotorWizardStep {
+    #[default]
+    Idle,
+    /// Esc output currently spinning and waiting for the operator's answer
+    Testing(usize),
+    /// All outputs answered, whether they formed a valid assignment
+    Finished { valid: bool },
+}
+
+/// Guided identification of the motor order and spin direction.
+///
+/// Spins one esc output after the other, the operator answers which mixer position spun and
+/// whether it spun the expected way. The answers replace [`ControlConfig::motor_outputs`].
+#[derive(Default)]
+pub struct MotorWizard {
+    /// Latest config reported by the drone
+    config: Option<ControlConfig>,
+    step: MotorWizardStep,
+    answers: [Option<MotorOutput>; 4],
+}
+
+impl MotorWizard {
+    /// Starts over at the first esc output, `None` until the drone reported its config
+    fn start(&mut self) -> Option<RemoteRequest> {
+        if self.config.is_none() {
+            return None;
+        }
+        self.answers = [None; 4];
+        self.step = MotorWizardStep::Testing(0);
+        self.spin()
+    }
+
+    /// Motor test spinning the esc output currently asked about
+    fn spin(&self) -> Option<RemoteRequest> {
+        let MotorWizardStep::Testing(output) = self.step else {
+            return None;
+        };
+        // motor tests are in mixer order and routed through the active assignment
+        let mixer_index = self.config?.motor_outputs[output].mixer_index;
+        Some(RemoteRequest::MotorTest {
+            index: mixer_index,
+            throttle: MOTOR_WIZARD_THROTTLE,
+        })
+    }
+
+    /// Records that the spinning output drives `mixer_index`, returns the next motor test or
+    /// the new config once all outputs are answered
+    fn answer(&mut self, mixer_index: u8, expected_direction: bool) -> Option<RemoteRequest> {
+        let MotorWizardStep::Testing(output) = self.step else {
+            return None;
+        };
+        let mut config = self.config?;
+        self.answers[output] = Some(MotorOutput {
+            mixer_index,
+            reversed: config.motor_outputs[output].reversed ^ !expected_direction,
+        });
+        if output + 1 < self.answers.len() {
+            self.step = MotorWizardStep::Testing(output + 1);
+            return self.spin();
+        }
+
+        config.motor_outputs = self.answers.map(|answer| answer.unwrap());
+        let valid = config.motor_outputs_valid();
+        self.step = MotorWizardStep::Finished { valid };
+        valid.then_some(RemoteRequest::SetConfig(config))
+    }
+
+    fn is_answered(&self, mixer_index: u8) -> bool {
+        self.answers
+            .iter()
+            .flatten()
+            .any(|answer| answer.mixer_index == mixer_index)
+    }
+}
+
+fn draw_motor_wizard(ui: &mut Ui, wizard: &mut MotorWizard, armed: bool) -> Option<RemoteRequest> {
+    let mut request = None;
+    ui.label(
+        RichText::new("Motor order and direction")
+            .size(16.0)
+            .strong(),
+    );
+    ui.add_space(8.);
+
+    if wizard.config.is_none() {
+        ui.label("Read the config from the drone first.");
+        if ui.button("Read from drone").clicked() {
+            request = Some(RemoteRequest::GetConfig);
+        }
+        return request;
+    }
+
+    match wizard.step {
+        MotorWizardStep::Testing(output) => {
+            ui.label(format!(
+                "Output {output} of 4 is spinning. Which motor is it and does it spin the \
+                 expected way?"
+            ));
+            ui.add_space(8.);
+            egui::Grid::new("motor_wizard_positions").show(ui, |ui| {
+                for (mixer_index, position) in (0..).zip(MIXER_POSITIONS) {
+                    let enabled = !wizard.is_answered(mixer_index);
+                    ui.label(position);
+                    if ui
+                        .add_enabled(enabled, Button::new("Expected way"))
+                        .clicked()
+                    {
+                        request = wizard.answer(mixer_index, true);
+                    }
+                    if ui.add_enabled(enabled, Button::new("Wrong way")).clicked() {
+                        request = wizard.answer(mixer_index, false);
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.add_space(8.);
+            ui.horizontal(|ui| {
+                if ui.button("Spin again").clicked() {
+                    request = wizard.spin();
+                }
+                if ui.button("Cancel").clicked() {
+                    wizard.step = MotorWizardStep::Idle;
+                }
+            });
+        }
+        MotorWizardStep::Finished { valid: true } => {
+            ui.label(
+                RichText::new("New motor assignment sent, save it to flash to keep it.")
+                    .color(Color32::LIGHT_GREEN),
+            );
+        }
+        MotorWizardStep::Finished { valid: false } => {
+            ui.label(
+                RichText::new("Each motor has to be picked exactly once, nothing was sent.")
+                    .color(Color32::LIGHT_RED),
+            );
+        }
+        MotorWizardStep::Idle => {
+            ui.label("Take the props off. Each motor spins briefly at low throttle.");
+        }
+    }
+
+    if !matches!(wizard.step, MotorWizardStep::Testing(_)) {
+        let start_button = ui.add_enabled(!armed, Button::new("Start"));
+        if start_button.clicked() {
+            request = wizard.start();
+        }
+    }
+    request
+}
+
+/// Commit the remote was built from
+const GIT_HASH: GitHash = parse_git_hash(env!("GIT_HASH"));
+
+/// Describes the drone firmware version next to the remote's own, as a warning if they differ
+fn version_report(drone_hash: GitHash, drone_schema: u8) -> (Level, String) {
+    let hex = |hash: GitHash| {
+        if hash == [0; 20] {
+            "unknown".to_owned()
+        } else {
+            hash.iter().map(|b| format!("{b:02x}")).collect()
+        }
+    };
+    let report = format!(
+        "Drone firmware {} (schema {drone_schema}), remote {} (schema {SCHEMA_VERSION})",
+        hex(drone_hash),
+        hex(GIT_HASH)
+    );
+    if drone_hash != GIT_HASH || drone_schema != SCHEMA_VERSION {
+        (Level::WARN, format!("Version mismatch: {report}"))
+    } else {
+        (Level::INFO, report)
+    }
+}
+
+/// Describes a [`DroneResponse::SelfTestResult`], as a warning if any check failed
+fn self_test_report(imu_ok: bool, motors_ok: bool, link_ms: Option<u16>) -> (Level, String) {
+    let link_ok = SelfTestChecks::link_ok(link_ms);
+    let check = |ok| if ok { "ok" } else { "FAILED" };
+    let link = match link_ms {
+        Some(ms) => format!("{ms}ms {}", check(link_ok)),
+        None => format!("unmeasured {}", check(link_ok)),
+    };
+    let report = format!(
+        "Self test: imu {}, motors {}, link {link}",
+        check(imu_ok),
+        check(motors_ok)
+    );
+    if imu_ok && motors_ok && link_ok {
+        (Level::INFO, report)
+    } else {
+        (Level::WARN, report)
+    }
+}
+
+/// Writes the collected frames to a timestamped csv file and clears them
+fn save_blackbox(blackbox: &mut Vec<BlackboxFrame>, logs: &mut Logs) {
+    let path = format!(
+        "blackbox-{}.csv",
+        std::time::UNIX_EPOCH
+            .elapsed()
+            .unwrap_or_default()
+            .as_secs()
+    );
+    match std::fs::write(&path, blackbox_csv(blackbox)) {
+        Ok(()) => logs.push(
+            Level::INFO,
+            format!("Saved {} blackbox frames to {path}", blackbox.len()),
+        ),
+        Err(err) => logs.push(
+            Level::ERROR,
+            format!("Unable to save blackbox to {path}: {err}"),
+        ),
+    }
+    blackbox.clear();
+}
+
+fn blackbox_csv(frames: &[BlackboxFrame]) -> String {
+    let mut csv =
+        "timestamp_us,roll,pitch,yaw,target_roll,target_pitch,target_yaw,m0,m1,m2,m3,armed\n"
+            .to_owned();
+    for frame in frames {
+        let [roll, pitch, yaw] = frame.orientation;
+        let [target_roll, target_pitch, target_yaw] = frame.target;
+        let [m0, m1, m2, m3] = frame.throttles;
+        csv += &format!(
+            "{},{roll},{pitch},{yaw},{target_roll},{target_pitch},{target_yaw},{m0},{m1},{m2},{m3},{}\n",
+            frame.timestamp, frame.armed
+        );
+    }
+    csv
+}
+
+/// Time within which the arm key has to be pressed a second time to arm
+const ARM_CONFIRM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Two-step arming with the space key, disarming takes a single press
+#[derive(Default, Debug, PartialEq)]
+pub enum ArmKey {
+    #[default]
+    Idle,
+    /// First press at the given time, waiting for the confirmation
+    Pending(Duration),
+}
+
+impl ArmKey {
+    /// Handles a key press at `now`, returns the arm state to request, if any
+    fn press(&mut self, now: Duration, armed: bool) -> Option<bool> {
+        if armed {
+            *self = ArmKey::Idle;
+            return Some(false);
+        }
+        if self.is_pending(now) {
+            *self = ArmKey::Idle;
+            Some(true)
+        } else {
+            *self = ArmKey::Pending(now);
+            None
+        }
+    }
+
+    fn is_pending(&self, now: Duration) -> bool {
+        match *self {
+            ArmKey::Pending(since) => now.saturating_sub(since) <= ARM_CONFIRM_TIMEOUT,
+            ArmKey::Idle => false,
+        }
+    }
+}
+
+/// Missed heartbeats after which the drone is shown as unresponsive
+const HEARTBEAT_STALE_INTERVALS: u64 = 3;
+
+/// Age of the latest heartbeat and whether it is stale, `None` if none was received
+fn heartbeat_age(now: Duration, last_heartbeat: Option<Duration>) -> Option<(Duration, bool)> {
+    let age = now.saturating_sub(last_heartbeat?);
+    let stale_after =
+        Duration::from_millis(HeartbeatTimer::INTERVAL_MS * HEARTBEAT_STALE_INTERVALS);
+    Some((age, age > stale_after))
+}
+
+/// Drone roundtrip time above which the link counts as degraded
+const LINK_DEGRADED_RTT: Duration = Duration::from_millis(100);
+/// Time without defmt frames after which a log channel counts as silent
+const LOG_SILENCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum LinkHealth {
+    Connected,
+    Degraded,
+    Lost,
+}
+
+impl LinkHealth {
+    fn label(self) -> RichText {
+        match self {
+            LinkHealth::Connected => RichText::new("Connected").color(Color32::LIGHT_GREEN),
+            LinkHealth::Degraded => RichText::new("Degraded").color(Color32::ORANGE),
+            LinkHealth::Lost => RichText::new("Lost").color(Color32::LIGHT_RED),
+        }
+    }
+}
+
+/// Health of the whole chain from the probe over the relay to the drone.
+///
+/// Lost when neither heartbeats nor pongs arrive from the drone, connected only with a fresh
+/// heartbeat, a fast roundtrip and defmt frames flowing on both the relay and drone channel.
+fn link_health(
+    now: Duration,
+    last_heartbeat: Option<Duration>,
+    drone_rtt: Option<Duration>,
+    last_logs: [Option<Duration>; 2],
+) -> LinkHealth {
+    let heartbeat_fresh = matches!(heartbeat_age(now, last_heartbeat), Some((_, false)));
+    if !heartbeat_fresh && drone_rtt.is_none() {
+        return LinkHealth::Lost;
+    }
+    let logs_flowing = last_logs
+        .iter()
+        .all(|last| last.is_some_and(|last| now.saturating_sub(last) <= LOG_SILENCE_TIMEOUT));
+    if heartbeat_fresh && drone_rtt.is_some_and(|rtt| rtt <= LINK_DEGRADED_RTT) && logs_flowing {
+        LinkHealth::Connected
+    } else {
+        LinkHealth::Degraded
+    }
+}
+
+pub fn draw_statusbar(
+    ui: &mut Ui,
+    now: Duration,
+    (drone_armed, arm_pending): (Option<bool>, bool),
+    ping_status: &PingStatus,
+    last_logs: [Option<Duration>; 2],
+    gamepad_status: &GamepadStatus,
+    rtt_status: &mut RttStatus,
+) {
     ui.horizontal(|ui| {
+        let arm_state = match (drone_armed, arm_pending) {
+            (Some(true), _) => RichText::new("ARMED").color(Color32::LIGHT_RED),
+            (_, true) => RichText::new("Press space again to arm").color(Color32::YELLOW),
+            (Some(false), false) => RichText::new("Disarmed").color(Color32::LIGHT_GREEN),
+            (None, false) => RichText::new("Arm state unknown").color(Color32::GRAY),
+        };
+        ui.label(arm_state.strong());
+
+        ui.add_space(8.0);
+
+        ui.label("Link: ");
+        let health = link_health(
+            now,
+            ping_status.last_heartbeat,
+            ping_status.roundtrip_drone,
+            last_logs,
+        );
+        ui.label(health.label().strong());
+
+        ui.add_space(8.0);
+
+        ui.label("Probe: ");
+        if let Some(err) = &rtt_status.error {
+            ui.label(RichText::new(err).color(Color32::LIGHT_RED));
+            if ui.button("Retry").clicked() {
+                rtt_status.retry = true;
+            }
+        } else if rtt_status.retry {
+            ui.label(RichText::new("Connecting").color(Color32::ORANGE));
+        } else {
+            ui.label(RichText::new("Connected").color(Color32::LIGHT_GREEN));
+        }
+
+        ui.add_space(8.0);
+
         ui.label("Drone: ");
         if let Some(rtt) = ping_status.roundtrip_drone {
             ui.label(
@@ -126,6 +641,17 @@ pub fn draw_statusbar(ui: &mut Ui, ping_status: &PingStatus, gamepad_status: &Ga
         } else {
             ui.label(RichText::new("Not connected").color(Color32::LIGHT_RED));
         }
+        match heartbeat_age(now, ping_status.last_heartbeat) {
+            Some((age, false)) => ui.label(
+                RichText::new(format!("heartbeat {:.1}s ago", age.as_secs_f32()))
+                    .color(Color32::LIGHT_GREEN),
+            ),
+            Some((age, true)) => ui.label(
+                RichText::new(format!("heartbeat stale, {:.1}s ago", age.as_secs_f32()))
+                    .color(Color32::LIGHT_RED),
+            ),
+            None => ui.label(RichText::new("no heartbeat").color(Color32::LIGHT_RED)),
+        };
 
         ui.add_space(8.0);
 
@@ -152,11 +678,155 @@ pub fn draw_statusbar(ui: &mut Ui, ping_status: &PingStatus, gamepad_status: &Ga
 
 #[derive(Default)]
 pub struct CollectedTelemetry {
+    latest: Option<Telemetry>,
     orientation: [Vec<PlotPoint>; 3],
     thrust: Vec<PlotPoint>,
     armed: Vec<PlotPoint>,
     output: [Vec<PlotPoint>; 3],
     throttles: [Vec<PlotPoint>; 4],
+    /// Latest [`DroneResponse::MotorsState`]
+    motors: Option<[f32; 4]>,
+}
+
+impl CollectedTelemetry {
+    pub fn push(&mut self, t: f64, sample: Telemetry) {
+        self.latest = Some(sample);
+
+        for i in 0..3 {
+            self.orientation[i].push(PlotPoint::new(t, sample.orientation[i] as f64));
+        }
+        self.thrust.push(PlotPoint::new(t, sample.thrust as f64));
+        self.armed.push(PlotPoint::new(
+            t,
+            sample.armed.then_some(1.0).unwrap_or(0.0),
+        ));
+        for i in 0..3 {
+            self.output[i].push(PlotPoint::new(t, sample.output[i] as f64));
+        }
+        for i in 0..4 {
+            self.throttles[i].push(PlotPoint::new(t, sample.throttles[i] as f64));
+        }
+    }
+}
+
+/// Maps how hard a motor spins, forward or reverse, to 0..=1
+fn throttle_fraction(throttle: u16) -> f32 {
+    common_messages::normalized_throttle(throttle)
+}
+
+/// Roll/pitch magnitude in degrees from which the attitude is flagged as unsafe
+const TILT_LIMIT_DEG: f32 = 30.0;
+/// Throttle fraction from which thrust and motors are flagged as near their limit
+const THROTTLE_WARN_FRACTION: f32 = 0.9;
+/// Full scale of the thrust input
+const MAX_THRUST: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ValueStyle {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl ValueStyle {
+    fn tilt(angle_deg: f32) -> Self {
+        if angle_deg.abs() > TILT_LIMIT_DEG || angle_deg.is_nan() {
+            ValueStyle::Critical
+        } else {
+            ValueStyle::Normal
+        }
+    }
+
+    fn throttle(fraction: f32) -> Self {
+        if fraction >= THROTTLE_WARN_FRACTION {
+            ValueStyle::Warning
+        } else {
+            ValueStyle::Normal
+        }
+    }
+
+    fn color(self, normal: Color32) -> Color32 {
+        match self {
+            ValueStyle::Normal => normal,
+            ValueStyle::Warning => Color32::YELLOW,
+            ValueStyle::Critical => Color32::RED,
+        }
+    }
+}
+
+pub fn draw_telemetry_summary(
+    ui: &mut Ui,
+    telemetry: &CollectedTelemetry,
+    ping_status: &PingStatus,
+) {
+    ui.add_space(8.);
+    ui.label(RichText::new("Status").size(16.0).strong());
+    ui.add_space(8.);
+
+    let Some(latest) = telemetry.latest else {
+        ui.label(RichText::new("No telemetry received").color(Color32::LIGHT_RED));
+        ui.add_space(16.);
+        return;
+    };
+
+    if !latest.live {
+        ui.label(RichText::new("Settling").strong().color(Color32::YELLOW));
+    } else if latest.armed {
+        ui.label(RichText::new("ARMED").strong().color(Color32::LIGHT_RED));
+    } else {
+        ui.label(
+            RichText::new("Disarmed")
+                .strong()
+                .color(Color32::LIGHT_GREEN),
+        );
+    }
+
+    let text_color = ui.visuals().text_color();
+    let styled = |text: String, style: ValueStyle| {
+        Label::new(
+            RichText::new(text)
+                .monospace()
+                .color(style.color(text_color)),
+        )
+    };
+
+    let [roll, pitch, yaw] = latest.orientation;
+    ui.add(styled(
+        format!("roll  {roll:>8.2}°"),
+        ValueStyle::tilt(roll),
+    ));
+    ui.add(styled(
+        format!("pitch {pitch:>8.2}°"),
+        ValueStyle::tilt(pitch),
+    ));
+    ui.monospace(format!("yaw   {yaw:>8.2}°"));
+    ui.add(styled(
+        format!("thrust {:>7.1}", latest.thrust),
+        ValueStyle::throttle(latest.thrust / MAX_THRUST),
+    ));
+    match ping_status.roundtrip_drone {
+        Some(rtt) => ui.monospace(format!("rtt   {:>6}ms", rtt.as_millis())),
+        None => ui.monospace("rtt        -"),
+    };
+    match latest.rssi {
+        Some(rssi) => ui.monospace(format!("rssi  {rssi:>5}dBm")),
+        None => ui.monospace("rssi       -"),
+    };
+    ui.monospace(format!("loop  {:>6.0}Hz", latest.loop_rate_hz));
+
+    let motors = telemetry
+        .motors
+        .unwrap_or(latest.throttles.map(throttle_fraction));
+    for (i, fraction) in motors.into_iter().enumerate() {
+        let mut bar =
+            egui::ProgressBar::new(fraction).text(format!("motor {i}: {:>3.0}%", fraction * 100.0));
+        if ValueStyle::throttle(fraction) == ValueStyle::Warning {
+            bar = bar.fill(Color32::YELLOW);
+        }
+        ui.add(bar);
+    }
+
+    ui.add_space(16.);
 }
 
 pub fn draw_telemetry(ui: &mut Ui, telemetry: &CollectedTelemetry) {
@@ -205,44 +875,287 @@ pub fn draw_telemetry(ui: &mut Ui, telemetry: &CollectedTelemetry) {
     });
 }
 
-fn draw_logs(ui: &mut Ui, logs: &[(Level, String)]) {
-    ScrollArea::both()
-        .animated(true)
-        .stick_to_bottom(true)
-        .show(ui, |ui| {
-            ui.take_available_space();
-            for (level, message) in logs {
-                let color = match *level {
-                    Level::TRACE => Color32::WHITE,
-                    Level::DEBUG => Color32::LIGHT_BLUE,
-                    Level::INFO => Color32::LIGHT_GREEN,
-                    Level::WARN => Color32::LIGHT_YELLOW,
-                    Level::ERROR => Color32::LIGHT_RED,
-                };
-                ui.horizontal(|ui| {
-                    ui.label(
-                        RichText::new(level.as_str().to_lowercase())
-                            .color(color)
-                            .monospace(),
-                    );
-                    ui.label(RichText::new(message).monospace());
-                });
+pub struct Logs {
+    lines: VecDeque<(Level, String)>,
+    /// Maximum number of retained lines, older lines get dropped
+    pub max_lines: usize,
+    /// Number of lines the view is scrolled up from the latest line
+    scroll: usize,
+    /// Lines less severe than this are hidden
+    min_level: Level,
+    /// Only lines containing this are shown, `None` when not searching
+    search: Option<String>,
+    /// Time the latest line was received
+    pub last_received: Option<Duration>,
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            max_lines: Self::DEFAULT_MAX_LINES,
+            scroll: 0,
+            min_level: Level::TRACE,
+            search: None,
+            last_received: None,
+        }
+    }
+}
+
+impl Logs {
+    pub const DEFAULT_MAX_LINES: usize = 10_000;
+
+    /// Levels from most to least severe
+    const LEVELS: [Level; 5] = [
+        Level::ERROR,
+        Level::WARN,
+        Level::INFO,
+        Level::DEBUG,
+        Level::TRACE,
+    ];
+
+    /// Whether a line of `level` is shown with the filter set to `min_level`
+    pub fn level_shown(level: Level, min_level: Level) -> bool {
+        // More verbose levels compare greater
+        level <= min_level
+    }
+
+    pub fn set_min_level(&mut self, min_level: Level) {
+        if self.min_level != min_level {
+            self.min_level = min_level;
+            self.scroll = 0;
+        }
+    }
+
+    /// Steps the filter towards more (`true`) or less severe levels only
+    pub fn step_min_level(&mut self, more_severe: bool) {
+        let pos = Self::LEVELS
+            .iter()
+            .position(|&level| level == self.min_level)
+            .unwrap_or(Self::LEVELS.len() - 1);
+        let pos = if more_severe {
+            pos.saturating_sub(1)
+        } else {
+            (pos + 1).min(Self::LEVELS.len() - 1)
+        };
+        self.set_min_level(Self::LEVELS[pos]);
+    }
+
+    /// Whether `message` matches the search `query`
+    pub fn search_matches(message: &str, query: &str) -> bool {
+        message.contains(query)
+    }
+
+    pub fn set_search(&mut self, search: Option<String>) {
+        if self.search != search {
+            self.search = search;
+            self.scroll = 0;
+        }
+    }
+
+    fn line_shown(&self, level: Level, message: &str) -> bool {
+        Self::level_shown(level, self.min_level)
+            && self
+                .search
+                .as_deref()
+                .is_none_or(|query| Self::search_matches(message, query))
+    }
+
+    fn shown_lines(&self) -> impl Iterator<Item = &(Level, String)> {
+        self.lines
+            .iter()
+            .filter(|(level, message)| self.line_shown(*level, message))
+    }
+
+    fn shown_len(&self) -> usize {
+        if self.min_level == Level::TRACE && self.search.as_deref().is_none_or(str::is_empty) {
+            self.lines.len()
+        } else {
+            self.shown_lines().count()
+        }
+    }
+
+    pub fn push(&mut self, level: Level, message: String) {
+        while self.lines.len() >= self.max_lines.max(1) {
+            self.lines.pop_front();
+        }
+        self.lines.push_back((level, message));
+
+        // Keep the view in place when not pinned to the latest line
+        let message = &self.lines.back().unwrap().1;
+        if self.scroll > 0 && self.line_shown(level, message) {
+            self.scroll += 1;
+        }
+    }
+
+    fn max_scroll(&self, viewport: usize) -> usize {
+        self.shown_len().saturating_sub(viewport)
+    }
+
+    pub fn scroll_by(&mut self, lines: isize, viewport: usize) {
+        self.scroll = self
+            .scroll
+            .min(self.max_scroll(viewport))
+            .saturating_add_signed(lines)
+            .min(self.max_scroll(viewport));
+    }
+
+    pub fn scroll_to_top(&mut self, viewport: usize) {
+        self.scroll = self.max_scroll(viewport);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Range of shown lines visible in a viewport of the given height
+    pub fn visible(&self, viewport: usize) -> std::ops::Range<usize> {
+        let end = self.shown_len() - self.scroll.min(self.max_scroll(viewport));
+        end.saturating_sub(viewport)..end
+    }
+}
+
+fn draw_logs(ui: &mut Ui, logs: &mut Logs) {
+    let (start_search, escape) = ui.input(|i| {
+        (
+            i.key_pressed(egui::Key::Slash),
+            i.key_pressed(egui::Key::Escape),
+        )
+    });
+    if escape {
+        logs.set_search(None);
+    } else if start_search && logs.search.is_none() && !ui.ctx().wants_keyboard_input() {
+        logs.set_search(Some(String::new()));
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Level (-/+):");
+        for level in Logs::LEVELS {
+            let selected = logs.min_level == level;
+            if ui.selectable_label(selected, level.as_str()).clicked() {
+                logs.set_min_level(level);
             }
-        });
+        }
+
+        ui.separator();
+        match logs.search.clone() {
+            Some(mut query) => {
+                ui.label("Search (Esc to clear):");
+                let response = ui.add(egui::TextEdit::singleline(&mut query));
+                if start_search && query.is_empty() {
+                    response.request_focus();
+                }
+                logs.set_search(Some(query));
+            }
+            None => {
+                ui.label("Press / to search");
+            }
+        }
+    });
+
+    let row_height =
+        ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().item_spacing.y;
+    let viewport = ((ui.available_height() / row_height) as usize).max(1);
+
+    let typing = ui.ctx().wants_keyboard_input();
+    let (page_up, page_down, home, end, wheel, more_severe, less_severe) = ui.input(|i| {
+        (
+            i.key_pressed(egui::Key::PageUp),
+            i.key_pressed(egui::Key::PageDown),
+            i.key_pressed(egui::Key::Home),
+            i.key_pressed(egui::Key::End),
+            i.raw_scroll_delta.y,
+            !typing && i.key_pressed(egui::Key::Minus),
+            !typing && (i.key_pressed(egui::Key::Plus) || i.key_pressed(egui::Key::Equals)),
+        )
+    });
+    if more_severe {
+        logs.step_min_level(true);
+    }
+    if less_severe {
+        logs.step_min_level(false);
+    }
+    if page_up {
+        logs.scroll_by(viewport as isize, viewport);
+    }
+    if page_down {
+        logs.scroll_by(-(viewport as isize), viewport);
+    }
+    if home {
+        logs.scroll_to_top(viewport);
+    }
+    if end {
+        logs.scroll_to_bottom();
+    }
+    if wheel != 0.0 {
+        logs.scroll_by((wheel / row_height).round() as isize, viewport);
+    }
+
+    ScrollArea::horizontal().show(ui, |ui| {
+        ui.take_available_space();
+        let visible = logs.visible(viewport);
+        let lines = logs.shown_lines().skip(visible.start).take(visible.len());
+        for (level, message) in lines {
+            let color = match *level {
+                Level::TRACE => Color32::WHITE,
+                Level::DEBUG => Color32::LIGHT_BLUE,
+                Level::INFO => Color32::LIGHT_GREEN,
+                Level::WARN => Color32::LIGHT_YELLOW,
+                Level::ERROR => Color32::LIGHT_RED,
+            };
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new(level.as_str().to_lowercase())
+                        .color(color)
+                        .monospace(),
+                );
+                match logs.search.as_deref() {
+                    Some(query) if !query.is_empty() => {
+                        ui.label(highlight_matches(ui, message, query));
+                    }
+                    _ => {
+                        ui.label(RichText::new(message).monospace());
+                    }
+                }
+            });
+        }
+    });
 }
 
-#[derive(Default)]
 pub struct Settings {
     kp: [f32; 3],
     ki: [f32; 3],
     kd: [f32; 3],
+    alpha: f32,
+    telemetry_interval: u32,
+    motor_test_index: u8,
+    motor_test_throttle: u16,
+    dry_run: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            kp: Default::default(),
+            ki: Default::default(),
+            kd: Default::default(),
+            alpha: 0.95,
+            telemetry_interval: 250,
+            motor_test_index: 0,
+            motor_test_throttle: 100,
+            dry_run: false,
+        }
+    }
 }
 
 pub fn draw_settings(
     ui: &mut Ui,
     settings: &mut Settings,
     keep_armed: &mut bool,
-    mut remote_msgs: MessageWriter<RemoteMessage>,
+    gamepad: &mut GamepadStatus,
+    (blackbox, drone_logs): (&mut Vec<BlackboxFrame>, &mut Logs),
+    drone_rtt: Option<Duration>,
+    remote_msgs: &mut MessageWriter<RemoteMessage>,
 ) {
     ui.add_space(8.);
     ui.label(RichText::new("Settings").size(16.0).strong());
@@ -260,6 +1173,38 @@ pub fn draw_settings(
         *keep_armed = !*keep_armed;
         remote_msgs.write(RemoteMessage(RemoteRequest::SetArm(*keep_armed)));
     }
+    let dry_run = ui.checkbox(&mut settings.dry_run, "Dry run, motors stay idle");
+    if dry_run.changed() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::SetDryRun(settings.dry_run)));
+    }
+    let stop_button = ui.add_sized(
+        [ui.available_width(), 0.0],
+        Button::new(
+            RichText::new("EMERGENCY STOP")
+                .strong()
+                .color(Color32::WHITE),
+        )
+        .fill(Color32::DARK_RED),
+    );
+    if stop_button.clicked() {
+        *keep_armed = false;
+        remote_msgs.write(RemoteMessage(RemoteRequest::EmergencyStop));
+    }
+
+    ui.checkbox(
+        &mut gamepad.sticks_request,
+        "Send gamepad as a single sticks request",
+    );
+    ui.horizontal(|ui| {
+        ui.label("sticks control");
+        let mut mode = gamepad.control_mode;
+        ui.selectable_value(&mut mode, ControlMode::Angle, "angle");
+        ui.selectable_value(&mut mode, ControlMode::Rate, "rate");
+        if mode != gamepad.control_mode {
+            gamepad.control_mode = mode;
+            remote_msgs.write(RemoteMessage(RemoteRequest::SetControlMode(mode)));
+        }
+    });
 
     ui.add_space(16.);
 
@@ -275,6 +1220,14 @@ pub fn draw_settings(
             col.add(egui::DragValue::new(&mut settings.kd[i]).max_decimals(4));
         }
     });
+    let read_button = ui.add_sized([ui.available_width(), 0.0], Button::new("Read from drone"));
+    if read_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::GetConfig));
+    }
+    let save_button = ui.add_sized([ui.available_width(), 0.0], Button::new("Save to flash"));
+    if save_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::SaveConfig));
+    }
     let update_button = ui.add_sized([ui.available_width(), 0.0], Button::new("Send"));
     if update_button.clicked() {
         remote_msgs.write(RemoteMessage(RemoteRequest::SetTune {
@@ -284,6 +1237,126 @@ pub fn draw_settings(
         }));
     }
 
+    ui.horizontal(|ui| {
+        ui.label("alpha");
+        ui.add(
+            egui::DragValue::new(&mut settings.alpha)
+                .range(0.0..=1.0)
+                .speed(0.001)
+                .max_decimals(4),
+        );
+    });
+    let alpha_button = ui.add_sized([ui.available_width(), 0.0], Button::new("Send alpha"));
+    if alpha_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::SetAlpha(settings.alpha)));
+    }
+
+    ui.add_space(16.);
+
+    ui.label(RichText::new("Telemetry").strong());
+    ui.horizontal(|ui| {
+        ui.label("interval");
+        ui.add(
+            egui::DragValue::new(&mut settings.telemetry_interval)
+                .range(20..=5000)
+                .suffix("ms"),
+        );
+    });
+    let telemetry_button = ui.add_sized([ui.available_width(), 0.0], Button::new("Send"));
+    if telemetry_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::SetTelemetryInterval(
+            settings.telemetry_interval,
+        )));
+    }
+
+    ui.add_space(16.);
+
+    ui.label(RichText::new("Motor test").strong());
+    ui.horizontal(|ui| {
+        ui.label("motor");
+        ui.add(egui::DragValue::new(&mut settings.motor_test_index).range(0..=3));
+        ui.label("throttle");
+        ui.add(egui::DragValue::new(&mut settings.motor_test_throttle).range(0..=1000));
+    });
+    let motor_test_button = ui.add_enabled(
+        !*keep_armed,
+        Button::new("Spin").min_size([ui.available_width(), 0.0].into()),
+    );
+    if motor_test_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::MotorTest {
+            index: settings.motor_test_index,
+            throttle: settings.motor_test_throttle,
+        }));
+    }
+
+    ui.add_space(16.);
+
+    ui.label(RichText::new("Gyro").strong());
+    let calibrate_button = ui.add_enabled(
+        !*keep_armed,
+        Button::new("Calibrate (keep still)").min_size([ui.available_width(), 0.0].into()),
+    );
+    if calibrate_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::CalibrateGyro));
+    }
+    let calibrate_level_button = ui.add_enabled(
+        !*keep_armed,
+        Button::new("Calibrate level (keep still)").min_size([ui.available_width(), 0.0].into()),
+    );
+    if calibrate_level_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::Calibrate));
+    }
+    let dump_imu_button = ui.add_enabled(
+        !*keep_armed,
+        Button::new("Log IMU registers").min_size([ui.available_width(), 0.0].into()),
+    );
+    if dump_imu_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::DumpImu));
+    }
+
+    ui.add_space(16.);
+
+    ui.label(RichText::new("Blackbox").strong());
+    let blackbox_button = ui.add_enabled(
+        !*keep_armed,
+        Button::new("Dump to csv").min_size([ui.available_width(), 0.0].into()),
+    );
+    if blackbox_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::DumpBlackbox));
+    }
+    // a dump missing its last frame is otherwise never saved
+    let export_button = ui.add_enabled(
+        !blackbox.is_empty(),
+        Button::new(format!("Export {} received frames", blackbox.len()))
+            .min_size([ui.available_width(), 0.0].into()),
+    );
+    if export_button.clicked() {
+        save_blackbox(blackbox, drone_logs);
+    }
+
+    ui.add_space(16.);
+
+    ui.label(RichText::new("Preflight").strong());
+    let self_test_button = ui.add_enabled(
+        !*keep_armed,
+        Button::new("Self test (spins each motor)").min_size([ui.available_width(), 0.0].into()),
+    );
+    if self_test_button.clicked() {
+        let link_ms = drone_rtt.map(|rtt| rtt.as_millis().min(u16::MAX.into()) as u16);
+        remote_msgs.write(RemoteMessage(RemoteRequest::SelfTest { link_ms }));
+    }
+
+    ui.add_space(16.);
+
+    ui.label(RichText::new("Firmware").strong());
+    let version_button = ui.add_sized(
+        [ui.available_width(), 0.0],
+        Button::new("Query firmware version"),
+    );
+    if version_button.clicked() {
+        remote_msgs.write(RemoteMessage(RemoteRequest::GetVersion));
+    }
+
     ui.add_space(16.);
 
     ui.label(RichText::new("Reset").strong());
@@ -292,3 +1365,394 @@ pub fn draw_settings(
         remote_msgs.write(RemoteMessage(RemoteRequest::Reset));
     }
 }
+
+#[test]
+fn tab_name_parsing() {
+    assert_eq!(ActiveTab::parse("telemetry").unwrap(), ActiveTab(0));
+    assert_eq!(ActiveTab::parse("relay").unwrap(), ActiveTab(2));
+    assert_eq!(ActiveTab::parse("Drone").unwrap(), ActiveTab(3));
+
+    let err = ActiveTab::parse("remote").unwrap_err().to_string();
+    assert!(err.contains("'remote'"));
+    assert!(err.contains("telemetry, preview, relay, drone"));
+}
+
+/// Lays out `message` with every occurrence of `query` highlighted
+fn highlight_matches(ui: &Ui, message: &str, query: &str) -> egui::text::LayoutJob {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let normal = egui::TextFormat::simple(font_id.clone(), ui.visuals().text_color());
+    let highlighted = egui::TextFormat {
+        background: Color32::from_rgb(86, 79, 173),
+        color: Color32::WHITE,
+        ..egui::TextFormat::simple(font_id, Color32::WHITE)
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    let mut rest = message;
+    while let Some(pos) = rest.find(query) {
+        job.append(&rest[..pos], 0.0, normal.clone());
+        job.append(&rest[pos..pos + query.len()], 0.0, highlighted.clone());
+        rest = &rest[pos + query.len()..];
+    }
+    job.append(rest, 0.0, normal);
+    job
+}
+
+#[test]
+fn telemetry_value_style() {
+    assert_eq!(ValueStyle::tilt(0.0), ValueStyle::Normal);
+    assert_eq!(ValueStyle::tilt(-TILT_LIMIT_DEG), ValueStyle::Normal);
+    assert_eq!(ValueStyle::tilt(TILT_LIMIT_DEG + 0.1), ValueStyle::Critical);
+    assert_eq!(ValueStyle::tilt(-90.0), ValueStyle::Critical);
+    assert_eq!(ValueStyle::tilt(f32::NAN), ValueStyle::Critical);
+
+    assert_eq!(ValueStyle::throttle(0.0), ValueStyle::Normal);
+    assert_eq!(ValueStyle::throttle(0.89), ValueStyle::Normal);
+    assert_eq!(
+        ValueStyle::throttle(throttle_fraction(1900)),
+        ValueStyle::Warning
+    );
+    assert_eq!(
+        ValueStyle::throttle(900.0 / MAX_THRUST),
+        ValueStyle::Warning
+    );
+
+    assert_eq!(ValueStyle::Normal.color(Color32::GRAY), Color32::GRAY);
+    assert_eq!(ValueStyle::Warning.color(Color32::GRAY), Color32::YELLOW);
+    assert_eq!(ValueStyle::Critical.color(Color32::GRAY), Color32::RED);
+}
+
+#[test]
+fn logs_search_filter() {
+    assert!(Logs::search_matches("arming motors", "arm"));
+    assert!(Logs::search_matches("anything", ""));
+    assert!(!Logs::search_matches("arming motors", "Arm"));
+
+    let mut logs = Logs::default();
+    for message in ["imu ready", "arming", "armed", "imu error", "disarmed"] {
+        logs.push(Level::INFO, message.to_owned());
+    }
+
+    logs.set_search(Some("arm".to_owned()));
+    let shown: Vec<_> = logs
+        .shown_lines()
+        .map(|(_, message)| message.as_str())
+        .collect();
+    assert_eq!(shown, ["arming", "armed", "disarmed"]);
+    assert_eq!(logs.visible(10), 0..3);
+
+    // Clearing the search restores all lines
+    logs.set_search(None);
+    assert_eq!(logs.visible(10), 0..5);
+}
+
+#[test]
+fn logs_level_filter() {
+    for (min_pos, &min_level) in Logs::LEVELS.iter().enumerate() {
+        for (pos, &level) in Logs::LEVELS.iter().enumerate() {
+            assert_eq!(Logs::level_shown(level, min_level), pos <= min_pos);
+        }
+    }
+
+    let mut logs = Logs::default();
+    for (i, level) in Logs::LEVELS.into_iter().cycle().take(50).enumerate() {
+        logs.push(level, i.to_string());
+    }
+    logs.set_min_level(Level::WARN);
+    assert_eq!(logs.visible(100), 0..20);
+    assert!(logs.shown_lines().all(|(level, _)| *level <= Level::WARN));
+
+    logs.step_min_level(true);
+    assert_eq!(logs.min_level, Level::ERROR);
+    logs.step_min_level(true);
+    assert_eq!(logs.min_level, Level::ERROR);
+    assert_eq!(logs.visible(5), 5..10);
+
+    // Hidden lines don't move a scrolled view
+    logs.scroll_by(2, 5);
+    logs.push(Level::INFO, "hidden".to_owned());
+    assert_eq!(logs.visible(5), 3..8);
+}
+
+#[test]
+fn logs_scroll_clamping() {
+    let mut logs = Logs::default();
+    for i in 0..100 {
+        logs.push(Level::INFO, i.to_string());
+    }
+
+    // pinned to the latest line
+    assert_eq!(logs.visible(10), 90..100);
+
+    logs.scroll_by(10, 10);
+    assert_eq!(logs.visible(10), 80..90);
+
+    // can't scroll past the first line
+    logs.scroll_by(1000, 10);
+    assert_eq!(logs.visible(10), 0..10);
+
+    // can't scroll past the latest line
+    logs.scroll_by(-1000, 10);
+    assert_eq!(logs.visible(10), 90..100);
+
+    // viewport larger than the number of lines
+    logs.scroll_to_top(200);
+    assert_eq!(logs.visible(200), 0..100);
+
+    // new lines don't move a scrolled view
+    logs.scroll_to_top(10);
+    logs.push(Level::INFO, "new".to_owned());
+    assert_eq!(logs.visible(10), 0..10);
+}
+
+#[test]
+fn logs_bounded_retention() {
+    let mut logs = Logs {
+        max_lines: 50,
+        ..Default::default()
+    };
+    for i in 0..120 {
+        logs.push(Level::INFO, i.to_string());
+    }
+
+    assert_eq!(logs.lines.len(), 50);
+    assert_eq!(logs.lines.front().unwrap().1, "70");
+    assert_eq!(logs.lines.back().unwrap().1, "119");
+    assert_eq!(logs.visible(10), 40..50);
+
+    // a view scrolled to the oldest line stays clamped to the buffer
+    logs.scroll_to_top(10);
+    logs.push(Level::INFO, "new".to_owned());
+    assert_eq!(logs.visible(10), 0..10);
+}
+
+#[test]
+fn collected_telemetry_update() {
+    let sample = |i: u16| Telemetry {
+        timestamp: i as u64 * 250,
+        orientation: [i as f32, -(i as f32), 0.5],
+        thrust: 100.0 * i as f32,
+        armed: i % 2 == 1,
+        output: [0.0; 3],
+        throttles: [1000 + i, 1100 + i, 1200 + i, 1300 + i],
+        rssi: Some(-40 - i as i8),
+        live: true,
+        loop_rate_hz: 1600.0,
+    };
+
+    let mut telemetry = CollectedTelemetry::default();
+    assert!(telemetry.latest.is_none());
+
+    for i in 0..5 {
+        telemetry.push(i as f64 * 250.0, sample(i));
+    }
+
+    assert_eq!(telemetry.latest, Some(sample(4)));
+    assert_eq!(telemetry.orientation[1].len(), 5);
+    assert_eq!(telemetry.throttles[3].last().unwrap().y, 1304.0);
+    assert_eq!(
+        telemetry.armed.iter().map(|p| p.y).collect::<Vec<_>>(),
+        [0.0, 1.0, 0.0, 1.0, 0.0]
+    );
+
+    assert_eq!(throttle_fraction(1000), 0.0);
+    assert_eq!(throttle_fraction(1500), 0.5);
+    assert_eq!(throttle_fraction(2500), 1.0);
+    // reversed motors
+    assert_eq!(throttle_fraction(500), 0.5);
+    assert_eq!(throttle_fraction(0), 1.0);
+}
+
+#[test]
+fn heartbeat_staleness() {
+    let ms = Duration::from_millis;
+    assert_eq!(heartbeat_age(ms(1000), None), None);
+    assert_eq!(
+        heartbeat_age(ms(1200), Some(ms(1000))),
+        Some((ms(200), false))
+    );
+    assert_eq!(
+        heartbeat_age(ms(2500), Some(ms(1000))),
+        Some((ms(1500), false))
+    );
+    assert_eq!(
+        heartbeat_age(ms(2501), Some(ms(1000))),
+        Some((ms(1501), true))
+    );
+    // received after the frame time was taken
+    assert_eq!(
+        heartbeat_age(ms(1000), Some(ms(1001))),
+        Some((ms(0), false))
+    );
+}
+
+#[test]
+fn link_health_classification() {
+    let ms = Duration::from_millis;
+    let now = ms(10_000);
+    let logs = [Some(ms(9_900)), Some(ms(9_950))];
+
+    assert_eq!(
+        link_health(now, Some(ms(9_800)), Some(ms(20)), logs),
+        LinkHealth::Connected
+    );
+    // Nothing heard from the drone at all
+    assert_eq!(link_health(now, None, None, logs), LinkHealth::Lost);
+    assert_eq!(
+        link_health(now, Some(ms(5_000)), None, logs),
+        LinkHealth::Lost
+    );
+    // Pongs still arrive but heartbeats went stale
+    assert_eq!(
+        link_health(now, Some(ms(5_000)), Some(ms(20)), logs),
+        LinkHealth::Degraded
+    );
+    // Heartbeats arrive but no pong in a while
+    assert_eq!(
+        link_health(now, Some(ms(9_800)), None, logs),
+        LinkHealth::Degraded
+    );
+    // Slow roundtrip
+    assert_eq!(
+        link_health(now, Some(ms(9_800)), Some(ms(101)), logs),
+        LinkHealth::Degraded
+    );
+    assert_eq!(
+        link_health(now, Some(ms(9_800)), Some(ms(100)), logs),
+        LinkHealth::Connected
+    );
+    // One of the defmt channels went silent or never had a frame
+    assert_eq!(
+        link_health(
+            now,
+            Some(ms(9_800)),
+            Some(ms(20)),
+            [Some(ms(7_999)), logs[1]]
+        ),
+        LinkHealth::Degraded
+    );
+    assert_eq!(
+        link_health(now, Some(ms(9_800)), Some(ms(20)), [logs[0], None]),
+        LinkHealth::Degraded
+    );
+}
+
+#[test]
+fn arm_key_confirmation() {
+    let s = Duration::from_secs;
+    let mut key = ArmKey::default();
+
+    // A single press only asks for confirmation
+    assert_eq!(key.press(s(10), false), None);
+    assert!(key.is_pending(s(11)));
+
+    // Second press within the timeout arms
+    assert_eq!(key.press(s(11), false), Some(true));
+    assert_eq!(key, ArmKey::Idle);
+
+    // A late second press starts over
+    assert_eq!(key.press(s(20), false), None);
+    assert!(!key.is_pending(s(23)));
+    assert_eq!(key.press(s(23), false), None);
+    assert_eq!(key.press(s(24), false), Some(true));
+
+    // Disarming takes a single press, also while a confirmation is pending
+    assert_eq!(key.press(s(30), true), Some(false));
+    assert_eq!(key.press(s(40), false), None);
+    assert_eq!(key.press(s(41), true), Some(false));
+    assert_eq!(key, ArmKey::Idle);
+}
+
+#[test]
+fn motor_wizard_assignment() {
+    let output = |mixer_index, reversed| MotorOutput {
+        mixer_index,
+        reversed,
+    };
+    let config = ControlConfig {
+        kp: [0.0; 3],
+        ki: [0.0; 3],
+        kd: [0.0; 3],
+        alpha: 0.95,
+        accel_lowpass: 0.1,
+        idle_thrust: 70.0,
+        thrust_expo: 0.0,
+        arm_timeout_ms: 500,
+        settle_ms: 2000,
+        max_tilt_deg: 60.0,
+        control_rate_hz: 1600,
+        throttle_deadband: 30.0,
+        motor_outputs: [
+            output(1, false),
+            output(2, true),
+            output(3, false),
+            output(0, true),
+        ],
+    };
+    let motor_test = |index| {
+        Some(RemoteRequest::MotorTest {
+            index,
+            throttle: MOTOR_WIZARD_THROTTLE,
+        })
+    };
+
+    // Nothing to spin without knowing the active assignment
+    let mut wizard = MotorWizard::default();
+    assert_eq!(wizard.start(), None);
+    assert_eq!(wizard.step, MotorWizardStep::Idle);
+
+    // Outputs are spun in order through the active assignment
+    wizard.config = Some(config);
+    assert_eq!(wizard.start(), motor_test(1));
+    assert_eq!(wizard.spin(), motor_test(1));
+    assert_eq!(wizard.answer(1, true), motor_test(2));
+    assert!(wizard.is_answered(1));
+    assert_eq!(wizard.answer(2, false), motor_test(3));
+    // outputs 2 and 3 turn out swapped
+    assert_eq!(wizard.answer(0, true), motor_test(0));
+    let Some(RemoteRequest::SetConfig(new_config)) = wizard.answer(3, false) else {
+        panic!("expected the new config");
+    };
+    assert_eq!(wizard.step, MotorWizardStep::Finished { valid: true });
+    assert_eq!(
+        new_config.motor_outputs,
+        [
+            output(1, false),
+            output(2, false),
+            output(0, false),
+            output(3, false),
+        ]
+    );
+    assert_eq!(new_config.kp, config.kp);
+    assert_eq!(wizard.answer(1, true), None);
+
+    // A motor picked twice sends nothing
+    assert_eq!(wizard.start(), motor_test(1));
+    for _ in 0..3 {
+        wizard.answer(1, true);
+    }
+    assert_eq!(wizard.answer(0, true), None);
+    assert_eq!(wizard.step, MotorWizardStep::Finished { valid: false });
+}
+
+#[test]
+fn blackbox_csv_format() {
+    let frames = [
+        BlackboxFrame {
+            timestamp: 1_000,
+            orientation: [1.5, -2.0, 0.0],
+            target: [0.0, 0.0, 10.0],
+            throttles: [1000, 1100, 1200, 2000],
+            armed: true,
+        },
+        BlackboxFrame::EMPTY,
+    ];
+
+    assert_eq!(
+        blackbox_csv(&frames),
+        "timestamp_us,roll,pitch,yaw,target_roll,target_pitch,target_yaw,m0,m1,m2,m3,armed\n\
+         1000,1.5,-2,0,0,0,10,1000,1100,1200,2000,true\n\
+         0,0,0,0,0,0,0,0,0,0,0,false\n"
+    );
+    assert_eq!(blackbox_csv(&[]).lines().count(), 1);
+}